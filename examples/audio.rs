@@ -1,22 +1,35 @@
 extern crate captcha;
 
-use captcha::{generate, Difficulty};
+use captcha::{generate, AudioOptions, Difficulty};
+use std::env;
 use std::fs::File;
 use std::io::prelude::*;
+use std::path::PathBuf;
 
 #[cfg(not(feature = "audio"))]
 compile_error!("audio feature not enabled");
 
+/// Writes the audio files into the directory given as the first argument or into the
+/// temporary directory.
 fn main() -> std::io::Result<()> {
-    let captcha = generate(Difficulty::Easy);
+    let dir = env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(env::temp_dir);
+    let mut captcha = generate(Difficulty::Easy);
     let s = captcha.as_wav();
     let mut c = 0;
     for i in s {
         c += 1;
-        let fname = format!("/tmp/audio{}.wav", c);
+        let fname = dir.join(format!("audio{}.wav", c));
         let mut f = File::create(fname)?;
         f.write_all(&i.unwrap())?;
     }
+
+    captcha.set_audio_options(AudioOptions::new().intro(true).repeat(1));
+    let mut f = File::create(dir.join("audio.wav"))?;
+    f.write_all(&captcha.as_audio().unwrap())?;
+
     println!("{}", captcha.chars_as_string());
     Ok(())
 }
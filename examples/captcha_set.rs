@@ -1,6 +1,6 @@
 extern crate captcha;
 
-use captcha::{by_name, generate, CaptchaName, Difficulty};
+use captcha::{generate, Difficulty};
 use std::path::Path;
 
 fn main() {
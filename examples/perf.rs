@@ -1,40 +1,30 @@
 extern crate captcha;
-extern crate time;
 
-use captcha::{generate, Difficulty};
-use std::thread;
-use time::Instant;
+use captcha::{generate_batch, Difficulty};
+use std::time::Instant;
 
+// Run with `cargo run --release --example perf --features rayon` to create the CAPTCHAs in
+// parallel.
 fn main() {
-    let n = 500;
-    let nthreads: i64 = 8;
-    let mut threads = vec![];
+    let n = 4000;
 
     let b = Instant::now();
-
-    for _ in 0..nthreads {
-        let h = thread::spawn(move || {
-            for _ in 0..n {
-                generate(Difficulty::Easy).as_tuple();
-            }
-            println!("done {:?} ms", b.elapsed().whole_milliseconds());
-        });
-        threads.push(h);
-    }
-
-    for i in threads {
-        i.join().expect("join failed");
+    let batch = generate_batch(n, Difficulty::Easy, 0);
+    println!("created in {:?} ms", b.elapsed().as_millis());
+    for c in &batch {
+        c.as_tuple();
     }
+    println!("encoded in {:?} ms", b.elapsed().as_millis());
 
     let d = b.elapsed();
-    println!("n                     : {}", n * nthreads);
-    println!("time in ms total      : {}", d.whole_milliseconds());
+    println!("n                     : {}", n);
+    println!("time in ms total      : {}", d.as_millis());
     println!(
         "time in ms per captcha: {}",
-        d.whole_milliseconds() as f64 / (n * nthreads) as f64
+        d.as_millis() as f64 / n as f64
     );
     println!(
         "#captchs per second   : {}",
-        (n * nthreads * 1000) / (d.whole_milliseconds() as i64)
+        (n as u128 * 1000) / d.as_millis()
     );
 }
@@ -1,6 +1,25 @@
 #!/usr/bin/env python3
+"""Generates the voice pack src/audio/audio.json.
 
-import subprocess, os, base64, sys, json
+Each character of the default font is spoken as "capital X", "lower case letter x" or
+"number N". The prompts, e.g. "type the following", are spoken in the same voice. Two
+backends are available:
+
+  formant  A small formant synthesiser which is part of this script. It needs nothing but
+           Python 3 and produces byte-identical output on every run. This is the default.
+           src/audio/synth.rs is a port of it and has to be kept in sync.
+  espeak   Records the phrases with espeak. The output depends on the installed version of
+           espeak.
+
+Usage: gen.py [--backend formant|espeak] [--out PATH]
+"""
+
+import argparse, base64, io, json, math, os, subprocess, sys, tempfile, wave
+
+FORMAT = 2
+SAMPLE_RATE = 22050
+# The audio module pads every clip to this number of samples.
+MAX_SAMPLES = 36000
 
 letters = {
 	"capital": "ABCDEFGHIJKLMNOPQRSTUVWXYZ",
@@ -8,15 +27,258 @@ letters = {
 	"number": "1234567890"
 }
 
-data = {}
+# Phrases which are spoken before or between the characters of an audio challenge.
+prompts = {
+	"intro": "type the following",
+}
+
+# ---------------------------------------------------------------------------------------------
+# Pronunciations (ARPAbet, a trailing 1 marks the stressed vowel).
+
+LEXICON = {
+	"capital": "K AE1 P IH T AH L",
+	"lower": "L OW1 ER",
+	"case": "K EY1 S",
+	"letter": "L EH1 T ER",
+	"number": "N AH1 M B ER",
+	"type": "T AY1 P", "the": "DH AH", "following": "F AA1 L OW IH NG",
+	"a": "EY1", "b": "B IY1", "c": "S IY1", "d": "D IY1", "e": "IY1", "f": "EH1 F",
+	"g": "JH IY1", "h": "EY1 CH", "i": "AY1", "j": "JH EY1", "k": "K EY1", "l": "EH1 L",
+	"m": "EH1 M", "n": "EH1 N", "o": "OW1", "p": "P IY1", "q": "K Y UW1", "r": "AA1 R",
+	"s": "EH1 S", "t": "T IY1", "u": "Y UW1", "v": "V IY1", "w": "D AH1 B AH L Y UW",
+	"x": "EH1 K S", "y": "W AY1", "z": "Z IY1",
+	"1": "W AH1 N", "2": "T UW1", "3": "TH R IY1", "4": "F AO1 R", "5": "F AY1 V",
+	"6": "S IH1 K S", "7": "S EH1 V AH N", "8": "EY1 T", "9": "N AY1 N", "0": "Z IH1 R OW",
+}
+
+# ---------------------------------------------------------------------------------------------
+# Phonemes. A segment is (duration in ms, F1, F2, F3, voicing, aspiration, frication,
+# frication centre, frication bandwidth). Formants which are None are taken from the next
+# segment.
+
+VOWELS = {
+	"IY": (270, 2290, 3010), "IH": (390, 1990, 2550), "EH": (530, 1840, 2480),
+	"AE": (660, 1720, 2410), "AA": (730, 1090, 2440), "AO": (570, 840, 2410),
+	"UH": (440, 1020, 2240), "UW": (300, 870, 2240), "AH": (640, 1190, 2390),
+	"ER": (490, 1350, 1690),
+}
+
+DIPHTHONGS = {
+	"EY": ((480, 1900, 2500), (300, 2250, 2900)),
+	"AY": ((700, 1200, 2500), (350, 2100, 2800)),
+	"OW": ((550, 950, 2400), (350, 800, 2300)),
+}
+
+APPROXIMANTS = {
+	"L": (360, 1300, 2700), "R": (420, 1300, 1600), "W": (300, 700, 2200),
+	"Y": (260, 2100, 3000),
+}
+
+NASALS = {"M": (280, 1000, 2200), "N": (280, 1600, 2600), "NG": (280, 2100, 2700)}
+
+# voicing, frication, centre, bandwidth
+FRICATIVES = {
+	"F": (0.0, 0.25, 6000, 3000), "V": (0.35, 0.15, 6000, 3000),
+	"TH": (0.0, 0.2, 5000, 3000), "DH": (0.4, 0.1, 5000, 3000),
+	"S": (0.0, 0.7, 5500, 1500), "Z": (0.35, 0.45, 5500, 1500),
+	"SH": (0.0, 0.7, 2800, 1200), "ZH": (0.35, 0.45, 2800, 1200),
+}
+
+# locus, burst centre, voiced
+STOPS = {
+	"P": ((250, 900, 2100), 1000, False), "B": ((250, 900, 2100), 1000, True),
+	"T": ((250, 1700, 2600), 4500, False), "D": ((250, 1700, 2600), 4500, True),
+	"K": ((250, 1900, 2000), 2000, False), "G": ((250, 1900, 2000), 2000, True),
+}
+
+NEUTRAL = (450, 1400, 2500)
+
+
+def segments(ph):
+	stressed = ph.endswith("1")
+	ph = ph.rstrip("1")
+	d = 1.4 if stressed else 1.0
+	if ph in VOWELS:
+		dur = 140 * d if ph != "AH" or stressed else 60
+		return [(dur,) + VOWELS[ph] + (1.0, 0, 0, 0, 1)]
+	if ph in DIPHTHONGS:
+		a, b = DIPHTHONGS[ph]
+		return [(90 * d,) + a + (1.0, 0, 0, 0, 1), (70 * d,) + b + (1.0, 0, 0, 0, 1)]
+	if ph in APPROXIMANTS:
+		return [(70,) + APPROXIMANTS[ph] + (0.7, 0, 0, 0, 1)]
+	if ph in NASALS:
+		return [(75,) + NASALS[ph] + (0.45, 0, 0, 0, 1)]
+	if ph in FRICATIVES:
+		av, af, fc, bw = FRICATIVES[ph]
+		return [(110,) + NEUTRAL + (av, 0, af, fc, bw)]
+	if ph == "HH":
+		return [(70, None, None, None, 0, 0.5, 0, 0, 1)]
+	if ph in STOPS:
+		locus, fc, voiced = STOPS[ph]
+		r = [(60,) + locus + (0.12 if voiced else 0, 0, 0, 0, 1),
+			(12,) + locus + (0, 0, 0.5 if not voiced else 0.3, fc, 2000)]
+		if not voiced:
+			r.append((45, None, None, None, 0, 0.45, 0, 0, 1))
+		return r
+	if ph in ("CH", "JH"):
+		voiced = ph == "JH"
+		locus = STOPS["T"][0]
+		return [(55,) + locus + (0.12 if voiced else 0, 0, 0, 0, 1),
+			(90,) + locus + (0.3 if voiced else 0, 0, 0.6, 2800, 1200)]
+	raise ValueError("unknown phoneme " + ph)
+
+
+def phrase_segments(words):
+	r = [(50,) + NEUTRAL + (0, 0, 0, 0, 1)]
+	for w in words:
+		for ph in LEXICON[w].split():
+			r += segments(ph)
+	r.append((60,) + NEUTRAL + (0, 0, 0, 0, 1))
+	# formants which are None are taken from the next segment which has formants
+	out = list(r)
+	nxt = NEUTRAL
+	for i in range(len(out) - 1, -1, -1):
+		if out[i][1] is None:
+			out[i] = (out[i][0],) + nxt + out[i][4:]
+		else:
+			nxt = out[i][1:4]
+	return out
+
+
+# ---------------------------------------------------------------------------------------------
+# Synthesis
+
+FRAME = 0.005
+
+
+def smooth(xs, n):
+	h = n // 2
+	r = []
+	for i in range(len(xs)):
+		w = xs[max(0, i - h):i + h + 1]
+		r.append(sum(w) / len(w))
+	return r
+
+
+class Resonator:
+	def __init__(self):
+		self.y1 = self.y2 = 0.0
+
+	def __call__(self, x, f, bw):
+		c = -math.exp(-2 * math.pi * bw / SAMPLE_RATE)
+		b = 2 * math.exp(-math.pi * bw / SAMPLE_RATE) * math.cos(2 * math.pi * f / SAMPLE_RATE)
+		a = 1 - b - c
+		y = a * x + b * self.y1 + c * self.y2
+		self.y2, self.y1 = self.y1, y
+		return y
+
+
+def synthesize(segs, seed):
+	total = sum(s[0] for s in segs) / 1000.0
+	# speak faster if the phrase does not fit into a clip
+	scale = min(1.0, (MAX_SAMPLES - 400) / SAMPLE_RATE / total)
+
+	tracks = [[] for _ in range(8)]
+	for s in segs:
+		for _ in range(max(1, int(round(s[0] * scale / 1000.0 / FRAME)))):
+			for k in range(8):
+				tracks[k].append(s[k + 1])
+	f1, f2, f3 = (smooth(t, 7) for t in tracks[:3])
+	av, ah, af = (smooth(t, 3) for t in tracks[3:6])
+	fc, fbw = tracks[6], tracks[7]
+
+	n = len(f1) * int(FRAME * SAMPLE_RATE)
+	per_frame = FRAME * SAMPLE_RATE
+	rs = [Resonator() for _ in range(5)]
+	rf = Resonator()
+	noise = seed & 0x7fffffff
+	phase = 0.0
+	prev = 0.0
+	out = []
+	for i in range(n):
+		j = min(int(i / per_frame), len(f1) - 1)
+		t = i / n
+		f0 = 120 + 15 * math.sin(math.pi * min(1.0, t * 2.5)) - 25 * t
+		phase += f0 / SAMPLE_RATE
+		if phase >= 1.0:
+			phase -= 1.0
+		# Rosenberg glottal pulse, differentiated for the lip radiation
+		if phase < 0.4:
+			g = 0.5 * (1 - math.cos(math.pi * phase / 0.4))
+		elif phase < 0.56:
+			g = math.cos(math.pi * (phase - 0.4) / 0.32)
+		else:
+			g = 0.0
+		src = (g - prev) * 8.0
+		prev = g
+		noise = (noise * 1103515245 + 12345) & 0x7fffffff
+		white = noise / 0x3fffffff - 1.0
+
+		x = av[j] * src + ah[j] * white * 0.3
+		x = rs[0](x, f1[j], 80)
+		x = rs[1](x, f2[j], 100)
+		x = rs[2](x, f3[j], 150)
+		x = rs[3](x, 3500, 250)
+		x = rs[4](x, 4500, 300)
+		if af[j] > 0:
+			x += rf(white * af[j], fc[j], fbw[j]) * 0.8
+		out.append(x)
+
+	peak = max(abs(x) for x in out) or 1.0
+	return [int(round(x / peak * 20000)) for x in out]
+
+
+def wav_bytes(samples):
+	f = io.BytesIO()
+	w = wave.open(f, "wb")
+	w.setnchannels(1)
+	w.setsampwidth(2)
+	w.setframerate(SAMPLE_RATE)
+	w.writeframes(b"".join(int(s).to_bytes(2, "little", signed=True) for s in samples))
+	w.close()
+	return f.getvalue()
+
+
+def formant(text, seed):
+	words = text.lower().split()
+	return wav_bytes(synthesize(phrase_segments(words), seed))
+
+
+def espeak(text, seed):
+	with tempfile.TemporaryDirectory() as d:
+		p = os.path.join(d, "sound.wav")
+		subprocess.check_call(["espeak", "-w", p, text])
+		return open(p, "rb").read()
+
+
+def main():
+	p = argparse.ArgumentParser()
+	p.add_argument("--backend", choices=["formant", "espeak"], default="formant")
+	p.add_argument("--out", default=os.path.join(os.path.dirname(__file__), "../../src/audio/audio.json"))
+	a = p.parse_args()
+
+	speak = formant if a.backend == "formant" else espeak
+	clips = {}
+	for prefix, s in letters.items():
+		for letter in s:
+			print(prefix, letter, file=sys.stderr)
+			wav = speak(prefix + " " + letter, ord(letter))
+			clips[letter] = base64.b64encode(wav).decode("utf-8")
+	spoken = {}
+	for name, text in prompts.items():
+		print(text, file=sys.stderr)
+		spoken[name] = base64.b64encode(speak(text, len(text))).decode("utf-8")
+
+	data = {
+		"format": FORMAT,
+		"name": "en-" + a.backend,
+		"sample_rate": SAMPLE_RATE,
+		"clips": clips,
+		"prompts": spoken,
+	}
+	open(a.out, "wt").write(json.dumps(data, sort_keys=True))
+	print("data written to " + a.out, file=sys.stderr)
 
-for prefix, s in letters.items():
-	for letter in s:
-		print(prefix, letter)
-		os.system("espeak -w sound.wav '" + prefix + " " + letter + "'")
-		data[letter] = base64.b64encode(open("sound.wav", "rb").read()).decode("utf-8")
-		#os.unlink("sound.wav")
 
-dst = "audio.json"
-open(dst, "wt").write(json.dumps(data))
-print ("data written to " + dst)
+if __name__ == "__main__":
+	main()
@@ -0,0 +1,114 @@
+#!/usr/bin/env python3
+"""Generates tests/fonts/test.ttf, a tiny TrueType font for the tests of the truetype feature.
+
+The font contains the glyphs 'I', 'W', 'a' and 'g' drawn with straight lines. 'g' reaches
+below the baseline. 'b' is mapped to a glyph without an outline. The font has only the tables
+which are needed to render glyphs: head, hhea, maxp, cmap, loca, glyf and hmtx.
+
+Usage: gen.py [--out PATH]
+"""
+
+import argparse, os, struct
+
+UNITS_PER_EM = 1000
+ASCENT = 800
+DESCENT = -200
+
+# Each glyph is a list of closed contours of points on the outline.
+GLYPHS = {
+	"I": [[(100, 0), (100, 700), (250, 700), (250, 0)]],
+	"W": [[(0, 700), (120, 700), (220, 200), (320, 700), (420, 700), (520, 200), (620, 700),
+		(740, 700), (580, 0), (460, 0), (370, 450), (280, 0), (160, 0)]],
+	"a": [[(50, 0), (50, 500), (450, 500), (450, 0)], [(150, 100), (350, 100), (350, 400),
+		(150, 400)]],
+	"b": [],
+	"g": [[(50, -200), (50, 500), (450, 500), (450, -200)]],
+}
+
+
+def glyf(contours):
+	if not contours:
+		return b""
+	pts = [p for c in contours for p in c]
+	xs = [p[0] for p in pts]
+	ys = [p[1] for p in pts]
+	ends = []
+	n = 0
+	for c in contours:
+		n += len(c)
+		ends.append(n - 1)
+	d = struct.pack(">hhhhh", len(contours), min(xs), min(ys), max(xs), max(ys))
+	d += struct.pack(">%dH" % len(ends), *ends)
+	d += struct.pack(">H", 0)  # no instructions
+	d += bytes([1] * len(pts))  # all points are on the curve, 16 bit deltas
+	px = py = 0
+	dx, dy = b"", b""
+	for x, y in pts:
+		dx += struct.pack(">h", x - px)
+		dy += struct.pack(">h", y - py)
+		px, py = x, y
+	d += dx + dy
+	return d + b"\0" * (-len(d) % 4)
+
+
+def checksum(data):
+	data += b"\0" * (-len(data) % 4)
+	return sum(struct.unpack(">%dI" % (len(data) // 4), data)) & 0xffffffff
+
+
+def font():
+	chars = sorted(GLYPHS)
+	outlines = [[]] + [GLYPHS[c] for c in chars]
+	data = [glyf(o) for o in outlines]
+	loca = [0]
+	for d in data:
+		loca.append(loca[-1] + len(d))
+	pts = [p for o in outlines for c in o for p in c]
+	x_max = max(p[0] for p in pts)
+	y_max = max(p[1] for p in pts)
+	advance = [500] + [max([p[0] for c in GLYPHS[ch] for p in c], default=400) + 100 for ch in chars]
+	lsb = [0] + [min([p[0] for c in GLYPHS[ch] for p in c], default=0) for ch in chars]
+	n = len(outlines)
+
+	tables = {}
+	tables[b"head"] = struct.pack(">IIIIHHqqhhhhHHhhh", 0x00010000, 0x00010000, 0, 0x5F0F3CF5,
+		0, UNITS_PER_EM, 0, 0, 0, 0, x_max, y_max, 0, 8, 2, 0, 0)
+	tables[b"hhea"] = struct.pack(">IhhhHhhhhhhhhhhhH", 0x00010000, ASCENT, DESCENT, 0,
+		max(advance), 0, 0, x_max, 1, 0, 0, 0, 0, 0, 0, 0, n)
+	tables[b"maxp"] = struct.pack(">IHHHHHHHHHHHHHH", 0x00010000, n, max(len(p) for p in [pts]),
+		max(len(o) for o in outlines), 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0)
+	groups = b"".join(struct.pack(">III", ord(c), ord(c), i + 1) for i, c in enumerate(chars))
+	sub = struct.pack(">HHIII", 12, 0, 16 + len(groups), 0, len(chars)) + groups
+	tables[b"cmap"] = struct.pack(">HHHHI", 0, 1, 3, 10, 12) + sub
+	tables[b"loca"] = struct.pack(">%dH" % len(loca), *[x // 2 for x in loca])
+	tables[b"glyf"] = b"".join(data)
+	tables[b"hmtx"] = b"".join(struct.pack(">Hh", a, l) for a, l in zip(advance, lsb))
+
+	tags = sorted(tables)
+	header = struct.pack(">IHHHH", 0x00010000, len(tags), 64, 2, len(tags) * 16 - 64)
+	offset = len(header) + 16 * len(tags)
+	records, body = b"", b""
+	head_offset = 0
+	for t in tags:
+		d = tables[t]
+		if t == b"head":
+			head_offset = offset
+		records += struct.pack(">4sIII", t, checksum(d), offset, len(d))
+		d += b"\0" * (-len(d) % 4)
+		body += d
+		offset += len(d)
+	out = bytearray(header + records + body)
+	adjust = (0xB1B0AFBA - checksum(bytes(out))) & 0xffffffff
+	out[head_offset + 8:head_offset + 12] = struct.pack(">I", adjust)
+	return bytes(out)
+
+
+def main():
+	p = argparse.ArgumentParser()
+	p.add_argument("--out", default=os.path.join(os.path.dirname(__file__), "../../tests/fonts/test.ttf"))
+	a = p.parse_args()
+	open(a.out, "wb").write(font())
+
+
+if __name__ == "__main__":
+	main()
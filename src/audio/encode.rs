@@ -0,0 +1,417 @@
+use std::io::Cursor;
+
+#[cfg(feature = "flac")]
+use audio::flac;
+use audio_filters::{resample, Biquad};
+use {Error, Result};
+
+/// The encoding of the audio which is returned by
+/// [`as_wav`](struct.RngCaptcha.html#method.as_wav) and
+/// [`as_audio`](struct.RngCaptcha.html#method.as_audio).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AudioEncoding {
+    /// WAV with 16 bit samples.
+    Pcm16,
+    /// WAV with 8 bit samples. Half the size of `Pcm16`.
+    Pcm8,
+    /// WAV with 8 bit µ-law samples (G.711). Half the size of `Pcm16` with a better dynamic
+    /// range than `Pcm8`.
+    MuLaw,
+    /// WAV with 4 bit IMA ADPCM samples. About a quarter of the size of `Pcm16`.
+    ImaAdpcm,
+    /// FLAC, i.e. lossless compression of 16 bit samples. Requires the feature `flac`;
+    /// without it the audio cannot be encoded and an
+    /// [`Error::InvalidParameter`](enum.Error.html) is returned.
+    Flac,
+}
+
+impl AudioEncoding {
+    /// Returns the MIME type of the encoding, e.g. `audio/wav`.
+    pub fn mime_type(&self) -> &'static str {
+        match *self {
+            AudioEncoding::Flac => "audio/flac",
+            _ => "audio/wav",
+        }
+    }
+}
+
+/// Converts the samples to the sample rate `to` and encodes them.
+pub fn encode(samples: &[i16], from: u32, to: u32, e: AudioEncoding) -> Result<Vec<u8>> {
+    if !(4000..=192_000).contains(&to) {
+        return Err(Error::invalid(format!(
+            "the sample rate must be in 4000..=192000 Hz, got {}",
+            to
+        )));
+    }
+    let converted;
+    let samples = if from == to {
+        samples
+    } else {
+        converted = convert_rate(samples, from, to);
+        &converted[..]
+    };
+    match e {
+        AudioEncoding::Pcm16 => pcm(samples, to, 16),
+        AudioEncoding::Pcm8 => pcm(samples, to, 8),
+        AudioEncoding::MuLaw => Ok(mu_law(samples, to)),
+        AudioEncoding::ImaAdpcm => Ok(ima_adpcm(samples, to)),
+        #[cfg(feature = "flac")]
+        AudioEncoding::Flac => Ok(flac::encode(samples, to)),
+        #[cfg(not(feature = "flac"))]
+        AudioEncoding::Flac => Err(Error::invalid("FLAC requires the feature flac")),
+    }
+}
+
+/// Resamples with linear interpolation. Before downsampling the frequencies above the new
+/// Nyquist frequency are removed.
+fn convert_rate(samples: &[i16], from: u32, to: u32) -> Vec<i16> {
+    let mut x: Vec<f32> = samples.iter().map(|&s| s as f32).collect();
+    if to < from {
+        let f = Biquad::low_pass(to as f32 * 0.45, from);
+        f.process(&mut x);
+        f.process(&mut x);
+    }
+    resample(&x, to as f32 / from as f32)
+        .iter()
+        .map(|&s| s.round().clamp(-32768.0, 32767.0) as i16)
+        .collect()
+}
+
+fn pcm(samples: &[i16], sample_rate: u32, bits: u16) -> Result<Vec<u8>> {
+    let mut cursor = Cursor::new(Vec::new());
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate,
+        bits_per_sample: bits,
+        sample_format: hound::SampleFormat::Int,
+    };
+    {
+        let mut writer = hound::WavWriter::new(&mut cursor, spec)?;
+        for &s in samples {
+            if bits == 8 {
+                writer.write_sample::<i8>((s >> 8) as i8)?;
+            } else {
+                writer.write_sample::<i16>(s)?;
+            }
+        }
+        writer.finalize()?;
+    }
+    Ok(cursor.into_inner())
+}
+
+/// Writes a WAV file with a compressed format, which requires the chunk `fact`.
+fn wav(
+    format: u16,
+    sample_rate: u32,
+    block_align: u16,
+    bits: u16,
+    extra: &[u8],
+    n: usize,
+    data: &[u8],
+) -> Vec<u8> {
+    let byte_rate = match format {
+        FORMAT_IMA_ADPCM => {
+            let per_block = u32::from(u16::from_le_bytes([extra[0], extra[1]]));
+            sample_rate * u32::from(block_align) / per_block
+        }
+        _ => sample_rate * u32::from(block_align),
+    };
+    let fmt_len = 18 + extra.len() as u32;
+    let pad = data.len() % 2;
+    let riff_len = 4 + (8 + fmt_len) + (8 + 4) + 8 + (data.len() + pad) as u32;
+
+    let mut v = Vec::with_capacity(riff_len as usize + 8);
+    v.extend_from_slice(b"RIFF");
+    v.extend_from_slice(&riff_len.to_le_bytes());
+    v.extend_from_slice(b"WAVE");
+    v.extend_from_slice(b"fmt ");
+    v.extend_from_slice(&fmt_len.to_le_bytes());
+    v.extend_from_slice(&format.to_le_bytes());
+    v.extend_from_slice(&1u16.to_le_bytes());
+    v.extend_from_slice(&sample_rate.to_le_bytes());
+    v.extend_from_slice(&byte_rate.to_le_bytes());
+    v.extend_from_slice(&block_align.to_le_bytes());
+    v.extend_from_slice(&bits.to_le_bytes());
+    v.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+    v.extend_from_slice(extra);
+    v.extend_from_slice(b"fact");
+    v.extend_from_slice(&4u32.to_le_bytes());
+    v.extend_from_slice(&(n as u32).to_le_bytes());
+    v.extend_from_slice(b"data");
+    v.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    v.extend_from_slice(data);
+    if pad == 1 {
+        v.push(0);
+    }
+    v
+}
+
+const FORMAT_MU_LAW: u16 = 7;
+const FORMAT_IMA_ADPCM: u16 = 0x11;
+
+fn mu_law(samples: &[i16], sample_rate: u32) -> Vec<u8> {
+    let data: Vec<u8> = samples.iter().map(|&s| mu_law_sample(s)).collect();
+    wav(FORMAT_MU_LAW, sample_rate, 1, 8, &[], samples.len(), &data)
+}
+
+/// Encodes a sample with the µ-law of G.711.
+fn mu_law_sample(s: i16) -> u8 {
+    const BIAS: i32 = 0x84;
+    const CLIP: i32 = 32635;
+    let sign = if s < 0 { 0x80 } else { 0 };
+    let x = (s as i32).abs().min(CLIP) + BIAS;
+    let exponent = (7 - (x << 17).leading_zeros().min(7)) as i32;
+    let mantissa = (x >> (exponent + 3)) & 0x0f;
+    !(sign | (exponent << 4) | mantissa) as u8
+}
+
+const IMA_INDEX: [i32; 16] = [-1, -1, -1, -1, 2, 4, 6, 8, -1, -1, -1, -1, 2, 4, 6, 8];
+
+const IMA_STEPS: [i32; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66,
+    73, 80, 88, 97, 107, 118, 130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449,
+    494, 544, 598, 658, 724, 796, 876, 963, 1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066, 2272,
+    2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871, 5358, 5894, 6484, 7132, 7845, 8630, 9493,
+    10442, 11487, 12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794, 32767,
+];
+
+/// The state of an IMA ADPCM coder.
+struct Ima {
+    predictor: i32,
+    index: i32,
+}
+
+impl Ima {
+    fn encode(&mut self, s: i16) -> u8 {
+        let step = IMA_STEPS[self.index as usize];
+        let mut diff = s as i32 - self.predictor;
+        let mut nibble = 0;
+        if diff < 0 {
+            nibble = 8;
+            diff = -diff;
+        }
+        let mut delta = step >> 3;
+        if diff >= step {
+            nibble |= 4;
+            diff -= step;
+            delta += step;
+        }
+        if diff >= step >> 1 {
+            nibble |= 2;
+            diff -= step >> 1;
+            delta += step >> 1;
+        }
+        if diff >= step >> 2 {
+            nibble |= 1;
+            delta += step >> 2;
+        }
+        // the decoder reconstructs the sample in the same way
+        if nibble & 8 != 0 {
+            self.predictor -= delta;
+        } else {
+            self.predictor += delta;
+        }
+        self.predictor = self.predictor.clamp(-32768, 32767);
+        self.index = (self.index + IMA_INDEX[nibble as usize]).clamp(0, 88);
+        nibble
+    }
+}
+
+fn ima_adpcm(samples: &[i16], sample_rate: u32) -> Vec<u8> {
+    // the usual block sizes of the format
+    let block_align: usize = match sample_rate {
+        0..=11025 => 256,
+        11026..=22050 => 512,
+        _ => 1024,
+    };
+    let per_block = (block_align - 4) * 2 + 1;
+    let mut data = Vec::with_capacity(samples.len() / 2 + block_align);
+    let mut ima = Ima {
+        predictor: 0,
+        index: 0,
+    };
+    for block in samples.chunks(per_block) {
+        // the header of a block contains the first sample and resets the predictor
+        ima.predictor = block[0] as i32;
+        data.extend_from_slice(&block[0].to_le_bytes());
+        data.push(ima.index as u8);
+        data.push(0);
+        let start = data.len();
+        let mut rest = block[1..].iter();
+        while let Some(&a) = rest.next() {
+            let lo = ima.encode(a);
+            let hi = rest.next().map(|&b| ima.encode(b)).unwrap_or(0);
+            data.push(lo | (hi << 4));
+        }
+        // the last block is padded
+        data.resize(start + block_align - 4, 0);
+    }
+    let extra = (per_block as u16).to_le_bytes();
+    wav(
+        FORMAT_IMA_ADPCM,
+        sample_rate,
+        block_align as u16,
+        4,
+        &extra,
+        samples.len(),
+        &data,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use audio::encode::{convert_rate, encode, mu_law_sample, AudioEncoding, IMA_INDEX, IMA_STEPS};
+
+    fn sine(n: usize) -> Vec<i16> {
+        (0..n)
+            .map(|i| ((i as f32 * 0.05).sin() * 12000.0) as i16)
+            .collect()
+    }
+
+    /// Decodes a µ-law sample (G.711).
+    fn mu_law_decode(u: u8) -> i32 {
+        let u = !u;
+        let t = ((((u & 0x0f) as i32) << 3) + 0x84) << ((u & 0x70) >> 4);
+        if u & 0x80 != 0 {
+            0x84 - t
+        } else {
+            t - 0x84
+        }
+    }
+
+    fn u32_at(v: &[u8], i: usize) -> u32 {
+        u32::from_le_bytes([v[i], v[i + 1], v[i + 2], v[i + 3]])
+    }
+
+    /// Returns the offset and the length of a chunk.
+    fn chunk(v: &[u8], id: &[u8]) -> (usize, usize) {
+        let mut i = 12;
+        while i + 8 <= v.len() {
+            let n = u32_at(v, i + 4) as usize;
+            if &v[i..i + 4] == id {
+                return (i + 8, n);
+            }
+            i += 8 + n + n % 2;
+        }
+        panic!("chunk not found");
+    }
+
+    #[test]
+    fn pcm() {
+        let s = sine(1000);
+        let w = encode(&s, 22050, 22050, AudioEncoding::Pcm16).unwrap();
+        let r = hound::WavReader::new(&w[..]).unwrap();
+        assert_eq!(r.spec().bits_per_sample, 16);
+        let v: Vec<i16> = r.into_samples().map(|x| x.unwrap()).collect();
+        assert_eq!(v, s);
+
+        let w = encode(&s, 22050, 22050, AudioEncoding::Pcm8).unwrap();
+        let r = hound::WavReader::new(&w[..]).unwrap();
+        assert_eq!(r.spec().bits_per_sample, 8);
+        let v: Vec<i8> = r.into_samples().map(|x| x.unwrap()).collect();
+        assert_eq!(v[10], (s[10] >> 8) as i8);
+        assert!(w.len() < 1100);
+    }
+
+    #[test]
+    fn mu_law() {
+        for &s in &[0i16, 1, -1, 100, -100, 1000, 8000, -8000, 32767, -32768] {
+            let d = mu_law_decode(mu_law_sample(s));
+            // the quantisation error grows with the amplitude
+            assert!(
+                (d - s as i32).abs() <= (s as i32).abs() / 16 + 8,
+                "{} {}",
+                s,
+                d
+            );
+        }
+        let s = sine(1001);
+        let w = encode(&s, 8000, 8000, AudioEncoding::MuLaw).unwrap();
+        assert_eq!(&w[0..4], b"RIFF");
+        assert_eq!(u32_at(&w, 4) as usize, w.len() - 8);
+        let (fmt, _) = chunk(&w, b"fmt ");
+        assert_eq!(w[fmt], 7);
+        let (fact, _) = chunk(&w, b"fact");
+        assert_eq!(u32_at(&w, fact), 1001);
+        let (data, n) = chunk(&w, b"data");
+        assert_eq!(n, 1001);
+        assert_eq!(
+            mu_law_decode(w[data + 7]),
+            mu_law_decode(mu_law_sample(s[7]))
+        );
+    }
+
+    #[test]
+    fn ima_adpcm() {
+        let s = sine(5000);
+        let w = encode(&s, 22050, 22050, AudioEncoding::ImaAdpcm).unwrap();
+        let (fmt, _) = chunk(&w, b"fmt ");
+        assert_eq!(w[fmt], 0x11);
+        let block_align = u16::from_le_bytes([w[fmt + 12], w[fmt + 13]]) as usize;
+        let per_block = u16::from_le_bytes([w[fmt + 18], w[fmt + 19]]) as usize;
+        assert_eq!((block_align, per_block), (512, 1017));
+        let (data, n) = chunk(&w, b"data");
+        assert_eq!(n % block_align, 0);
+
+        // decode and compare with the input
+        let mut out = vec![];
+        for block in w[data..data + n].chunks(block_align) {
+            let mut p = i16::from_le_bytes([block[0], block[1]]) as i32;
+            let mut index = block[2] as i32;
+            out.push(p);
+            for &b in &block[4..] {
+                for &nibble in &[b & 0x0f, b >> 4] {
+                    let step = IMA_STEPS[index as usize];
+                    let mut delta = step >> 3;
+                    if nibble & 4 != 0 {
+                        delta += step;
+                    }
+                    if nibble & 2 != 0 {
+                        delta += step >> 1;
+                    }
+                    if nibble & 1 != 0 {
+                        delta += step >> 2;
+                    }
+                    p = if nibble & 8 != 0 {
+                        p - delta
+                    } else {
+                        p + delta
+                    };
+                    p = p.clamp(-32768, 32767);
+                    index = (index + IMA_INDEX[nibble as usize]).clamp(0, 88);
+                    out.push(p);
+                }
+            }
+        }
+        assert_eq!(out[0], s[0] as i32);
+        // the step size needs a few samples to adapt at the start
+        let err = s
+            .iter()
+            .zip(&out)
+            .skip(16)
+            .map(|(&a, &b)| (a as i32 - b).abs())
+            .max()
+            .unwrap();
+        assert!(err < 1500, "{}", err);
+        assert!(w.len() < s.len() / 2 + 600);
+    }
+
+    #[test]
+    fn sample_rate() {
+        let s = sine(22050);
+        assert_eq!(convert_rate(&s, 22050, 8000).len(), 8000);
+        assert_eq!(convert_rate(&s, 22050, 44100).len(), 44100);
+        let w = encode(&s, 22050, 16000, AudioEncoding::Pcm16).unwrap();
+        let r = hound::WavReader::new(&w[..]).unwrap();
+        assert_eq!((r.spec().sample_rate, r.len()), (16000, 16000));
+        assert!(encode(&s, 22050, 100, AudioEncoding::Pcm16).is_err());
+    }
+
+    #[test]
+    fn mime_type() {
+        assert_eq!(AudioEncoding::MuLaw.mime_type(), "audio/wav");
+        assert_eq!(AudioEncoding::Flac.mime_type(), "audio/flac");
+        let flac = encode(&sine(100), 22050, 22050, AudioEncoding::Flac);
+        assert_eq!(flac.is_ok(), cfg!(feature = "flac"));
+    }
+}
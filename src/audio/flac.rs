@@ -0,0 +1,345 @@
+//! A small FLAC encoder for 16 bit mono audio.
+//!
+//! Each block is coded with the fixed predictor of the order which results in the smallest
+//! residual and a single Rice partition. This is a subset of FLAC which every decoder supports.
+
+const BLOCK_SIZE: usize = 4096;
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u64,
+    n: u32,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter {
+            bytes: vec![],
+            acc: 0,
+            n: 0,
+        }
+    }
+
+    /// Writes the lowest `bits` bits of `v`, most significant bit first.
+    fn write(&mut self, v: u64, bits: u32) {
+        for i in (0..bits).rev() {
+            self.acc = (self.acc << 1) | ((v >> i) & 1);
+            self.n += 1;
+            if self.n == 8 {
+                self.bytes.push(self.acc as u8);
+                self.acc = 0;
+                self.n = 0;
+            }
+        }
+    }
+
+    fn write_signed(&mut self, v: i64, bits: u32) {
+        self.write(v as u64 & ((1 << bits) - 1), bits);
+    }
+
+    /// Pads the last byte with zeros.
+    fn align(&mut self) {
+        while self.n != 0 {
+            self.write(0, 1);
+        }
+    }
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &b in data {
+        crc ^= b;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &b in data {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Writes `v` in the UTF-8 like coding of FLAC frame numbers.
+fn write_utf8(w: &mut BitWriter, v: u64) {
+    if v < 0x80 {
+        w.write(v, 8);
+        return;
+    }
+    let mut n = 2;
+    while v >= 1 << (5 * n + 1) {
+        n += 1;
+    }
+    let lead = (0xff00u64 >> n) & 0xff;
+    w.write(lead | (v >> (6 * (n - 1))), 8);
+    for i in (0..n - 1).rev() {
+        w.write(0x80 | ((v >> (6 * i)) & 0x3f), 8);
+    }
+}
+
+/// Returns the residual of the fixed predictor of the given order.
+fn residual(x: &[i64], order: usize) -> Vec<i64> {
+    (order..x.len())
+        .map(|i| match order {
+            0 => x[i],
+            1 => x[i] - x[i - 1],
+            2 => x[i] - 2 * x[i - 1] + x[i - 2],
+            3 => x[i] - 3 * x[i - 1] + 3 * x[i - 2] - x[i - 3],
+            _ => x[i] - 4 * x[i - 1] + 6 * x[i - 2] - 4 * x[i - 3] + x[i - 4],
+        })
+        .collect()
+}
+
+fn zigzag(r: i64) -> u64 {
+    ((r << 1) ^ (r >> 63)) as u64
+}
+
+/// Returns the Rice parameter with the smallest size of the residual and the size in bits.
+fn rice_parameter(r: &[i64]) -> (u32, u64) {
+    (0..15)
+        .map(|k| {
+            let bits: u64 = r.iter().map(|&x| (zigzag(x) >> k) + 1 + k as u64).sum();
+            (k, bits)
+        })
+        .min_by_key(|&(_, bits)| bits)
+        .expect("at least one parameter")
+}
+
+fn write_frame(out: &mut Vec<u8>, block: &[i16], number: u64) {
+    let mut w = BitWriter::new();
+    w.write(0xfff8, 16); // sync code and fixed block size
+    w.write(0b0111, 4); // block size - 1 in 16 bits at the end of the header
+    w.write(0b0000, 4); // sample rate from STREAMINFO
+    w.write(0b0000, 4); // mono
+    w.write(0b100, 3); // 16 bits per sample
+    w.write(0, 1);
+    write_utf8(&mut w, number);
+    w.write(block.len() as u64 - 1, 16);
+    let crc = crc8(&w.bytes);
+    w.write(crc as u64, 8);
+
+    let x: Vec<i64> = block.iter().map(|&s| s as i64).collect();
+    let verbatim = 16 * x.len() as u64;
+    let best = (0..5)
+        .filter(|&order| order < x.len())
+        .map(|order| {
+            let r = residual(&x, order);
+            let (k, bits) = rice_parameter(&r);
+            (order, r, k, bits + 16 * order as u64 + 10)
+        })
+        .min_by_key(|f| f.3);
+
+    match best {
+        Some((order, r, k, bits)) if bits < verbatim => {
+            w.write(0, 1);
+            w.write(0b001000 | order as u64, 6);
+            w.write(0, 1);
+            for &s in &x[..order] {
+                w.write_signed(s, 16);
+            }
+            w.write(0b00, 2); // Rice coding with 4 bit parameters
+            w.write(0, 4); // one partition
+            w.write(k as u64, 4);
+            for &v in &r {
+                let u = zigzag(v);
+                let q = u >> k;
+                for _ in 0..q {
+                    w.write(0, 1);
+                }
+                w.write(1, 1);
+                w.write(u & ((1 << k) - 1), k);
+            }
+        }
+        _ => {
+            w.write(0, 1);
+            w.write(0b000001, 6);
+            w.write(0, 1);
+            for &s in &x {
+                w.write_signed(s, 16);
+            }
+        }
+    }
+    w.align();
+    let crc = crc16(&w.bytes);
+    w.write(crc as u64, 16);
+    out.extend_from_slice(&w.bytes);
+}
+
+/// Encodes 16 bit mono samples as FLAC.
+pub fn encode(samples: &[i16], sample_rate: u32) -> Vec<u8> {
+    let mut out = b"fLaC".to_vec();
+
+    let mut w = BitWriter::new();
+    w.write(1, 1); // last metadata block
+    w.write(0, 7); // STREAMINFO
+    w.write(34, 24);
+    let block = BLOCK_SIZE.min(samples.len().max(16)) as u64;
+    w.write(block, 16);
+    w.write(block, 16);
+    w.write(0, 24); // frame sizes are unknown
+    w.write(0, 24);
+    w.write(sample_rate as u64, 20);
+    w.write(0, 3); // one channel
+    w.write(15, 5); // 16 bits per sample
+    w.write(samples.len() as u64, 36);
+    w.write(0, 64); // no MD5 signature
+    w.write(0, 64);
+    out.extend_from_slice(&w.bytes);
+
+    for (i, block) in samples.chunks(BLOCK_SIZE).enumerate() {
+        write_frame(&mut out, block, i as u64);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use audio::flac::{crc16, crc8, encode};
+
+    struct BitReader<'a> {
+        data: &'a [u8],
+        pos: usize,
+    }
+
+    impl<'a> BitReader<'a> {
+        fn read(&mut self, bits: u32) -> u64 {
+            let mut v = 0;
+            for _ in 0..bits {
+                let b = (self.data[self.pos / 8] >> (7 - self.pos % 8)) & 1;
+                v = (v << 1) | b as u64;
+                self.pos += 1;
+            }
+            v
+        }
+
+        fn read_signed(&mut self, bits: u32) -> i64 {
+            let v = self.read(bits) as i64;
+            (v << (64 - bits)) >> (64 - bits)
+        }
+    }
+
+    /// Decodes the subset of FLAC which is written by the encoder.
+    fn decode(data: &[u8]) -> (u32, Vec<i16>) {
+        assert_eq!(&data[..4], b"fLaC");
+        let mut r = BitReader { data, pos: 32 };
+        assert_eq!(r.read(1), 1);
+        assert_eq!(r.read(7), 0);
+        assert_eq!(r.read(24), 34);
+        r.read(16 + 16 + 24 + 24);
+        let rate = r.read(20) as u32;
+        assert_eq!(r.read(3), 0);
+        assert_eq!(r.read(5), 15);
+        let total = r.read(36) as usize;
+        r.read(128);
+
+        let mut out: Vec<i64> = vec![];
+        while out.len() < total {
+            let start = r.pos / 8;
+            assert_eq!(r.read(16), 0xfff8);
+            assert_eq!(r.read(4), 0b0111);
+            r.read(4 + 4 + 3 + 1);
+            // frame number
+            let first = r.read(8) as u8;
+            let extra = if first < 0x80 {
+                0
+            } else {
+                first.leading_ones() - 1
+            };
+            for _ in 0..extra {
+                assert_eq!(r.read(2), 0b10);
+                r.read(6);
+            }
+            let n = r.read(16) as usize + 1;
+            let crc = crc8(&data[start..r.pos / 8]);
+            assert_eq!(r.read(8) as u8, crc);
+
+            assert_eq!(r.read(1), 0);
+            let t = r.read(6);
+            assert_eq!(r.read(1), 0);
+            let mut x: Vec<i64> = vec![];
+            if t == 1 {
+                for _ in 0..n {
+                    x.push(r.read_signed(16));
+                }
+            } else {
+                assert_eq!(t & 0b111000, 0b001000);
+                let order = (t & 7) as usize;
+                for _ in 0..order {
+                    x.push(r.read_signed(16));
+                }
+                assert_eq!(r.read(2), 0);
+                assert_eq!(r.read(4), 0);
+                let k = r.read(4) as u32;
+                for i in order..n {
+                    let mut q = 0;
+                    while r.read(1) == 0 {
+                        q += 1;
+                    }
+                    let u = (q << k) | r.read(k);
+                    let e = (u >> 1) as i64 ^ -((u & 1) as i64);
+                    let p = match order {
+                        0 => 0,
+                        1 => x[i - 1],
+                        2 => 2 * x[i - 1] - x[i - 2],
+                        3 => 3 * x[i - 1] - 3 * x[i - 2] + x[i - 3],
+                        _ => 4 * x[i - 1] - 6 * x[i - 2] + 4 * x[i - 3] - x[i - 4],
+                    };
+                    x.push(p + e);
+                }
+            }
+            r.pos = r.pos.div_ceil(8) * 8;
+            let crc = crc16(&data[start..r.pos / 8]);
+            assert_eq!(r.read(16) as u16, crc);
+            out.extend(x);
+        }
+        assert_eq!(r.pos / 8, data.len());
+        (rate, out.iter().map(|&s| s as i16).collect())
+    }
+
+    #[test]
+    fn checksums() {
+        // check values of the CRCs used by FLAC
+        assert_eq!(crc8(b"123456789"), 0xf4);
+        assert_eq!(crc16(b"123456789"), 0xfee8);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut seed = 1u32;
+        let noise: Vec<i16> = (0..10000)
+            .map(|i| {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                ((i as f32 * 0.03).sin() * 20000.0) as i16 / 2 + (seed >> 20) as i16 - 2048
+            })
+            .collect();
+        for s in &[
+            noise.clone(),
+            vec![0; 5000],
+            vec![7],
+            vec![i16::MIN, i16::MAX, 0, -1],
+        ] {
+            let f = encode(s, 22050);
+            let (rate, v) = decode(&f);
+            assert_eq!(rate, 22050);
+            assert_eq!(&v, s);
+        }
+        // silence compresses well
+        assert!(encode(&vec![0; 20000], 22050).len() < 3000);
+        assert!(encode(&noise, 22050).len() < noise.len() * 2);
+    }
+}
@@ -1,95 +1,391 @@
 #[cfg(feature = "audio")]
-use base64::decode;
+use audio_filters::{SharedAudioFilter, Sound};
 #[cfg(feature = "audio")]
-use hound::Result;
+use rand::Rng;
 #[cfg(feature = "audio")]
-use rand::{rng, Rng};
+use {Error, Result};
+
+#[cfg(feature = "audio")]
+mod encode;
+#[cfg(feature = "flac")]
+mod flac;
+#[cfg(feature = "audio")]
+mod synth;
 #[cfg(feature = "audio")]
-use serde_json;
+mod voice;
+
+#[cfg(feature = "audio")]
+pub use audio::encode::AudioEncoding;
 #[cfg(feature = "audio")]
-use std::collections::HashMap;
+pub use audio::synth::Synth;
 #[cfg(feature = "audio")]
-use std::io::Cursor;
+pub use audio::voice::{VoicePack, VOICE_PACK_FORMAT};
+
+/// Options for the audio of a CAPTCHA.
+///
+/// The intro, the repetition and the pauses apply to the audio challenge which contains all
+/// characters of a CAPTCHA in one file (see
+/// [`RngCaptcha::as_audio`](struct.RngCaptcha.html#method.as_audio)). The sample rate and the
+/// encoding also apply to the audio of the single characters (see
+/// [`RngCaptcha::as_wav`](struct.RngCaptcha.html#method.as_wav)).
+#[cfg(feature = "audio")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioOptions {
+    intro: bool,
+    repeat: u32,
+    gap: (u32, u32),
+    sample_rate: Option<u32>,
+    encoding: AudioEncoding,
+}
+
+#[cfg(feature = "audio")]
+impl AudioOptions {
+    /// Returns the default options: no intro, no repetition, pauses of 300 to 900 milliseconds
+    /// between the characters and 16 bit WAV files with the sample rate of the voice pack.
+    pub fn new() -> AudioOptions {
+        AudioOptions {
+            intro: false,
+            repeat: 0,
+            gap: (300, 900),
+            sample_rate: None,
+            encoding: AudioEncoding::Pcm16,
+        }
+    }
+
+    /// Speaks the prompt `intro` of the voice pack ("type the following") before the
+    /// characters.
+    pub fn intro(self, intro: bool) -> Self {
+        AudioOptions { intro, ..self }
+    }
+
+    /// Sets how often the whole sequence of characters is repeated after it has been spoken
+    /// the first time.
+    pub fn repeat(self, repeat: u32) -> Self {
+        AudioOptions { repeat, ..self }
+    }
+
+    /// Sets the minimum and the maximum length of the pause between two characters in
+    /// milliseconds. The length of each pause is chosen randomly. The maximum must not exceed
+    /// 10 seconds.
+    pub fn gap(self, min_ms: u32, max_ms: u32) -> Self {
+        AudioOptions {
+            gap: (min_ms, max_ms),
+            ..self
+        }
+    }
+
+    /// Sets the sample rate of the output in Hz. The audio is resampled if the rate differs
+    /// from the rate of the voice pack. Lower rates result in smaller files; 8000 Hz is
+    /// sufficient for speech.
+    pub fn sample_rate(self, sample_rate: u32) -> Self {
+        AudioOptions {
+            sample_rate: Some(sample_rate),
+            ..self
+        }
+    }
+
+    /// Sets the encoding of the output.
+    pub fn encoding(self, encoding: AudioEncoding) -> Self {
+        AudioOptions { encoding, ..self }
+    }
+
+    pub(crate) fn mime_type(&self) -> &'static str {
+        self.encoding.mime_type()
+    }
+
+    pub(crate) fn has_intro(&self) -> bool {
+        self.intro
+    }
+}
+
+#[cfg(feature = "audio")]
+impl Default for AudioOptions {
+    fn default() -> Self {
+        AudioOptions::new()
+    }
+}
+
+/// The maximum length of a pause between two characters in milliseconds.
+#[cfg(feature = "audio")]
+const MAX_GAP_MS: u32 = 10_000;
 
 #[cfg(feature = "audio")]
 pub struct Audio {
-    data: HashMap<char, String>,
+    voice: VoicePack,
+    filters: Vec<SharedAudioFilter>,
 }
 
 #[cfg(feature = "audio")]
 impl Audio {
-    pub fn new() -> Audio {
-        let json = include_str!("audio.json").to_string();
+    pub fn try_new() -> Result<Audio> {
+        Ok(Audio::with_voice(VoicePack::builtin()?))
+    }
 
+    pub fn with_voice(voice: VoicePack) -> Audio {
         Audio {
-            data: serde_json::from_str(&json).expect("invalid json"),
+            voice,
+            filters: vec![],
         }
     }
 
-    pub fn as_wav(&self, letter: char) -> Option<Vec<u8>> {
-        match self.data.get(&letter) {
-            None => None,
-            Some(s) => match decode(s) {
-                Err(_) => None,
-                Ok(v) => match Audio::add_noise(v) {
-                    Ok(v) => Some(v),
-                    _ => None,
-                },
-            },
-        }
+    /// Sets the filters which are applied to each audio before the noise is added.
+    pub fn filters(self, filters: Vec<SharedAudioFilter>) -> Self {
+        Audio { filters, ..self }
     }
 
-    fn add_noise(v: Vec<u8>) -> Result<Vec<u8>> {
-        let mut cursor = Cursor::new(Vec::new());
+    pub fn as_wav<R: Rng>(&self, letter: char, o: &AudioOptions, rng: &mut R) -> Option<Vec<u8>> {
+        self.try_as_wav(letter, o, rng).ok()
+    }
 
-        let spec = hound::WavSpec {
-            channels: 1,
-            sample_rate: 22050,
-            bits_per_sample: 16,
-            sample_format: hound::SampleFormat::Int,
+    pub fn try_as_wav<R: Rng>(
+        &self,
+        letter: char,
+        o: &AudioOptions,
+        rng: &mut R,
+    ) -> Result<Vec<u8>> {
+        let v = self.voice.clip(letter).ok_or(Error::MissingGlyph(letter))?;
+        let v = self.apply_filters(v, rng);
+        // each audio should have the same length
+        self.encode(&v, 36000, o, rng)
+    }
+
+    /// Returns one WAV file which contains all letters.
+    pub fn try_as_audio<R: Rng>(
+        &self,
+        letters: &[char],
+        o: &AudioOptions,
+        rng: &mut R,
+    ) -> Result<Vec<u8>> {
+        if o.gap.0 > o.gap.1 {
+            return Err(Error::InvalidParameter(format!(
+                "minimum gap {} ms is greater than maximum gap {} ms",
+                o.gap.0, o.gap.1
+            )));
+        }
+        if o.gap.1 > MAX_GAP_MS {
+            return Err(Error::InvalidParameter(format!(
+                "maximum gap {} ms is greater than {} ms",
+                o.gap.1, MAX_GAP_MS
+            )));
+        }
+        let ms = |n: u32| n as usize * self.voice.sample_rate() as usize / 1000;
+        let mut samples = vec![];
+        let pause = |samples: &mut Vec<i16>, min: u32, max: u32, rng: &mut R| {
+            let n = ms(rng.random_range(min..=max));
+            samples.resize(samples.len() + n, 0);
         };
 
-        {
-            let mut reader = hound::WavReader::new(&v[..])?;
-            let mut writer = hound::WavWriter::new(&mut cursor, spec)?;
-
-            let mut rng = rng();
-            for s in reader.samples::<i16>() {
-                let mut k: i16 = s?;
-                let rnd: i32 = rng.random_range(0..6000) - 3000;
-                if k as i32 + rnd < i16::MAX as i32 && k as i32 + rnd > i16::MIN as i32 {
-                    k += rnd as i16;
+        pause(&mut samples, 200, 400, rng);
+        if o.intro {
+            let intro = self
+                .voice
+                .prompt("intro")
+                .ok_or_else(|| Error::InvalidParameter("voice pack has no intro".to_string()))?;
+            samples.extend_from_slice(intro);
+            pause(&mut samples, o.gap.1, o.gap.1 * 2, rng);
+        }
+        for i in 0..=o.repeat {
+            if i > 0 {
+                // a longer pause separates the repetitions
+                pause(&mut samples, o.gap.1 * 2, o.gap.1 * 3, rng);
+            }
+            for (j, &letter) in letters.iter().enumerate() {
+                if j > 0 {
+                    pause(&mut samples, o.gap.0, o.gap.1, rng);
                 }
-                writer.write_sample::<i16>(k)?;
+                let v = self.voice.clip(letter).ok_or(Error::MissingGlyph(letter))?;
+                samples.extend_from_slice(v);
             }
-            writer.flush()?;
+        }
+        pause(&mut samples, 200, 400, rng);
+
+        let samples = self.apply_filters(&samples, rng);
+        self.encode(&samples, 0, o, rng)
+    }
+
+    fn apply_filters<R: Rng>(&self, v: &[i16], rng: &mut R) -> Vec<i16> {
+        if self.filters.is_empty() {
+            return v.to_vec();
+        }
+        let mut s = Sound::from_i16(v, self.voice.sample_rate());
+        for f in &self.filters {
+            f.apply(&mut s, &self.voice, rng);
+        }
+        s.to_i16()
+    }
 
-            // each audio should have the same length
-            for _ in writer.len()..36000 {
-                writer.write_sample::<i16>(rng.random_range(0..6000) - 3000)?;
+    /// Adds noise to the samples, pads them with noise to at least `len` samples and encodes
+    /// them as requested by `o`.
+    fn encode<R: Rng>(
+        &self,
+        v: &[i16],
+        len: usize,
+        o: &AudioOptions,
+        rng: &mut R,
+    ) -> Result<Vec<u8>> {
+        let mut samples = Vec::with_capacity(v.len().max(len));
+        for &s in v {
+            let mut k: i16 = s;
+            let rnd: i32 = rng.random_range(0..6000) - 3000;
+            if k as i32 + rnd < i16::MAX as i32 && k as i32 + rnd > i16::MIN as i32 {
+                k += rnd as i16;
             }
+            samples.push(k);
+        }
+        while samples.len() < len {
+            samples.push(rng.random_range(0..6000) - 3000);
         }
 
-        Ok(cursor.get_ref().clone())
+        let rate = self.voice.sample_rate();
+        encode::encode(&samples, rate, o.sample_rate.unwrap_or(rate), o.encoding)
     }
 }
 
 #[cfg(feature = "audio")]
 #[cfg(test)]
 mod tests {
-    use audio::Audio;
+    use audio::{Audio, AudioEncoding, AudioOptions, VoicePack};
     use fonts::{Default, Font};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
     use std::cmp::max;
 
     #[test]
     fn length_of_all_audio() {
         let f = Default::new();
-        let a = Audio::new();
+        let a = Audio::try_new().unwrap();
+        let o = AudioOptions::new();
 
+        let mut rng = StdRng::seed_from_u64(0);
         let mut mx = 0;
         for letter in f.chars() {
-            mx = max(a.as_wav(letter).unwrap().len(), mx)
+            mx = max(a.as_wav(letter, &o, &mut rng).unwrap().len(), mx)
         }
         println!("max audio length: {}", mx);
     }
+
+    #[test]
+    fn deterministic_noise() {
+        let a = Audio::try_new().unwrap();
+        let o = AudioOptions::new();
+        let x = a.as_wav('a', &o, &mut StdRng::seed_from_u64(5));
+        let y = a.as_wav('a', &o, &mut StdRng::seed_from_u64(5));
+        let z = a.as_wav('a', &o, &mut StdRng::seed_from_u64(6));
+        assert_eq!(x, y);
+        assert_ne!(x, z);
+    }
+
+    #[test]
+    fn builtin_voice_pack() {
+        let v = VoicePack::builtin().unwrap();
+        assert_eq!(v.sample_rate(), 22050);
+        for c in Default::new().chars() {
+            let clip = v.clip(c).unwrap();
+            assert!(clip.len() <= 36000, "clip for {} is too long", c);
+        }
+    }
+
+    #[test]
+    fn invalid_voice_pack() {
+        let clip = |s: &str| format!("{{\"format\": 1, \"sample_rate\": 8000, \"clips\": {}}}", s);
+        assert!(VoicePack::from_json("{").is_err());
+        assert!(VoicePack::from_json("{\"format\": 2, \"sample_rate\": 8000}").is_err());
+        assert!(VoicePack::from_json(&clip("{}").replace(": 1,", ": 3,")).is_err());
+        assert!(VoicePack::from_json(&clip("{}")).is_err());
+        assert!(VoicePack::from_json(&clip("{\"ab\": \"\"}")).is_err());
+        assert!(VoicePack::from_json(&clip("{\"a\": \"!!\"}")).is_err());
+    }
+
+    #[test]
+    fn custom_voice_pack() {
+        let mut w = ::std::io::Cursor::new(vec![]);
+        {
+            let spec = hound::WavSpec {
+                channels: 1,
+                sample_rate: 8000,
+                bits_per_sample: 16,
+                sample_format: hound::SampleFormat::Int,
+            };
+            let mut w = hound::WavWriter::new(&mut w, spec).unwrap();
+            for i in 0..100 {
+                w.write_sample::<i16>(i).unwrap();
+            }
+            w.finalize().unwrap();
+        }
+        let wav = ::base64::encode(w.get_ref());
+        let json = format!(
+            "{{\"format\": 1, \"name\": \"test\", \"sample_rate\": 8000, \"clips\": {{\"x\": \"{}\"}}}}",
+            wav
+        );
+        let v = VoicePack::from_json(&json).unwrap();
+        assert_eq!(v.name(), "test");
+        assert_eq!(v.chars(), vec!['x']);
+        assert_eq!(v.clip('x').unwrap().len(), 100);
+
+        let a = Audio::with_voice(v.clone());
+        let o = AudioOptions::new();
+        assert!(a.as_wav('x', &o, &mut StdRng::seed_from_u64(0)).is_some());
+        assert!(a.as_wav('y', &o, &mut StdRng::seed_from_u64(0)).is_none());
+        let intro = AudioOptions::new().intro(true);
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!(a.try_as_audio(&['x'], &intro, &mut rng).is_err());
+
+        let wrong_rate = json.replace("8000", "16000");
+        assert!(VoicePack::from_json(&wrong_rate).is_err());
+
+        // prompts require format 2
+        let intro = format!("\"prompts\": {{\"intro\": \"{}\"}}, \"clips\"", wav);
+        let json = json.replace("\"clips\"", &intro);
+        assert!(VoicePack::from_json(&json).is_err());
+        let v = VoicePack::from_json(&json.replace("\"format\": 1", "\"format\": 2")).unwrap();
+        assert_eq!(v.prompt("intro").unwrap().len(), 100);
+    }
+
+    fn samples(wav: &[u8]) -> usize {
+        hound::WavReader::new(wav).unwrap().len() as usize
+    }
+
+    #[test]
+    fn combined_audio() {
+        let a = Audio::try_new().unwrap();
+        let v = VoicePack::builtin().unwrap();
+        let letters = ['a', 'B', '3'];
+        let spoken: usize = letters.iter().map(|&c| v.clip(c).unwrap().len()).sum();
+        let audio = |o: &AudioOptions, seed: u64| {
+            a.try_as_audio(&letters, o, &mut StdRng::seed_from_u64(seed))
+                .unwrap()
+        };
+
+        let o = AudioOptions::new().gap(500, 1000);
+        let x = audio(&o, 1);
+        assert_eq!(x, audio(&o, 1));
+        assert_ne!(samples(&x), samples(&audio(&o, 2)));
+        let n = samples(&x);
+        // two pauses between the letters and at least 200 ms at both ends
+        assert!(n >= spoken + 2 * 11025 + 2 * 4410);
+        assert!(n <= spoken + 2 * 22050 + 2 * 8820);
+
+        let intro = v.prompt("intro").unwrap().len();
+        assert!(samples(&audio(&o.clone().intro(true), 1)) >= n + intro);
+        assert!(samples(&audio(&o.clone().repeat(2), 1)) >= 3 * spoken);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!(a
+            .try_as_audio(&letters, &AudioOptions::new().gap(10, 5), &mut rng)
+            .is_err());
+        let long = AudioOptions::new().gap(0, u32::MAX);
+        assert!(a.try_as_audio(&letters, &long, &mut rng).is_err());
+        assert!(a.try_as_audio(&['%'], &o, &mut rng).is_err());
+
+        let narrow = audio(
+            &o.clone().sample_rate(8000).encoding(AudioEncoding::Pcm8),
+            1,
+        );
+        let r = hound::WavReader::new(&narrow[..]).unwrap();
+        assert_eq!((r.spec().sample_rate, r.spec().bits_per_sample), (8000, 8));
+        assert!(narrow.len() < x.len() / 5);
+        assert!(a
+            .try_as_audio(&letters, &o.clone().sample_rate(1), &mut rng)
+            .is_err());
+    }
 }
@@ -0,0 +1,958 @@
+//! A formant synthesiser which speaks arbitrary text.
+//!
+//! This is a port of the synthesiser in `gen/audio/gen.py` which creates the embedded voice
+//! pack. With the default parameters both produce the same clips.
+
+use rand::Rng;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::iter::once;
+
+use audio::VoicePack;
+use {Error, Result};
+
+const SAMPLE_RATE: u32 = 22050;
+/// Spelled characters are spoken faster if they do not fit into this number of samples. The
+/// audio module pads every clip to 36000 samples.
+const MAX_SAMPLES: usize = 36000 - 400;
+/// The names of symbols may contain symbols with names themselves. They are expanded at most
+/// this many times, so that names which refer to each other are rejected.
+const MAX_NAME_DEPTH: usize = 8;
+/// The length of a frame in seconds. The parameters of the synthesiser change once per frame.
+const FRAME: f64 = 0.005;
+
+type Formants = [f64; 3];
+
+const VOWELS: &[(&str, Formants)] = &[
+    ("IY", [270.0, 2290.0, 3010.0]),
+    ("IH", [390.0, 1990.0, 2550.0]),
+    ("EH", [530.0, 1840.0, 2480.0]),
+    ("AE", [660.0, 1720.0, 2410.0]),
+    ("AA", [730.0, 1090.0, 2440.0]),
+    ("AO", [570.0, 840.0, 2410.0]),
+    ("UH", [440.0, 1020.0, 2240.0]),
+    ("UW", [300.0, 870.0, 2240.0]),
+    ("AH", [640.0, 1190.0, 2390.0]),
+    ("ER", [490.0, 1350.0, 1690.0]),
+];
+
+const DIPHTHONGS: &[(&str, (Formants, Formants))] = &[
+    ("EY", ([480.0, 1900.0, 2500.0], [300.0, 2250.0, 2900.0])),
+    ("AY", ([700.0, 1200.0, 2500.0], [350.0, 2100.0, 2800.0])),
+    ("OW", ([550.0, 950.0, 2400.0], [350.0, 800.0, 2300.0])),
+    ("AW", ([700.0, 1200.0, 2500.0], [400.0, 900.0, 2300.0])),
+    ("OY", ([550.0, 850.0, 2400.0], [350.0, 2000.0, 2700.0])),
+];
+
+const APPROXIMANTS: &[(&str, Formants)] = &[
+    ("L", [360.0, 1300.0, 2700.0]),
+    ("R", [420.0, 1300.0, 1600.0]),
+    ("W", [300.0, 700.0, 2200.0]),
+    ("Y", [260.0, 2100.0, 3000.0]),
+];
+
+const NASALS: &[(&str, Formants)] = &[
+    ("M", [280.0, 1000.0, 2200.0]),
+    ("N", [280.0, 1600.0, 2600.0]),
+    ("NG", [280.0, 2100.0, 2700.0]),
+];
+
+/// Voicing, frication, centre and bandwidth of the frication.
+type Frication = (f64, f64, f64, f64);
+
+const FRICATIVES: &[(&str, Frication)] = &[
+    ("F", (0.0, 0.25, 6000.0, 3000.0)),
+    ("V", (0.35, 0.15, 6000.0, 3000.0)),
+    ("TH", (0.0, 0.2, 5000.0, 3000.0)),
+    ("DH", (0.4, 0.1, 5000.0, 3000.0)),
+    ("S", (0.0, 0.7, 5500.0, 1500.0)),
+    ("Z", (0.35, 0.45, 5500.0, 1500.0)),
+    ("SH", (0.0, 0.7, 2800.0, 1200.0)),
+    ("ZH", (0.35, 0.45, 2800.0, 1200.0)),
+];
+
+/// Locus, centre of the burst and whether the stop is voiced.
+const STOPS: &[(&str, (Formants, f64, bool))] = &[
+    ("P", ([250.0, 900.0, 2100.0], 1000.0, false)),
+    ("B", ([250.0, 900.0, 2100.0], 1000.0, true)),
+    ("T", ([250.0, 1700.0, 2600.0], 4500.0, false)),
+    ("D", ([250.0, 1700.0, 2600.0], 4500.0, true)),
+    ("K", ([250.0, 1900.0, 2000.0], 2000.0, false)),
+    ("G", ([250.0, 1900.0, 2000.0], 2000.0, true)),
+];
+
+const OTHERS: &[&str] = &["HH", "CH", "JH"];
+
+const NEUTRAL: Formants = [450.0, 1400.0, 2500.0];
+
+/// Pronunciations (ARPAbet, a trailing 1 marks the stressed vowel) of the words which are
+/// used to spell characters.
+const WORDS: &[(&str, &str)] = &[
+    ("capital", "K AE1 P IH T AH L"),
+    ("lower", "L OW1 ER"),
+    ("case", "K EY1 S"),
+    ("letter", "L EH1 T ER"),
+    ("number", "N AH1 M B ER"),
+    ("type", "T AY1 P"),
+    ("the", "DH AH"),
+    ("following", "F AA1 L OW IH NG"),
+    ("one", "W AH1 N"),
+    ("two", "T UW1"),
+    ("three", "TH R IY1"),
+    ("four", "F AO1 R"),
+    ("five", "F AY1 V"),
+    ("six", "S IH1 K S"),
+    ("seven", "S EH1 V AH N"),
+    ("eight", "EY1 T"),
+    ("nine", "N AY1 N"),
+    ("zero", "Z IH1 R OW"),
+    ("plus", "P L AH1 S"),
+    ("minus", "M AY1 N AH S"),
+    ("hash", "HH AE1 SH"),
+    ("at", "AE1 T"),
+    ("and", "AE1 N D"),
+    ("star", "S T AA1 R"),
+    ("percent", "P ER S EH1 N T"),
+    ("equals", "IY1 K W AH L Z"),
+    ("dollar", "D AA1 L ER"),
+    ("slash", "S L AE1 SH"),
+    ("dot", "D AA1 T"),
+    ("question", "K W EH1 S CH AH N"),
+    ("exclamation", "EH K S K L AH M EY1 SH AH N"),
+    ("mark", "M AA1 R K"),
+    ("underscore", "AH1 N D ER S K AO R"),
+];
+
+const DIGITS: [&str; 10] = [
+    "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine",
+];
+
+/// The names of the letters of the English alphabet.
+const LETTERS: [&str; 26] = [
+    "EY1",
+    "B IY1",
+    "S IY1",
+    "D IY1",
+    "IY1",
+    "EH1 F",
+    "JH IY1",
+    "EY1 CH",
+    "AY1",
+    "JH EY1",
+    "K EY1",
+    "EH1 L",
+    "EH1 M",
+    "EH1 N",
+    "OW1",
+    "P IY1",
+    "K Y UW1",
+    "AA1 R",
+    "EH1 S",
+    "T IY1",
+    "Y UW1",
+    "V IY1",
+    "D AH1 B AH L Y UW",
+    "EH1 K S",
+    "W AY1",
+    "Z IY1",
+];
+
+const SYMBOLS: &[(char, &str)] = &[
+    ('+', "plus"),
+    ('-', "minus"),
+    ('#', "hash"),
+    ('@', "at"),
+    ('&', "and"),
+    ('*', "star"),
+    ('%', "percent"),
+    ('=', "equals"),
+    ('$', "dollar"),
+    ('/', "slash"),
+    ('.', "dot"),
+    ('?', "question mark"),
+    ('!', "exclamation mark"),
+    ('_', "underscore"),
+];
+
+/// Letter to sound rules for words which are not in the lexicon. The longest match wins, so
+/// groups of letters come first. Besides English the rules read languages with a regular
+/// spelling reasonably well.
+const RULES: &[(&str, &str)] = &[
+    ("tion", "SH AH N"),
+    ("sch", "SH"),
+    ("tch", "CH"),
+    ("igh", "AY"),
+    ("th", "TH"),
+    ("sh", "SH"),
+    ("ch", "CH"),
+    ("ph", "F"),
+    ("ck", "K"),
+    ("ng", "NG"),
+    ("qu", "K W"),
+    ("wh", "W"),
+    ("kn", "N"),
+    ("ee", "IY"),
+    ("ea", "IY"),
+    ("oo", "UW"),
+    ("ou", "AW"),
+    ("ow", "OW"),
+    ("ai", "EY"),
+    ("ay", "EY"),
+    ("oa", "OW"),
+    ("ie", "IY"),
+    ("ei", "AY"),
+    ("au", "AO"),
+    ("oi", "OY"),
+    ("oy", "OY"),
+    ("ue", "UW"),
+    ("er", "ER"),
+    ("a", "AE"),
+    ("b", "B"),
+    ("c", "K"),
+    ("d", "D"),
+    ("e", "EH"),
+    ("f", "F"),
+    ("g", "G"),
+    ("h", "HH"),
+    ("i", "IH"),
+    ("j", "JH"),
+    ("k", "K"),
+    ("l", "L"),
+    ("m", "M"),
+    ("n", "N"),
+    ("o", "AA"),
+    ("p", "P"),
+    ("q", "K"),
+    ("r", "R"),
+    ("s", "S"),
+    ("t", "T"),
+    ("u", "AH"),
+    ("v", "V"),
+    ("w", "W"),
+    ("x", "K S"),
+    ("y", "IY"),
+    ("z", "Z"),
+    ("à", "AA"),
+    ("á", "AA"),
+    ("â", "AA"),
+    ("ä", "EH"),
+    ("å", "AO"),
+    ("æ", "AE"),
+    ("ç", "S"),
+    ("è", "EH"),
+    ("é", "EY"),
+    ("ê", "EH"),
+    ("ë", "EH"),
+    ("ì", "IY"),
+    ("í", "IY"),
+    ("î", "IY"),
+    ("ï", "IY"),
+    ("ñ", "N Y"),
+    ("ò", "OW"),
+    ("ó", "OW"),
+    ("ô", "OW"),
+    ("ö", "ER"),
+    ("ø", "ER"),
+    ("ß", "S"),
+    ("ù", "UW"),
+    ("ú", "UW"),
+    ("û", "UW"),
+    ("ü", "UW"),
+    ("ý", "IY"),
+    ("'", ""),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Token {
+    /// A phoneme and whether it is stressed.
+    Phone(&'static str, bool),
+    /// A pause in milliseconds.
+    Pause(f64),
+}
+
+fn find<T: Copy>(table: &[(&'static str, T)], ph: &str) -> Option<T> {
+    table.iter().find(|x| x.0 == ph).map(|x| x.1)
+}
+
+/// Returns the phoneme with the given name.
+fn phoneme(name: &str) -> Option<&'static str> {
+    let names = VOWELS.iter().map(|x| x.0);
+    let names = names.chain(DIPHTHONGS.iter().map(|x| x.0));
+    let names = names.chain(APPROXIMANTS.iter().map(|x| x.0));
+    let names = names.chain(NASALS.iter().map(|x| x.0));
+    let names = names.chain(FRICATIVES.iter().map(|x| x.0));
+    let names = names.chain(STOPS.iter().map(|x| x.0));
+    let mut names = names.chain(OTHERS.iter().cloned());
+    names.find(|&x| x == name)
+}
+
+fn is_vowel(ph: &str) -> bool {
+    find(VOWELS, ph).is_some() || find(DIPHTHONGS, ph).is_some()
+}
+
+/// Parses phonemes in ARPAbet, e.g. `K AE1 T`.
+fn parse(phonemes: &str) -> Result<Vec<Token>> {
+    phonemes
+        .split_whitespace()
+        .map(|p| {
+            let name = p.trim_end_matches(|c: char| c.is_ascii_digit());
+            let stressed = p.ends_with('1') || p.ends_with('2');
+            phoneme(name)
+                .map(|ph| Token::Phone(ph, stressed))
+                .ok_or_else(|| Error::invalid(format!("unknown phoneme {:?}", p)))
+        })
+        .collect()
+}
+
+/// Reads a word which is not in the lexicon with the letter to sound rules. The first vowel
+/// is stressed.
+fn read(word: &str) -> Result<Vec<Token>> {
+    let mut out = vec![];
+    let n = word.chars().count();
+    let mut i = 0;
+    while i < word.len() {
+        let rest = &word[i..];
+        let mut it = rest.chars();
+        let c = it.next().expect("not at the end");
+        let next = it.next();
+        let rule = RULES
+            .iter()
+            .find(|r| rest.starts_with(r.0))
+            .ok_or(Error::MissingGlyph(c))?;
+        let (len, ph) = if c == 'c' && next.is_some_and(|x| "eiy".contains(x)) {
+            (1, "S")
+        } else if c == 'e' && next.is_none() && n > 2 {
+            // a final e is silent
+            (1, "")
+        } else if c == 'y' && i == 0 && next.is_some_and(|x| "aeiou".contains(x)) {
+            (1, "Y")
+        } else {
+            (rule.0.len(), rule.1)
+        };
+        out.extend(parse(ph)?);
+        i += len;
+        // double consonants are spoken once
+        if len == 1 && !"aeiou".contains(c) && word[i..].starts_with(c) {
+            i += c.len_utf8();
+        }
+    }
+    if let Some(t) = out.iter_mut().find(|t| match **t {
+        Token::Phone(ph, _) => is_vowel(ph),
+        Token::Pause(_) => false,
+    }) {
+        if let Token::Phone(ph, _) = *t {
+            *t = Token::Phone(ph, true);
+        }
+    }
+    Ok(out)
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Segment {
+    ms: f64,
+    formants: Option<Formants>,
+    voicing: f64,
+    aspiration: f64,
+    frication: f64,
+    centre: f64,
+    bandwidth: f64,
+}
+
+fn seg(ms: f64, formants: Option<Formants>, v: f64, a: f64, f: f64, c: f64, b: f64) -> Segment {
+    Segment {
+        ms,
+        formants,
+        voicing: v,
+        aspiration: a,
+        frication: f,
+        centre: c,
+        bandwidth: b,
+    }
+}
+
+fn silence(ms: f64) -> Segment {
+    seg(ms, Some(NEUTRAL), 0.0, 0.0, 0.0, 0.0, 1.0)
+}
+
+/// Returns the segments of a phoneme. Formants which are `None` are taken from the next
+/// segment.
+fn segments(ph: &str, stressed: bool) -> Vec<Segment> {
+    let d = if stressed { 1.4 } else { 1.0 };
+    if let Some(f) = find(VOWELS, ph) {
+        let ms = if ph != "AH" || stressed {
+            140.0 * d
+        } else {
+            60.0
+        };
+        return vec![seg(ms, Some(f), 1.0, 0.0, 0.0, 0.0, 1.0)];
+    }
+    if let Some((a, b)) = find(DIPHTHONGS, ph) {
+        return vec![
+            seg(90.0 * d, Some(a), 1.0, 0.0, 0.0, 0.0, 1.0),
+            seg(70.0 * d, Some(b), 1.0, 0.0, 0.0, 0.0, 1.0),
+        ];
+    }
+    if let Some(f) = find(APPROXIMANTS, ph) {
+        return vec![seg(70.0, Some(f), 0.7, 0.0, 0.0, 0.0, 1.0)];
+    }
+    if let Some(f) = find(NASALS, ph) {
+        return vec![seg(75.0, Some(f), 0.45, 0.0, 0.0, 0.0, 1.0)];
+    }
+    if let Some((av, af, fc, bw)) = find(FRICATIVES, ph) {
+        return vec![seg(110.0, Some(NEUTRAL), av, 0.0, af, fc, bw)];
+    }
+    if let Some((locus, fc, voiced)) = find(STOPS, ph) {
+        let (av, af) = if voiced { (0.12, 0.3) } else { (0.0, 0.5) };
+        let mut r = vec![
+            seg(60.0, Some(locus), av, 0.0, 0.0, 0.0, 1.0),
+            seg(12.0, Some(locus), 0.0, 0.0, af, fc, 2000.0),
+        ];
+        if !voiced {
+            r.push(seg(45.0, None, 0.0, 0.45, 0.0, 0.0, 1.0));
+        }
+        return r;
+    }
+    match ph {
+        "HH" => vec![seg(70.0, None, 0.0, 0.5, 0.0, 0.0, 1.0)],
+        _ => {
+            // CH and JH
+            let voiced = ph == "JH";
+            let locus = find(STOPS, "T").expect("T is a stop").0;
+            let av = if voiced { (0.12, 0.3) } else { (0.0, 0.0) };
+            vec![
+                seg(55.0, Some(locus), av.0, 0.0, 0.0, 0.0, 1.0),
+                seg(90.0, Some(locus), av.1, 0.0, 0.6, 2800.0, 1200.0),
+            ]
+        }
+    }
+}
+
+fn smooth(xs: &[f64], n: usize) -> Vec<f64> {
+    let h = n / 2;
+    (0..xs.len())
+        .map(|i| {
+            let w = &xs[i.saturating_sub(h)..(i + h + 1).min(xs.len())];
+            w.iter().sum::<f64>() / w.len() as f64
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct Resonator {
+    y1: f64,
+    y2: f64,
+}
+
+impl Resonator {
+    fn process(&mut self, x: f64, f: f64, bw: f64) -> f64 {
+        let rate = SAMPLE_RATE as f64;
+        let c = -(-2.0 * PI * bw / rate).exp();
+        let b = 2.0 * (-PI * bw / rate).exp() * (2.0 * PI * f / rate).cos();
+        let a = 1.0 - b - c;
+        let y = a * x + b * self.y1 + c * self.y2;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y
+    }
+}
+
+/// A speech synthesiser which speaks arbitrary text.
+///
+/// Unlike a [`VoicePack`](struct.VoicePack.html), which only contains recordings of a fixed
+/// set of characters, the synthesiser can spell any character and speak prompts in any
+/// language which is written in the Latin alphabet. Its voice is controlled by the pitch, the
+/// speed and the timbre, so each CAPTCHA can be spoken by a different voice (see
+/// [`vary`](#method.vary) and
+/// [`RngCaptcha::set_synth`](struct.RngCaptcha.html#method.set_synth)).
+///
+/// Words are looked up in a small English lexicon, which can be extended with
+/// [`word`](#method.word); all other words are read with simple letter to sound rules.
+/// Characters are spelled as "capital x", "lower case letter x" or "number n" and symbols by
+/// their name, e.g. "hash". Use [`name`](#method.name) to spell other characters or to spell
+/// them in another language.
+///
+/// # Example
+///
+/// ```
+/// use captcha::Synth;
+///
+/// let s = Synth::new()
+///     .pitch(180.0)
+///     .prompt("intro", "bitte geben Sie Folgendes ein")
+///     .name('ä', "a umlaut");
+/// let clip = s.spell('ä').unwrap();
+/// let pack = s.voice_pack(&['ä', 'x', '7']).unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Synth {
+    pitch: f32,
+    speed: f32,
+    timbre: f32,
+    seed: u32,
+    words: HashMap<String, Vec<Token>>,
+    names: HashMap<char, String>,
+    prompts: HashMap<String, String>,
+}
+
+impl Synth {
+    /// Returns a synthesiser with the voice of the embedded voice pack and the prompt `intro`
+    /// ("type the following").
+    pub fn new() -> Synth {
+        let mut prompts = HashMap::new();
+        prompts.insert("intro".to_string(), "type the following".to_string());
+        Synth {
+            pitch: 120.0,
+            speed: 1.0,
+            timbre: 1.0,
+            seed: 0,
+            words: HashMap::new(),
+            names: HashMap::new(),
+            prompts,
+        }
+    }
+
+    /// Sets the pitch of the voice in Hz. Valid values are in `50..=400`.
+    pub fn pitch(self, hz: f32) -> Self {
+        Synth { pitch: hz, ..self }
+    }
+
+    /// Sets the speed relative to the normal speed. Valid values are in `0.5..=2`.
+    pub fn speed(self, speed: f32) -> Self {
+        Synth { speed, ..self }
+    }
+
+    /// Sets the size of the vocal tract relative to the normal size by scaling the formants.
+    /// Values above 1 sound like a smaller person. Valid values are in `0.7..=1.4`.
+    pub fn timbre(self, timbre: f32) -> Self {
+        Synth { timbre, ..self }
+    }
+
+    /// Sets the pronunciation of a word in ARPAbet, e.g. `("hola", "OW1 L AA")`.
+    ///
+    /// Returns an error if one of the phonemes is unknown.
+    pub fn word(self, word: &str, phonemes: &str) -> Result<Synth> {
+        let p = parse(phonemes)?;
+        if p.is_empty() {
+            return Err(Error::invalid(format!("no phonemes for {:?}", word)));
+        }
+        let mut words = self.words;
+        words.insert(word.to_lowercase(), p);
+        Ok(Synth { words, ..self })
+    }
+
+    /// Sets the text which is spoken when the character `c` is spelled, e.g. `('#', "Raute")`.
+    /// A symbol with a name is also spoken by its name when it appears in a text.
+    /// Speaking a name which contains the symbol itself, directly or via other names, returns
+    /// an error.
+    pub fn name(self, c: char, text: &str) -> Self {
+        let mut names = self.names;
+        names.insert(c, text.to_string());
+        Synth { names, ..self }
+    }
+
+    /// Sets the text of a prompt, e.g. `("intro", "escriba lo siguiente")`.
+    pub fn prompt(self, name: &str, text: &str) -> Self {
+        let mut prompts = self.prompts;
+        prompts.insert(name.to_string(), text.to_string());
+        Synth { prompts, ..self }
+    }
+
+    /// Returns a copy with a random voice close to this voice: the pitch changes by a factor
+    /// of 0.7 to 1.6, the speed by 0.85 to 1.15 and the timbre by 0.9 to 1.15. The random
+    /// numbers are taken from `rng`.
+    pub fn vary<R: Rng>(&self, rng: &mut R) -> Synth {
+        Synth {
+            pitch: (self.pitch * rng.random_range(0.7..=1.6)).clamp(50.0, 400.0),
+            speed: (self.speed * rng.random_range(0.85..=1.15)).clamp(0.5, 2.0),
+            timbre: (self.timbre * rng.random_range(0.9..=1.15)).clamp(0.7, 1.4),
+            seed: rng.random(),
+            ..self.clone()
+        }
+    }
+
+    /// Checks whether the parameters are valid.
+    pub fn validate(&self) -> Result<()> {
+        if !(50.0..=400.0).contains(&self.pitch) {
+            return Err(Error::invalid("the pitch must be in 50..=400 Hz"));
+        }
+        if !(0.5..=2.0).contains(&self.speed) {
+            return Err(Error::invalid("the speed must be in 0.5..=2"));
+        }
+        if !(0.7..=1.4).contains(&self.timbre) {
+            return Err(Error::invalid("the timbre must be in 0.7..=1.4"));
+        }
+        Ok(())
+    }
+
+    /// Returns the sample rate of the synthesised audio.
+    pub fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    /// Speaks a text.
+    ///
+    /// Returns an error if the parameters are invalid, if the text contains a character which
+    /// cannot be spoken or if there is nothing to speak.
+    pub fn speak(&self, text: &str) -> Result<Vec<i16>> {
+        self.validate()?;
+        let tokens = self.tokens(text)?;
+        Ok(self.synthesize(&tokens, None, text.chars().count() as u32))
+    }
+
+    /// Spells a character, e.g. "capital a" for `A`. The clip is spoken faster if it would
+    /// be longer than the clips of the embedded voice pack.
+    pub fn spell(&self, c: char) -> Result<Vec<i16>> {
+        self.validate()?;
+        let tokens = self.spelling(c)?;
+        Ok(self.synthesize(&tokens, Some(MAX_SAMPLES), c as u32))
+    }
+
+    /// Creates a voice pack with the given characters and all prompts.
+    pub fn voice_pack(&self, chars: &[char]) -> Result<VoicePack> {
+        let names: Vec<&str> = self.prompts.keys().map(|k| k.as_str()).collect();
+        self.voice_pack_with(chars, &names)
+    }
+
+    /// Creates a voice pack with the given characters and the given prompts. Prompts which
+    /// have no text are left out.
+    pub(crate) fn voice_pack_with(&self, chars: &[char], names: &[&str]) -> Result<VoicePack> {
+        let mut clips = HashMap::new();
+        for &c in chars {
+            if let Entry::Vacant(e) = clips.entry(c) {
+                e.insert(self.spell(c)?);
+            }
+        }
+        let mut prompts = HashMap::new();
+        for &name in names {
+            if let Some(text) = self.prompts.get(name) {
+                prompts.insert(name.to_string(), self.speak(text)?);
+            }
+        }
+        VoicePack::new("synth", SAMPLE_RATE, clips, prompts)
+    }
+
+    fn spelling(&self, c: char) -> Result<Vec<Token>> {
+        if let Some(text) = self.names.get(&c) {
+            return self.tokens(text);
+        }
+        if let Some(d) = c.to_digit(10) {
+            return self.tokens(&format!("number {}", DIGITS[d as usize]));
+        }
+        if let Some(&(_, text)) = SYMBOLS.iter().find(|x| x.0 == c) {
+            return self.tokens(text);
+        }
+        let lower = c.to_lowercase().next().unwrap_or(c);
+        let prefix = if c.is_uppercase() {
+            "capital"
+        } else if c.is_lowercase() {
+            "lower case letter"
+        } else {
+            return Err(Error::MissingGlyph(c));
+        };
+        let letter = if lower.is_ascii_lowercase() {
+            parse(LETTERS[lower as usize - 'a' as usize])?
+        } else {
+            read(&lower.to_string()).map_err(|_| Error::MissingGlyph(c))?
+        };
+        let mut t = self.tokens(prefix)?;
+        t.extend(letter);
+        Ok(t)
+    }
+
+    fn pronounce(&self, word: &str) -> Result<Vec<Token>> {
+        let w = word.to_lowercase();
+        if let Some(t) = self.words.get(&w) {
+            return Ok(t.clone());
+        }
+        match find(WORDS, &w) {
+            Some(p) => parse(p),
+            None => read(&w),
+        }
+    }
+
+    fn tokens(&self, text: &str) -> Result<Vec<Token>> {
+        self.tokens_at(text, 0)
+    }
+
+    /// Returns the tokens of a text which is part of a name expanded `depth` times.
+    fn tokens_at(&self, text: &str, depth: usize) -> Result<Vec<Token>> {
+        if depth > MAX_NAME_DEPTH {
+            return Err(Error::invalid(format!(
+                "the names of symbols refer to each other in {:?}",
+                text
+            )));
+        }
+        let mut out = vec![];
+        let mut word = String::new();
+        for c in text.chars().chain(once(' ')) {
+            if c.is_alphabetic() || c == '\'' {
+                word.push(c);
+                continue;
+            }
+            if !word.is_empty() {
+                out.extend(self.pronounce(&word)?);
+                word.clear();
+            }
+            if let Some(name) = self.names.get(&c) {
+                out.extend(self.tokens_at(name, depth + 1)?);
+            } else if c.is_whitespace() || c == '-' {
+                continue;
+            } else if let Some(d) = c.to_digit(10) {
+                out.extend(self.pronounce(DIGITS[d as usize])?);
+            } else if ",;:".contains(c) {
+                out.push(Token::Pause(150.0));
+            } else if ".!?".contains(c) {
+                out.push(Token::Pause(300.0));
+            } else if let Some(&(_, name)) = SYMBOLS.iter().find(|x| x.0 == c) {
+                out.extend(self.tokens_at(name, depth + 1)?);
+            } else {
+                return Err(Error::MissingGlyph(c));
+            }
+        }
+        if !out.iter().any(|t| match *t {
+            Token::Phone(..) => true,
+            Token::Pause(_) => false,
+        }) {
+            return Err(Error::invalid(format!("nothing to speak in {:?}", text)));
+        }
+        Ok(out)
+    }
+
+    fn synthesize(&self, tokens: &[Token], max: Option<usize>, seed: u32) -> Vec<i16> {
+        let mut segs = vec![silence(50.0)];
+        for t in tokens {
+            match *t {
+                Token::Phone(ph, stressed) => segs.extend(segments(ph, stressed)),
+                Token::Pause(ms) => segs.push(seg(ms, None, 0.0, 0.0, 0.0, 0.0, 1.0)),
+            }
+        }
+        segs.push(silence(60.0));
+        // formants which are None are taken from the next segment which has formants
+        let mut next = NEUTRAL;
+        for s in segs.iter_mut().rev() {
+            match s.formants {
+                Some(f) => next = f,
+                None => s.formants = Some(next),
+            }
+        }
+
+        let rate = SAMPLE_RATE as f64;
+        let speed = self.speed as f64;
+        let total = segs.iter().map(|s| s.ms).sum::<f64>() / 1000.0 / speed;
+        let fit = max.map_or(1.0, |m| (m as f64 / rate / total).min(1.0));
+        let timbre = self.timbre as f64;
+
+        let mut tracks: [Vec<f64>; 8] = Default::default();
+        for s in &segs {
+            let frames = (s.ms / speed * fit / 1000.0 / FRAME).round_ties_even() as usize;
+            let f = s.formants.unwrap_or(NEUTRAL);
+            let values = [
+                f[0] * timbre,
+                f[1] * timbre,
+                f[2] * timbre,
+                s.voicing,
+                s.aspiration,
+                s.frication,
+                s.centre,
+                s.bandwidth,
+            ];
+            for _ in 0..frames.max(1) {
+                for (t, &v) in tracks.iter_mut().zip(values.iter()) {
+                    t.push(v);
+                }
+            }
+        }
+        let (f1, f2, f3) = (
+            smooth(&tracks[0], 7),
+            smooth(&tracks[1], 7),
+            smooth(&tracks[2], 7),
+        );
+        let (av, ah, af) = (
+            smooth(&tracks[3], 3),
+            smooth(&tracks[4], 3),
+            smooth(&tracks[5], 3),
+        );
+        let (fc, fbw) = (&tracks[6], &tracks[7]);
+
+        let per_frame = FRAME * rate;
+        let n = f1.len() * per_frame as usize;
+        let pitch = self.pitch as f64;
+        // the intonation is scaled with the pitch
+        let k = pitch / 120.0;
+        let mut rs: [Resonator; 5] = Default::default();
+        let mut rf = Resonator::default();
+        let mut noise = (self.seed.wrapping_add(seed) & 0x7fff_ffff) as u64;
+        let mut phase = 0.0;
+        let mut prev = 0.0;
+        let mut out = Vec::with_capacity(n);
+        for i in 0..n {
+            let j = ((i as f64 / per_frame) as usize).min(f1.len() - 1);
+            let t = i as f64 / n as f64;
+            let f0 = pitch + 15.0 * k * (PI * (t * 2.5).min(1.0)).sin() - 25.0 * k * t;
+            phase += f0 / rate;
+            if phase >= 1.0 {
+                phase -= 1.0;
+            }
+            // Rosenberg glottal pulse, differentiated for the lip radiation
+            let g = if phase < 0.4 {
+                0.5 * (1.0 - (PI * phase / 0.4).cos())
+            } else if phase < 0.56 {
+                (PI * (phase - 0.4) / 0.32).cos()
+            } else {
+                0.0
+            };
+            let src = (g - prev) * 8.0;
+            prev = g;
+            noise = (noise * 1_103_515_245 + 12345) & 0x7fff_ffff;
+            let white = noise as f64 / 0x3fff_ffff as f64 - 1.0;
+
+            let mut x = av[j] * src + ah[j] * white * 0.3;
+            x = rs[0].process(x, f1[j], 80.0);
+            x = rs[1].process(x, f2[j], 100.0);
+            x = rs[2].process(x, f3[j], 150.0);
+            x = rs[3].process(x, 3500.0 * timbre, 250.0);
+            x = rs[4].process(x, 4500.0 * timbre, 300.0);
+            if af[j] > 0.0 {
+                x += rf.process(white * af[j], fc[j], fbw[j]) * 0.8;
+            }
+            out.push(x);
+        }
+
+        let peak = out.iter().fold(0.0f64, |m, x| m.max(x.abs()));
+        let peak = if peak > 0.0 { peak } else { 1.0 };
+        out.iter()
+            .map(|x| (x / peak * 20000.0).round_ties_even() as i16)
+            .collect()
+    }
+}
+
+impl Default for Synth {
+    fn default() -> Self {
+        Synth::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use audio::synth::{parse, read, Synth, Token, LETTERS, RULES, WORDS};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use {Error, VoicePack};
+
+    #[test]
+    fn tables() {
+        for &(_, p) in WORDS.iter().chain(RULES.iter()) {
+            parse(p).unwrap();
+        }
+        for p in LETTERS.iter() {
+            parse(p).unwrap();
+        }
+        assert!(parse("K AE1 X").is_err());
+        assert_eq!(
+            parse("K AE1 T").unwrap(),
+            vec![
+                Token::Phone("K", false),
+                Token::Phone("AE", true),
+                Token::Phone("T", false)
+            ]
+        );
+        assert_eq!(read("sheep").unwrap(), parse("SH IY1 P").unwrap());
+        assert_eq!(read("city").unwrap(), parse("S IH1 T IY").unwrap());
+        assert_eq!(read("hello").unwrap(), parse("HH EH1 L AA").unwrap());
+    }
+
+    #[test]
+    fn same_as_voice_pack() {
+        let v = VoicePack::builtin().unwrap();
+        let s = Synth::new();
+        for &c in &['A', 'q', '7'] {
+            assert_eq!(&s.spell(c).unwrap()[..], v.clip(c).unwrap(), "{}", c);
+        }
+        let intro = s.speak("type the following").unwrap();
+        assert_eq!(&intro[..], v.prompt("intro").unwrap());
+    }
+
+    #[test]
+    fn voice_pack_with_prompts() {
+        let s = Synth::new().prompt("outro", "thank you");
+        let p = s.voice_pack_with(&['x'], &[]).unwrap();
+        assert!(p.prompt("intro").is_none());
+        let p = s.voice_pack_with(&['x'], &["intro", "missing"]).unwrap();
+        assert!(p.prompt("intro").is_some());
+        assert!(p.prompt("outro").is_none());
+        assert!(s.voice_pack(&['x']).unwrap().prompt("outro").is_some());
+    }
+
+    #[test]
+    fn speak() {
+        let s = Synth::new();
+        assert!(s.speak("Hallo Welt, wie geht's?").unwrap().len() > 22050);
+        assert!(s.speak("señor, à bientôt").is_ok());
+        assert!(s.speak("a + b = 3").is_ok());
+        assert!(s.speak("").is_err());
+        assert!(s.speak(" .. ").is_err());
+        match s.speak("5 €") {
+            Err(Error::MissingGlyph('€')) => {}
+            _ => panic!("expected missing glyph"),
+        }
+        assert!(s.clone().name('€', "euro").speak("5 €").is_ok());
+        assert!(s.clone().name('€', "# euro").speak("5 €").is_ok());
+        assert!(s.clone().name('#', "#").speak("#").is_err());
+        let cycle = s.clone().name('#', "@").name('@', "number #");
+        assert!(cycle.speak("a # b").is_err());
+        assert!(cycle.spell('@').is_err());
+        assert!(cycle.voice_pack(&['#']).is_err());
+
+        let fast = s.clone().speed(2.0).speak("hello").unwrap();
+        assert!(fast.len() < s.speak("hello").unwrap().len() * 2 / 3);
+        assert_ne!(
+            s.clone().pitch(200.0).speak("hello").unwrap(),
+            s.speak("hello").unwrap()
+        );
+        assert!(s.clone().pitch(10.0).speak("hello").is_err());
+        assert!(s.clone().timbre(2.0).validate().is_err());
+    }
+
+    #[test]
+    fn words() {
+        assert!(Synth::new().word("hola", "OW1 L AA").is_ok());
+        assert!(Synth::new().word("hola", "QQ").is_err());
+        assert!(Synth::new().word("hola", "").is_err());
+        let s = Synth::new().word("Hola", "OW1 L AA").unwrap();
+        assert_ne!(
+            s.speak("hola").unwrap(),
+            Synth::new().speak("hola").unwrap()
+        );
+    }
+
+    #[test]
+    fn custom_chars() {
+        let s = Synth::new();
+        for &c in &['Ä', 'é', 'ñ', '#', '@', 'Z'] {
+            assert!(s.spell(c).unwrap().len() <= 36000, "{}", c);
+        }
+        assert!(s.spell('Ж').is_err());
+        assert!(s.spell('\u{263a}').is_err());
+        let s = s.name('Ж', "zhe").prompt("intro", "escriba lo siguiente");
+        let p = s.voice_pack(&['Ж', 'x', 'x', '3']).unwrap();
+        assert_eq!(p.chars(), vec!['3', 'x', 'Ж']);
+        assert_eq!(p.sample_rate(), 22050);
+        assert!(p.prompt("intro").is_some());
+    }
+
+    #[test]
+    fn vary() {
+        let s = Synth::new();
+        let a = s.vary(&mut StdRng::seed_from_u64(1));
+        assert_eq!(a, s.vary(&mut StdRng::seed_from_u64(1)));
+        assert_ne!(a, s.vary(&mut StdRng::seed_from_u64(2)));
+        assert_ne!(a.spell('x').unwrap(), s.spell('x').unwrap());
+        for i in 0..100 {
+            let v = Synth::new()
+                .pitch(390.0)
+                .vary(&mut StdRng::seed_from_u64(i));
+            v.validate().unwrap();
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use base64::decode;
+use serde_json::{self, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use {Error, Result};
+
+/// The version of the voice pack format which is written by `gen/audio/gen.py`.
+///
+/// Format 2 added the prompts. Packs of format 1 are still accepted.
+pub const VOICE_PACK_FORMAT: u64 = 2;
+
+struct Clips {
+    name: String,
+    sample_rate: u32,
+    clips: HashMap<char, Vec<i16>>,
+    prompts: HashMap<String, Vec<i16>>,
+}
+
+/// Spoken clips for the characters of a CAPTCHA.
+///
+/// A voice pack is a JSON object of the following form:
+///
+/// ```text
+/// {
+///   "format": 2,
+///   "name": "en-formant",
+///   "sample_rate": 22050,
+///   "clips": { "A": "<base64 encoded WAV file>", ... },
+///   "prompts": { "intro": "<base64 encoded WAV file>" }
+/// }
+/// ```
+///
+/// The prompts are optional. The prompt `intro` is spoken before the characters of an audio
+/// challenge if requested (see [`AudioOptions`](struct.AudioOptions.html)).
+///
+/// Each clip and each prompt has to be a mono WAV file with 16 bit samples and the sample rate
+/// of the pack. The pack is validated completely when it is loaded, so a broken pack is
+/// detected before the first CAPTCHA is created.
+///
+/// The embedded voice pack is created by `gen/audio/gen.py`.
+#[derive(Clone)]
+pub struct VoicePack {
+    clips: Arc<Clips>,
+}
+
+static BUILTIN: OnceLock<::std::result::Result<VoicePack, String>> = OnceLock::new();
+
+impl VoicePack {
+    /// Returns the embedded voice pack.
+    ///
+    /// The pack is decoded and validated once, the first time this function is called.
+    pub fn builtin() -> Result<VoicePack> {
+        let pack = BUILTIN.get_or_init(|| {
+            VoicePack::from_json(include_str!("audio.json")).map_err(|e| e.to_string())
+        });
+        match *pack {
+            Ok(ref p) => Ok(p.clone()),
+            Err(ref e) => Err(Error::Encoding(e.clone())),
+        }
+    }
+
+    /// Loads a voice pack from a JSON file.
+    pub fn from_file(path: &Path) -> Result<VoicePack> {
+        VoicePack::from_json(&fs::read_to_string(path)?)
+    }
+
+    /// Parses and validates a voice pack.
+    pub fn from_json(json: &str) -> Result<VoicePack> {
+        let bad = |msg: String| Error::Encoding(format!("invalid voice pack: {}", msg));
+        let v: Value = serde_json::from_str(json).map_err(|e| bad(e.to_string()))?;
+
+        let format = match v["format"].as_u64() {
+            Some(f) if (1..=VOICE_PACK_FORMAT).contains(&f) => f,
+            Some(f) => return Err(bad(format!("unsupported format {}", f))),
+            None => return Err(bad("missing format".to_string())),
+        };
+        let sample_rate = match v["sample_rate"].as_u64() {
+            Some(r) if r > 0 && r <= u32::MAX as u64 => r as u32,
+            _ => return Err(bad("missing or invalid sample_rate".to_string())),
+        };
+        let name = v["name"].as_str().unwrap_or("");
+        let entries = v["clips"]
+            .as_object()
+            .ok_or_else(|| bad("missing clips".to_string()))?;
+
+        let decode_clip = |k: &str, data: &Value| {
+            let data = data
+                .as_str()
+                .ok_or_else(|| bad(format!("clip {:?} is not a string", k)))?;
+            let wav = decode(data).map_err(|e| bad(format!("clip {:?}: {}", k, e)))?;
+            read_clip(&wav, sample_rate).map_err(|e| bad(format!("clip {:?}: {}", k, e)))
+        };
+
+        let mut clips = HashMap::new();
+        for (k, data) in entries {
+            let mut it = k.chars();
+            let c = match (it.next(), it.next()) {
+                (Some(c), None) => c,
+                _ => return Err(bad(format!("clip {:?} is not a single character", k))),
+            };
+            clips.insert(c, decode_clip(k, data)?);
+        }
+
+        let mut prompts = HashMap::new();
+        match v["prompts"] {
+            Value::Null => {}
+            Value::Object(_) if format < 2 => {
+                return Err(bad("prompts require format 2".to_string()))
+            }
+            Value::Object(ref m) => {
+                for (k, data) in m {
+                    prompts.insert(k.clone(), decode_clip(k, data)?);
+                }
+            }
+            _ => return Err(bad("prompts is not an object".to_string())),
+        }
+
+        VoicePack::new(name, sample_rate, clips, prompts)
+    }
+
+    /// Creates a voice pack from samples, e.g. from the clips of a
+    /// [`Synth`](struct.Synth.html).
+    ///
+    /// Returns an error if the sample rate is 0, if there are no clips or if a clip or a prompt
+    /// is empty.
+    pub fn new(
+        name: &str,
+        sample_rate: u32,
+        clips: HashMap<char, Vec<i16>>,
+        prompts: HashMap<String, Vec<i16>>,
+    ) -> Result<VoicePack> {
+        let bad = |msg: String| Error::Encoding(format!("invalid voice pack: {}", msg));
+        if sample_rate == 0 {
+            return Err(bad("invalid sample_rate".to_string()));
+        }
+        if clips.is_empty() {
+            return Err(bad("no clips".to_string()));
+        }
+        if let Some(c) = clips.iter().find(|x| x.1.is_empty()) {
+            return Err(bad(format!("clip {:?}: empty clip", c.0)));
+        }
+        if let Some(p) = prompts.iter().find(|x| x.1.is_empty()) {
+            return Err(bad(format!("prompt {:?}: empty clip", p.0)));
+        }
+        Ok(VoicePack {
+            clips: Arc::new(Clips {
+                name: name.to_string(),
+                sample_rate,
+                clips,
+                prompts,
+            }),
+        })
+    }
+
+    /// Returns the name of the voice pack.
+    pub fn name(&self) -> &str {
+        &self.clips.name
+    }
+
+    /// Returns the sample rate of the clips.
+    pub fn sample_rate(&self) -> u32 {
+        self.clips.sample_rate
+    }
+
+    /// Returns the characters for which the pack has a clip in ascending order.
+    pub fn chars(&self) -> Vec<char> {
+        let mut v: Vec<char> = self.clips.clips.keys().cloned().collect();
+        v.sort();
+        v
+    }
+
+    /// Returns the samples of the clip for `c`.
+    pub fn clip(&self, c: char) -> Option<&[i16]> {
+        self.clips.clips.get(&c).map(|v| &v[..])
+    }
+
+    /// Returns the samples of the prompt with the given name, e.g. `intro`.
+    pub fn prompt(&self, name: &str) -> Option<&[i16]> {
+        self.clips.prompts.get(name).map(|v| &v[..])
+    }
+}
+
+fn read_clip(wav: &[u8], sample_rate: u32) -> ::std::result::Result<Vec<i16>, String> {
+    let reader = hound::WavReader::new(wav).map_err(|e| e.to_string())?;
+    let spec = reader.spec();
+    if spec.channels != 1
+        || spec.bits_per_sample != 16
+        || spec.sample_format != hound::SampleFormat::Int
+    {
+        return Err("expected a mono WAV file with 16 bit samples".to_string());
+    }
+    if spec.sample_rate != sample_rate {
+        return Err(format!(
+            "sample rate {} differs from the pack ({})",
+            spec.sample_rate, sample_rate
+        ));
+    }
+    let samples = reader
+        .into_samples::<i16>()
+        .collect::<::std::result::Result<Vec<i16>, _>>()
+        .map_err(|e| e.to_string())?;
+    if samples.is_empty() {
+        return Err("empty clip".to_string());
+    }
+    Ok(samples)
+}
@@ -0,0 +1,56 @@
+use rand::{Rng, RngCore};
+
+use audio_filters::{AudioFilter, Sound};
+use {Error, Result, VoicePack};
+
+/// Mixes background speech into the audio.
+///
+/// Each voice is a stream of random clips of the voice pack which runs for the whole length of
+/// the audio. The clips are played backwards, so the background sounds like speech but cannot
+/// be mistaken for characters of the CAPTCHA.
+pub struct Babble {
+    voices: u32,
+    level: f32,
+}
+
+impl Babble {
+    /// Creates `voices` streams of background speech. `level` is the volume of each stream
+    /// relative to the foreground.
+    pub fn new(voices: u32, level: f32) -> Babble {
+        Babble { voices, level }
+    }
+}
+
+impl AudioFilter for Babble {
+    fn apply(&self, s: &mut Sound, voice: &VoicePack, rng: &mut dyn RngCore) {
+        let chars = voice.chars();
+        let rate_ok = voice.sample_rate() == s.sample_rate();
+        if chars.is_empty() || !rate_ok {
+            return;
+        }
+        let max_gap = s.ms(200.0);
+        let n = s.len() as i64;
+        let samples = s.samples_mut();
+        for _ in 0..self.voices {
+            let mut pos = -rng.random_range(0..n.max(1));
+            while pos < n {
+                let c = chars[rng.random_range(0..chars.len())];
+                let clip = voice.clip(c).unwrap_or(&[]);
+                for (i, &x) in clip.iter().rev().enumerate() {
+                    let j = pos + i as i64;
+                    if j >= 0 && j < n {
+                        samples[j as usize] += x as f32 / 32768.0 * self.level;
+                    }
+                }
+                pos += clip.len() as i64 + rng.random_range(0..=max_gap) as i64;
+            }
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !(self.level >= 0.0 && self.level <= 1.0) {
+            return Err(Error::invalid("the level of the babble must be in 0..=1"));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+use rand::RngCore;
+
+use audio_filters::{AudioFilter, Biquad, Sound};
+use {Error, Result, VoicePack};
+
+/// Removes frequencies above the cutoff frequency.
+pub struct LowPass {
+    cutoff: f32,
+}
+
+impl LowPass {
+    /// Creates a filter with the given cutoff frequency in Hz.
+    pub fn new(cutoff: f32) -> LowPass {
+        LowPass { cutoff }
+    }
+}
+
+impl AudioFilter for LowPass {
+    fn apply(&self, s: &mut Sound, _voice: &VoicePack, _rng: &mut dyn RngCore) {
+        let f = Biquad::low_pass(self.cutoff, s.sample_rate());
+        f.process(s.samples_mut());
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !(self.cutoff.is_finite() && self.cutoff > 0.0) {
+            return Err(Error::invalid(
+                "the cutoff frequency must be positive and finite",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Removes frequencies outside of a band, like a telephone line.
+pub struct BandPass {
+    low: f32,
+    high: f32,
+}
+
+impl BandPass {
+    /// Creates a filter which keeps the frequencies from `low` to `high` Hz.
+    pub fn new(low: f32, high: f32) -> BandPass {
+        BandPass { low, high }
+    }
+}
+
+impl AudioFilter for BandPass {
+    fn apply(&self, s: &mut Sound, _voice: &VoicePack, _rng: &mut dyn RngCore) {
+        let rate = s.sample_rate();
+        Biquad::high_pass(self.low, rate).process(s.samples_mut());
+        Biquad::low_pass(self.high, rate).process(s.samples_mut());
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !(self.low > 0.0 && self.low < self.high && self.high.is_finite()) {
+            return Err(Error::invalid(
+                "the band must have a positive lower frequency below a finite upper frequency",
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+use rand::{Rng, RngCore};
+
+use audio_filters::{AudioFilter, Sound};
+use {Error, Result, VoicePack};
+
+/// Changes the volume over time.
+///
+/// Every 150 to 400 milliseconds a random gain is chosen. The gain is interpolated linearly
+/// between these points.
+pub struct GainEnvelope {
+    min_db: f32,
+    max_db: f32,
+}
+
+impl GainEnvelope {
+    /// The gains are chosen from `min_db..=max_db` decibels.
+    pub fn new(min_db: f32, max_db: f32) -> GainEnvelope {
+        GainEnvelope { min_db, max_db }
+    }
+}
+
+impl AudioFilter for GainEnvelope {
+    fn apply(&self, s: &mut Sound, _voice: &VoicePack, rng: &mut dyn RngCore) {
+        let (min, max) = (s.ms(150.0).max(1), s.ms(400.0).max(1));
+        let gain = |rng: &mut dyn RngCore| {
+            let db: f32 = rng.random_range(self.min_db..=self.max_db);
+            10f32.powf(db / 20.0)
+        };
+        let mut start = 0;
+        let mut from = gain(rng);
+        let samples = s.samples_mut();
+        while start < samples.len() {
+            let n = rng.random_range(min..=max);
+            let to = gain(rng);
+            for (i, x) in samples.iter_mut().skip(start).take(n).enumerate() {
+                *x *= from + (to - from) * i as f32 / n as f32;
+            }
+            start += n;
+            from = to;
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !(self.min_db.is_finite() && self.max_db.is_finite() && self.min_db <= self.max_db) {
+            return Err(Error::invalid(
+                "the gains must be finite and the minimum must not exceed the maximum",
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,419 @@
+//! Filters to disturb and transform audio CAPTCHAs.
+//!
+//! Audio filters are the counterpart of the [image filters](../filters/index.html). They are
+//! added to a CAPTCHA with
+//! [`add_audio_filter`](../struct.RngCaptcha.html#method.add_audio_filter) and applied to every
+//! audio which is created by [`as_wav`](../struct.RngCaptcha.html#method.as_wav) and
+//! [`as_audio`](../struct.RngCaptcha.html#method.as_audio), in the order in which they have
+//! been added. Afterwards white noise is added as before.
+//!
+//! [`preset`](fn.preset.html) returns the filters for a difficulty. The CAPTCHAs created by
+//! [`generate`](../fn.generate.html) and [`by_name`](../fn.by_name.html) use the preset of
+//! their difficulty.
+//!
+//! This module requires the feature `audio`.
+//!
+//! # Examples
+//!
+//! ```
+//! # extern crate captcha;
+//! use captcha::audio_filters::{LowPass, PitchShift, Reverb};
+//! use captcha::Captcha;
+//!
+//! # fn main() {
+//! let mut c = Captcha::new();
+//! c.add_chars(5)
+//!     .add_audio_filter(PitchShift::new(-2.0, 2.0))
+//!     .add_audio_filter(LowPass::new(4000.0))
+//!     .add_audio_filter(Reverb::new(0.5, 0.3));
+//! let wav = c.as_audio().expect("could not create audio");
+//! # }
+//! ```
+
+mod babble;
+mod band;
+mod gain;
+mod pitch;
+mod reverb;
+mod stretch;
+
+use rand::RngCore;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use {Difficulty, Result, VoicePack};
+
+// reexports
+pub use audio_filters::babble::Babble;
+pub use audio_filters::band::{BandPass, LowPass};
+pub use audio_filters::gain::GainEnvelope;
+pub use audio_filters::pitch::PitchShift;
+pub use audio_filters::reverb::Reverb;
+pub use audio_filters::stretch::TimeStretch;
+
+/// Mono audio samples in the range `-1.0..=1.0`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sound {
+    samples: Vec<f32>,
+    sample_rate: u32,
+}
+
+impl Sound {
+    pub fn new(samples: Vec<f32>, sample_rate: u32) -> Sound {
+        Sound {
+            samples,
+            sample_rate,
+        }
+    }
+
+    /// Converts 16 bit samples.
+    pub fn from_i16(samples: &[i16], sample_rate: u32) -> Sound {
+        Sound::new(
+            samples.iter().map(|&s| s as f32 / 32768.0).collect(),
+            sample_rate,
+        )
+    }
+
+    /// Returns the samples as 16 bit samples. Samples outside of the valid range are clipped.
+    pub fn to_i16(&self) -> Vec<i16> {
+        self.samples
+            .iter()
+            .map(|&s| (s * 32768.0).round().clamp(-32768.0, 32767.0) as i16)
+            .collect()
+    }
+
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    pub fn samples_mut(&mut self) -> &mut Vec<f32> {
+        &mut self.samples
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Returns the number of samples for the given number of milliseconds.
+    pub fn ms(&self, ms: f32) -> usize {
+        (ms * self.sample_rate as f32 / 1000.0).max(0.0) as usize
+    }
+}
+
+pub trait AudioFilter {
+    /// Applies the filter to `s` using `rng` as the source of randomness.
+    ///
+    /// `voice` is the voice pack from which the audio has been created. Filters which mix in
+    /// speech, like [`Babble`](struct.Babble.html), take the clips from this pack.
+    fn apply(&self, s: &mut Sound, voice: &VoicePack, rng: &mut dyn RngCore);
+
+    /// Checks whether the parameters of the filter are valid.
+    ///
+    /// This method is called by
+    /// [`Captcha::try_add_audio_filter`](../struct.RngCaptcha.html#method.try_add_audio_filter).
+    /// Filters which would panic for some parameters should override this method and return
+    /// [`Error::InvalidParameter`](../enum.Error.html). The default implementation accepts all
+    /// parameters.
+    fn validate(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// An audio filter which can be shared between threads.
+pub type SharedAudioFilter = Arc<dyn AudioFilter + Send + Sync>;
+
+/// Returns the audio filters for the given difficulty.
+///
+/// * `Easy`: small changes of the pitch and of the volume.
+/// * `Medium`: additionally changes of the speed, quiet background babble, a band-pass filter
+///   and a little reverb.
+/// * `Hard`: stronger versions of all filters of `Medium`.
+pub fn preset(d: Difficulty) -> Vec<SharedAudioFilter> {
+    match d {
+        Difficulty::Easy => vec![
+            Arc::new(PitchShift::new(-1.0, 1.0)),
+            Arc::new(GainEnvelope::new(-4.0, 0.0)),
+        ],
+        Difficulty::Medium => vec![
+            Arc::new(PitchShift::new(-2.0, 2.0)),
+            Arc::new(TimeStretch::new(0.9, 1.1)),
+            Arc::new(GainEnvelope::new(-8.0, 0.0)),
+            Arc::new(Babble::new(1, 0.15)),
+            Arc::new(BandPass::new(200.0, 5000.0)),
+            Arc::new(Reverb::new(0.3, 0.15)),
+        ],
+        Difficulty::Hard => vec![
+            Arc::new(PitchShift::new(-3.0, 3.0)),
+            Arc::new(TimeStretch::new(0.8, 1.2)),
+            Arc::new(GainEnvelope::new(-12.0, 0.0)),
+            Arc::new(Babble::new(3, 0.25)),
+            Arc::new(BandPass::new(300.0, 3500.0)),
+            Arc::new(Reverb::new(0.5, 0.3)),
+        ],
+    }
+}
+
+/// Changes the number of samples by `factor` with linear interpolation. The pitch changes by
+/// `1 / factor`.
+pub(crate) fn resample(x: &[f32], factor: f32) -> Vec<f32> {
+    let n = (x.len() as f32 * factor).round() as usize;
+    if x.is_empty() || n == 0 {
+        return vec![];
+    }
+    (0..n)
+        .map(|i| {
+            let p = i as f32 / factor;
+            let j = p as usize;
+            let f = p - j as f32;
+            let a = x[j.min(x.len() - 1)];
+            let b = x[(j + 1).min(x.len() - 1)];
+            a + (b - a) * f
+        })
+        .collect()
+}
+
+/// Changes the number of samples by `factor` without changing the pitch (overlap-add with
+/// frames of `frame` samples).
+fn stretch(x: &[f32], factor: f32, frame: usize) -> Vec<f32> {
+    let n = (x.len() as f32 * factor).round() as usize;
+    let frame = frame.max(4);
+    let hop = frame / 2;
+    let window: Vec<f32> = (0..frame)
+        .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / frame as f32).cos())
+        .collect();
+    let mut out = vec![0.0; n + frame];
+    let mut weight = vec![0.0; n + frame];
+    let mut k = 0;
+    while k * hop < n {
+        let src = (k as f32 * hop as f32 / factor) as usize;
+        for i in 0..frame {
+            let s = x.get(src + i).cloned().unwrap_or(0.0);
+            out[k * hop + i] += s * window[i];
+            weight[k * hop + i] += window[i];
+        }
+        k += 1;
+    }
+    out.truncate(n);
+    for (o, w) in out.iter_mut().zip(weight) {
+        if w > 1e-3 {
+            *o /= w;
+        }
+    }
+    out
+}
+
+/// A second order IIR filter.
+pub(crate) struct Biquad {
+    b: [f32; 3],
+    a: [f32; 2],
+}
+
+impl Biquad {
+    /// Low-pass filter with the given cutoff frequency.
+    pub(crate) fn low_pass(cutoff: f32, sample_rate: u32) -> Biquad {
+        let (cos, alpha) = Biquad::params(cutoff, sample_rate);
+        Biquad::new(
+            [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    /// High-pass filter with the given cutoff frequency.
+    fn high_pass(cutoff: f32, sample_rate: u32) -> Biquad {
+        let (cos, alpha) = Biquad::params(cutoff, sample_rate);
+        Biquad::new(
+            [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    fn params(cutoff: f32, sample_rate: u32) -> (f32, f32) {
+        // the cutoff has to be below the Nyquist frequency
+        let f = cutoff.max(1.0).min(sample_rate as f32 * 0.45);
+        let w = 2.0 * PI * f / sample_rate as f32;
+        (w.cos(), w.sin() / (2.0 * ::std::f32::consts::FRAC_1_SQRT_2))
+    }
+
+    fn new(b: [f32; 3], a: [f32; 3]) -> Biquad {
+        Biquad {
+            b: [b[0] / a[0], b[1] / a[0], b[2] / a[0]],
+            a: [a[1] / a[0], a[2] / a[0]],
+        }
+    }
+
+    pub(crate) fn process(&self, x: &mut [f32]) {
+        let (mut x1, mut x2, mut y1, mut y2) = (0.0, 0.0, 0.0, 0.0);
+        for s in x.iter_mut() {
+            let y =
+                self.b[0] * *s + self.b[1] * x1 + self.b[2] * x2 - self.a[0] * y1 - self.a[1] * y2;
+            x2 = x1;
+            x1 = *s;
+            y2 = y1;
+            y1 = y;
+            *s = y;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use audio_filters::{
+        preset, resample, stretch, AudioFilter, Babble, BandPass, GainEnvelope, LowPass,
+        PitchShift, Reverb, Sound, TimeStretch,
+    };
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::f32::consts::PI;
+    use {Difficulty, VoicePack};
+
+    fn sine(freq: f32, n: usize) -> Sound {
+        let v = (0..n)
+            .map(|i| 0.5 * (2.0 * PI * freq * i as f32 / 22050.0).sin())
+            .collect();
+        Sound::new(v, 22050)
+    }
+
+    fn energy(s: &[f32]) -> f32 {
+        s.iter().map(|x| x * x).sum::<f32>() / s.len() as f32
+    }
+
+    /// Counts the zero crossings, which are proportional to the frequency of a sine.
+    fn crossings(s: &[f32]) -> usize {
+        s.windows(2)
+            .filter(|w| (w[0] < 0.0) != (w[1] < 0.0))
+            .count()
+    }
+
+    fn apply<F: AudioFilter>(f: F, s: &mut Sound, seed: u64) {
+        f.validate().unwrap();
+        let voice = VoicePack::builtin().unwrap();
+        f.apply(s, &voice, &mut StdRng::seed_from_u64(seed));
+    }
+
+    #[test]
+    fn conversion() {
+        let s = Sound::from_i16(&[0, 16384, -32768, 32767], 8000);
+        assert_eq!(s.samples()[1], 0.5);
+        assert_eq!(s.to_i16(), vec![0, 16384, -32768, 32767]);
+        assert_eq!(
+            Sound::new(vec![2.0, -2.0], 8000).to_i16(),
+            vec![32767, -32768]
+        );
+        assert_eq!(s.ms(500.0), 4000);
+    }
+
+    #[test]
+    fn resample_and_stretch() {
+        let s = sine(440.0, 22050);
+        let r = resample(s.samples(), 0.5);
+        assert_eq!(r.len(), 11025);
+        let c = crossings(&r) as f32 / crossings(s.samples()) as f32;
+        assert!((c - 1.0).abs() < 0.05);
+
+        let t = stretch(s.samples(), 1.5, 882);
+        assert_eq!(t.len(), 33075);
+        // the frequency is unchanged, so the number of crossings grows with the length
+        let c = crossings(&t) as f32 / crossings(s.samples()) as f32;
+        assert!((c - 1.5).abs() < 0.1);
+    }
+
+    #[test]
+    fn pitch_shift() {
+        let mut s = sine(440.0, 22050);
+        apply(PitchShift::new(12.0, 12.0), &mut s, 0);
+        assert!((s.len() as i64 - 22050).abs() < 10);
+        let c = crossings(s.samples()) as f32 / crossings(sine(440.0, 22050).samples()) as f32;
+        assert!((c - 2.0).abs() < 0.1, "{}", c);
+        assert!(PitchShift::new(2.0, 1.0).validate().is_err());
+    }
+
+    #[test]
+    fn time_stretch() {
+        let mut s = sine(440.0, 22050);
+        apply(TimeStretch::new(0.8, 0.8), &mut s, 0);
+        assert_eq!(s.len(), 17640);
+        assert!(TimeStretch::new(0.0, 1.0).validate().is_err());
+    }
+
+    #[test]
+    fn gain_envelope() {
+        let mut s = sine(440.0, 22050);
+        apply(GainEnvelope::new(-20.0, -20.0), &mut s, 0);
+        let e = energy(s.samples()) / energy(sine(440.0, 22050).samples());
+        assert!((e - 0.01).abs() < 0.001);
+        assert!(GainEnvelope::new(0.0, -1.0).validate().is_err());
+        assert!(GainEnvelope::new(f32::NEG_INFINITY, 0.0)
+            .validate()
+            .is_err());
+        assert!(GainEnvelope::new(0.0, f32::INFINITY).validate().is_err());
+        assert!(GainEnvelope::new(f32::NAN, 0.0).validate().is_err());
+    }
+
+    #[test]
+    fn filters() {
+        let low = |f: f32| {
+            let mut s = sine(f, 22050);
+            apply(LowPass::new(1000.0), &mut s, 0);
+            energy(&s.samples()[1000..])
+        };
+        assert!(low(200.0) > 0.1);
+        assert!(low(8000.0) < 0.01);
+
+        let band = |f: f32| {
+            let mut s = sine(f, 22050);
+            apply(BandPass::new(500.0, 2000.0), &mut s, 0);
+            energy(&s.samples()[1000..])
+        };
+        assert!(band(50.0) < 0.01);
+        assert!(band(1000.0) > 0.08);
+        assert!(band(9000.0) < 0.01);
+        assert!(BandPass::new(2000.0, 500.0).validate().is_err());
+        assert!(BandPass::new(500.0, f32::INFINITY).validate().is_err());
+        assert!(LowPass::new(0.0).validate().is_err());
+        assert!(LowPass::new(f32::INFINITY).validate().is_err());
+        assert!(LowPass::new(f32::NAN).validate().is_err());
+    }
+
+    #[test]
+    fn reverb() {
+        let mut s = Sound::new(vec![0.0; 22050], 22050);
+        s.samples_mut()[0] = 1.0;
+        apply(Reverb::new(0.5, 0.5), &mut s, 0);
+        assert!(energy(&s.samples()[2000..]) > 0.0);
+        assert!(Reverb::new(1.0, 0.5).validate().is_err());
+    }
+
+    #[test]
+    fn babble() {
+        let mut s = Sound::new(vec![0.0; 44100], 22050);
+        apply(Babble::new(2, 0.5), &mut s, 1);
+        assert!(energy(s.samples()) > 0.0);
+        let mut t = Sound::new(vec![0.0; 44100], 22050);
+        apply(Babble::new(2, 0.5), &mut t, 1);
+        assert_eq!(s, t);
+        assert!(Babble::new(1, -1.0).validate().is_err());
+    }
+
+    #[test]
+    fn presets() {
+        let voice = VoicePack::builtin().unwrap();
+        for d in &[Difficulty::Easy, Difficulty::Medium, Difficulty::Hard] {
+            let mut s = Sound::from_i16(voice.clip('A').unwrap(), voice.sample_rate());
+            let mut rng = StdRng::seed_from_u64(3);
+            for f in preset(*d) {
+                f.validate().unwrap();
+                f.apply(&mut s, &voice, &mut rng);
+            }
+            assert!(!s.is_empty());
+            assert!(s.samples().iter().all(|x| x.is_finite()));
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use rand::{Rng, RngCore};
+
+use audio_filters::{resample, stretch, AudioFilter, Sound};
+use {Error, Result, VoicePack};
+
+/// Shifts the pitch by a random number of semitones without changing the length of the audio.
+pub struct PitchShift {
+    min: f32,
+    max: f32,
+}
+
+impl PitchShift {
+    /// The shift is chosen from `min..=max` semitones. Negative values lower the pitch.
+    pub fn new(min: f32, max: f32) -> PitchShift {
+        PitchShift { min, max }
+    }
+}
+
+impl AudioFilter for PitchShift {
+    fn apply(&self, s: &mut Sound, _voice: &VoicePack, rng: &mut dyn RngCore) {
+        let semitones: f32 = rng.random_range(self.min..=self.max);
+        let factor = 2f32.powf(semitones / 12.0);
+        let frame = s.ms(40.0);
+        let stretched = stretch(s.samples(), factor, frame);
+        *s.samples_mut() = resample(&stretched, 1.0 / factor);
+    }
+
+    fn validate(&self) -> Result<()> {
+        let in_range = |x: f32| (-24.0..=24.0).contains(&x);
+        if !in_range(self.min) || !in_range(self.max) || self.min > self.max {
+            return Err(Error::invalid(
+                "the pitch shift must be a range within -24 to 24 semitones",
+            ));
+        }
+        Ok(())
+    }
+}
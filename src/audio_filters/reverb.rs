@@ -0,0 +1,61 @@
+use rand::RngCore;
+
+use audio_filters::{AudioFilter, Sound};
+use {Error, Result, VoicePack};
+
+/// Adds the reverberation of a room (Schroeder reverberator).
+pub struct Reverb {
+    room: f32,
+    mix: f32,
+}
+
+impl Reverb {
+    /// `room` in `0..1` controls how long the reverberation lasts, `mix` in `0..=1` is the
+    /// share of the reverberation in the output.
+    pub fn new(room: f32, mix: f32) -> Reverb {
+        Reverb { room, mix }
+    }
+}
+
+/// Delays of the parallel comb filters and of the serial all-pass filters in milliseconds.
+const COMBS: [f32; 4] = [29.7, 37.1, 41.1, 43.7];
+const ALL_PASSES: [f32; 2] = [5.0, 1.7];
+
+impl AudioFilter for Reverb {
+    fn apply(&self, s: &mut Sound, _voice: &VoicePack, _rng: &mut dyn RngCore) {
+        let feedback = 0.7 + 0.28 * self.room;
+        let dry: Vec<f32> = s.samples().to_vec();
+        let mut wet = vec![0.0; dry.len()];
+        for &ms in COMBS.iter() {
+            let d = s.ms(ms).max(1);
+            let mut buf = vec![0.0; d];
+            for (i, x) in dry.iter().enumerate() {
+                let y = buf[i % d];
+                buf[i % d] = x + y * feedback;
+                wet[i] += y / COMBS.len() as f32;
+            }
+        }
+        for &ms in ALL_PASSES.iter() {
+            let d = s.ms(ms).max(1);
+            let mut buf = vec![0.0; d];
+            for (i, x) in wet.iter_mut().enumerate() {
+                let b = buf[i % d];
+                let y = -0.7 * *x + b;
+                buf[i % d] = *x + 0.7 * y;
+                *x = y;
+            }
+        }
+        for (o, (d, w)) in s.samples_mut().iter_mut().zip(dry.iter().zip(wet)) {
+            *o = d * (1.0 - self.mix) + w * self.mix;
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !(self.room >= 0.0 && self.room < 1.0 && self.mix >= 0.0 && self.mix <= 1.0) {
+            return Err(Error::invalid(
+                "the room size must be in 0..1 and the mix in 0..=1",
+            ));
+        }
+        Ok(())
+    }
+}
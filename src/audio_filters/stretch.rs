@@ -0,0 +1,35 @@
+use rand::{Rng, RngCore};
+
+use audio_filters::{stretch, AudioFilter, Sound};
+use {Error, Result, VoicePack};
+
+/// Changes the speed by a random factor without changing the pitch.
+pub struct TimeStretch {
+    min: f32,
+    max: f32,
+}
+
+impl TimeStretch {
+    /// The length of the audio is multiplied by a factor from `min..=max`, i.e. factors above
+    /// 1 slow the speech down.
+    pub fn new(min: f32, max: f32) -> TimeStretch {
+        TimeStretch { min, max }
+    }
+}
+
+impl AudioFilter for TimeStretch {
+    fn apply(&self, s: &mut Sound, _voice: &VoicePack, rng: &mut dyn RngCore) {
+        let factor = rng.random_range(self.min..=self.max);
+        let frame = s.ms(40.0);
+        *s.samples_mut() = stretch(s.samples(), factor, frame);
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !(self.min > 0.0 && self.min <= self.max && self.max <= 4.0) {
+            return Err(Error::invalid(
+                "the stretch factors must be a range within 0 (exclusive) and 4",
+            ));
+        }
+        Ok(())
+    }
+}
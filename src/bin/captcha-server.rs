@@ -0,0 +1,91 @@
+//! HTTP server which hands out CAPTCHAs and checks the answers.
+//!
+//! See the module `captcha::server` for the endpoints. This binary requires the feature `server`.
+
+extern crate captcha;
+
+use captcha::server::ChallengeServer;
+use captcha::{Difficulty, MemoryStore};
+use std::env;
+use std::process;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const USAGE: &str = "Usage: captcha-server [options]
+
+Options:
+  -a, --addr ADDR                        address to listen on (default: 127.0.0.1:8080)
+  -d, --difficulty easy|medium|hard      default difficulty (default: medium)
+  -t, --threads N                        number of threads (default: 4)
+      --ttl SECONDS                      time after which a challenge expires (default: 300)
+      --attempts N                       wrong answers per challenge (default: 3)
+";
+
+struct Args {
+    addr: String,
+    difficulty: Difficulty,
+    threads: usize,
+    ttl: u64,
+    attempts: u32,
+}
+
+fn parse<I: Iterator<Item = String>>(mut it: I) -> Result<Args, String> {
+    let mut a = Args {
+        addr: "127.0.0.1:8080".to_string(),
+        difficulty: Difficulty::Medium,
+        threads: 4,
+        ttl: 300,
+        attempts: 3,
+    };
+    while let Some(arg) = it.next() {
+        let mut value = || it.next().ok_or(format!("missing value for {}", arg));
+        match arg.as_str() {
+            "-a" | "--addr" => a.addr = value()?,
+            "-d" | "--difficulty" => {
+                a.difficulty = value()?.parse().map_err(|e| format!("{}", e))?
+            }
+            "-t" | "--threads" => a.threads = value()?.parse().map_err(|_| "invalid threads")?,
+            "--ttl" => a.ttl = value()?.parse().map_err(|_| "invalid ttl")?,
+            "--attempts" => a.attempts = value()?.parse().map_err(|_| "invalid attempts")?,
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                process::exit(0);
+            }
+            s => return Err(format!("unknown argument: {}", s)),
+        }
+    }
+    Ok(a)
+}
+
+fn main() {
+    let a = match parse(env::args().skip(1)) {
+        Ok(a) => a,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    let store = MemoryStore::new()
+        .ttl(Duration::from_secs(a.ttl))
+        .max_attempts(a.attempts);
+    let server = match ChallengeServer::bind(&a.addr) {
+        Ok(s) => s.store(Arc::new(store)).difficulty(a.difficulty),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        }
+    };
+    let server = Arc::new(server);
+    println!("listening on {}", a.addr);
+
+    let threads: Vec<_> = (0..a.threads.max(1))
+        .map(|_| {
+            let s = server.clone();
+            thread::spawn(move || s.run())
+        })
+        .collect();
+    for t in threads {
+        let _ = t.join();
+    }
+}
@@ -0,0 +1,294 @@
+//! Command line tool to create and verify CAPTCHAs.
+//!
+//! This binary requires the feature `cli`.
+
+extern crate captcha;
+extern crate rand;
+extern crate serde_json;
+
+#[cfg(feature = "truetype")]
+use captcha::fonts::TrueType;
+use captcha::fonts::{Custom, Default, Font};
+use captcha::{by_name_nth, generate_nth, Captcha, CaptchaName, Difficulty, Verifier};
+use rand::{rng, Rng};
+use serde_json::json;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process;
+
+const USAGE: &str = "Usage:
+  captcha generate [options] [-o FILE]   create one CAPTCHA
+  captcha batch [options] -n N -o DIR    create N CAPTCHAs and DIR/manifest.jsonl
+  captcha chars [--font PATH] [--size PX]
+                                         print the characters supported by a font
+  captcha verify [-i] [--confusables] [--] EXPECTED INPUT
+                                         exit with 0 if INPUT matches EXPECTED; arguments
+                                         after -- are never read as options
+
+Options:
+  -d, --difficulty easy|medium|hard      difficulty (default: medium)
+  -N, --name amelia|lucy|mila            predefined CAPTCHA (default: random)
+  -f, --format png|base64|json           output format of generate (default: png)
+  -s, --seed N                           seed of the random number generator
+  -o, --output PATH                      output file or directory (default for generate: stdout)
+  -i, --case-insensitive                 ignore upper and lower case
+      --confusables                      treat confusable characters like 0 and O as equal
+";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Png,
+    Base64,
+    Json,
+}
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    command: String,
+    difficulty: Difficulty,
+    name: Option<CaptchaName>,
+    format: Format,
+    seed: Option<u64>,
+    output: Option<String>,
+    count: usize,
+    font: Option<String>,
+    size: f32,
+    case_insensitive: bool,
+    confusables: bool,
+    positional: Vec<String>,
+}
+
+fn parse<I: Iterator<Item = String>>(mut it: I) -> Result<Args, String> {
+    let command = it.next().ok_or("missing command")?;
+    let mut a = Args {
+        command,
+        difficulty: Difficulty::Medium,
+        name: None,
+        format: Format::Png,
+        seed: None,
+        output: None,
+        count: 1,
+        font: None,
+        size: 40.0,
+        case_insensitive: false,
+        confusables: false,
+        positional: vec![],
+    };
+    while let Some(arg) = it.next() {
+        if arg == "--" {
+            a.positional.extend(it);
+            break;
+        }
+        let mut value = || it.next().ok_or(format!("missing value for {}", arg));
+        match arg.as_str() {
+            "-d" | "--difficulty" => {
+                a.difficulty = value()?.parse().map_err(|e| format!("{}", e))?
+            }
+            "-N" | "--name" => a.name = Some(value()?.parse().map_err(|e| format!("{}", e))?),
+            "-f" | "--format" => {
+                a.format = match value()?.as_str() {
+                    "png" => Format::Png,
+                    "base64" => Format::Base64,
+                    "json" => Format::Json,
+                    f => return Err(format!("unknown format: {}", f)),
+                }
+            }
+            "-s" | "--seed" => a.seed = Some(value()?.parse().map_err(|_| "invalid seed")?),
+            "-o" | "--output" => a.output = Some(value()?),
+            "-n" | "--count" => a.count = value()?.parse().map_err(|_| "invalid count")?,
+            "--font" => a.font = Some(value()?),
+            "--size" => a.size = value()?.parse().map_err(|_| "invalid size")?,
+            "-i" | "--case-insensitive" => a.case_insensitive = true,
+            "--confusables" => a.confusables = true,
+            s if s.starts_with('-') && s.len() > 1 => return Err(format!("unknown option: {}", s)),
+            _ => a.positional.push(arg),
+        }
+    }
+    Ok(a)
+}
+
+/// Returns the seed of the option `--seed` or a random seed.
+fn seed(a: &Args) -> u64 {
+    a.seed.unwrap_or_else(|| rng().random())
+}
+
+/// Creates the CAPTCHA with the given index. Without a name this is the CAPTCHA of
+/// `generate_iter(difficulty, seed)` at this index.
+fn create(a: &Args, seed: u64, i: u64) -> (CaptchaName, Captcha) {
+    match a.name {
+        Some(t) => (t, by_name_nth(a.difficulty, t, seed, i)),
+        None => generate_nth(a.difficulty, seed, i),
+    }
+}
+
+fn generate(a: &Args) -> Result<(), String> {
+    let (_, c) = create(a, seed(a), 0);
+    let data = match a.format {
+        Format::Png => c.try_as_png().map_err(|e| e.to_string())?,
+        Format::Base64 => {
+            let mut s = c.as_base64().ok_or("could not encode CAPTCHA")?;
+            s.push('\n');
+            s.into_bytes()
+        }
+        Format::Json => {
+            let png = c.as_base64().ok_or("could not encode CAPTCHA")?;
+            let v = json!({ "answer": c.chars_as_string(), "png": png });
+            format!("{}\n", v).into_bytes()
+        }
+    };
+    match a.output {
+        Some(ref o) => fs::write(o, data).map_err(|e| e.to_string()),
+        None => io::stdout().write_all(&data).map_err(|e| e.to_string()),
+    }
+}
+
+fn batch(a: &Args) -> Result<(), String> {
+    let dir = Path::new(a.output.as_ref().ok_or("missing output directory")?);
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let f = File::create(dir.join("manifest.jsonl")).map_err(|e| e.to_string())?;
+    let mut manifest = BufWriter::new(f);
+    let seed = seed(a);
+    for i in 0..a.count {
+        let (name, c) = create(a, seed, i as u64);
+        let file = format!("{:06}.png", i);
+        c.save(&dir.join(&file)).map_err(|e| e.to_string())?;
+        let v = json!({
+            "file": file,
+            "answer": c.chars_as_string(),
+            "difficulty": format!("{:?}", a.difficulty),
+            "name": format!("{:?}", name),
+        });
+        writeln!(manifest, "{}", v).map_err(|e| e.to_string())?;
+    }
+    manifest.flush().map_err(|e| e.to_string())
+}
+
+fn load_font(a: &Args) -> Result<Box<dyn Font>, String> {
+    let p = match a.font {
+        None => return Ok(Box::new(Default::new())),
+        Some(ref p) => Path::new(p),
+    };
+    if p.is_dir() {
+        return Custom::from_dir(p)
+            .map(|f| Box::new(f) as Box<dyn Font>)
+            .map_err(|e| e.to_string());
+    }
+    #[cfg(feature = "truetype")]
+    return TrueType::from_file(p, a.size)
+        .map(|f| Box::new(f) as Box<dyn Font>)
+        .map_err(|e| e.to_string());
+    #[cfg(not(feature = "truetype"))]
+    Err("TrueType fonts require the feature truetype".to_string())
+}
+
+fn chars(a: &Args) -> Result<(), String> {
+    let s: String = load_font(a)?.chars().into_iter().collect();
+    println!("{}", s);
+    Ok(())
+}
+
+fn verify(a: &Args) -> Result<bool, String> {
+    if a.positional.len() != 2 {
+        return Err("expected EXPECTED and INPUT".to_string());
+    }
+    let mut v = Verifier::new().case_insensitive(a.case_insensitive);
+    if a.confusables {
+        v = v.default_confusables().font(&Default::new());
+    }
+    Ok(v.verify(&a.positional[0], &a.positional[1]))
+}
+
+fn run(a: &Args) -> Result<bool, String> {
+    match a.command.as_str() {
+        "generate" => generate(a).map(|_| true),
+        "batch" => batch(a).map(|_| true),
+        "chars" => chars(a).map(|_| true),
+        "verify" => verify(a),
+        "help" | "-h" | "--help" => {
+            print!("{}", USAGE);
+            Ok(true)
+        }
+        c => Err(format!("unknown command: {}", c)),
+    }
+}
+
+fn main() {
+    let r = parse(env::args().skip(1)).and_then(|a| run(&a));
+    match r {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{create, generate, parse, verify, Format};
+    use captcha::{generate_batch, CaptchaName, Difficulty};
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_args() {
+        let a =
+            parse(args("generate -d hard --name Lucy -f json -s 3 -o x.json").into_iter()).unwrap();
+        assert_eq!(a.command, "generate");
+        assert_eq!(a.difficulty, Difficulty::Hard);
+        assert_eq!(a.name, Some(CaptchaName::Lucy));
+        assert_eq!(a.format, Format::Json);
+        assert_eq!(a.seed, Some(3));
+        assert_eq!(a.output, Some("x.json".to_string()));
+
+        assert!(parse(args("generate -d extreme").into_iter()).is_err());
+        assert!(parse(args("generate --bogus").into_iter()).is_err());
+        assert!(parse(args("generate -o").into_iter()).is_err());
+        assert!(parse(vec![].into_iter()).is_err());
+    }
+
+    #[test]
+    fn seeded() {
+        let a = parse(args("batch -s 9 -n 2").into_iter()).unwrap();
+        let (n1, c1) = create(&a, 9, 1);
+        let (n2, c2) = create(&a, 9, 1);
+        assert_eq!(n1, n2);
+        assert_eq!(c1.as_tuple(), c2.as_tuple());
+        let b = generate_batch(2, Difficulty::Medium, 9);
+        assert_eq!(c1.as_tuple(), b[1].as_tuple());
+
+        let a = parse(args("batch -s 9 -n 2 -N mila").into_iter()).unwrap();
+        assert_eq!(create(&a, 9, 1).0, CaptchaName::Mila);
+    }
+
+    #[test]
+    fn verify_args() {
+        assert!(verify(&parse(args("verify abc abc").into_iter()).unwrap()).unwrap());
+        assert!(!verify(&parse(args("verify abc ABC").into_iter()).unwrap()).unwrap());
+        assert!(verify(&parse(args("verify -i abc ABC").into_iter()).unwrap()).unwrap());
+        assert!(verify(&parse(args("verify abc").into_iter()).unwrap()).is_err());
+        assert!(parse(args("verify abc -abc").into_iter()).is_err());
+        let a = parse(args("verify -i -- -abc -ABC").into_iter()).unwrap();
+        assert_eq!(a.positional, vec!["-abc", "-ABC"]);
+        assert!(a.case_insensitive);
+        assert!(verify(&a).unwrap());
+    }
+
+    #[test]
+    fn png_without_extension() {
+        let p = env::temp_dir().join(format!("captcha_cli_{}", process::id()));
+        let o = p.to_str().unwrap();
+        generate(&parse(args(&format!("generate -s 1 -o {}", o)).into_iter()).unwrap()).unwrap();
+        let data = fs::read(&p).unwrap();
+        fs::remove_file(&p).unwrap();
+        assert_eq!(&data[1..4], b"PNG");
+    }
+}
@@ -0,0 +1,228 @@
+//! Builder for CAPTCHAs with a fixed size.
+//!
+//! # Examples
+//!
+//! ```
+//! # extern crate captcha;
+//! use captcha::filters::Noise;
+//! use captcha::CaptchaBuilder;
+//!
+//! # fn main() {
+//! let mut c = CaptchaBuilder::new(150, 50).chars(10).build();
+//! c.apply_filter(Noise::new(0.1)).as_png();
+//! # }
+//! ```
+
+use fonts::Font;
+use rand::{Rng, RngCore};
+use std::sync::Arc;
+use {default_rng, Captcha, Result, RngCaptcha, SharedFont};
+
+/// Creates CAPTCHAs with a fixed size into which the characters are fitted automatically.
+///
+/// In contrast to [`RngCaptcha::view`](struct.RngCaptcha.html#method.view), which clips the
+/// text if it is larger than the requested size, the builder scales the characters down until
+/// all of them fit into the image.
+pub struct CaptchaBuilder {
+    width: u32,
+    height: u32,
+    n: u32,
+    margin: u32,
+    font: Option<SharedFont>,
+    rotation: f32,
+    scale: (f32, f32),
+    jitter: u32,
+    spacing: i32,
+    mask: bool,
+}
+
+impl CaptchaBuilder {
+    /// Returns a builder for CAPTCHAs of the given width and height.
+    ///
+    /// By default a CAPTCHA has 5 characters and a margin of 4 pixels.
+    pub fn new(width: u32, height: u32) -> CaptchaBuilder {
+        CaptchaBuilder {
+            width,
+            height,
+            n: 5,
+            margin: 4,
+            font: None,
+            rotation: 0.0,
+            scale: (1.0, 1.0),
+            jitter: 0,
+            spacing: 0,
+            mask: false,
+        }
+    }
+
+    /// Sets the number of characters.
+    pub fn chars(self, n: u32) -> Self {
+        CaptchaBuilder { n, ..self }
+    }
+
+    /// Sets the minimum number of pixels between the text and the border of the image.
+    pub fn margin(self, margin: u32) -> Self {
+        CaptchaBuilder { margin, ..self }
+    }
+
+    /// Sets the font of the characters.
+    pub fn font<F: Font + Send + Sync + 'static>(self, f: F) -> Self {
+        self.shared_font(Arc::new(f))
+    }
+
+    /// Sets a font which is shared with other CAPTCHAs.
+    pub fn shared_font(self, f: SharedFont) -> Self {
+        CaptchaBuilder {
+            font: Some(f),
+            ..self
+        }
+    }
+
+    /// See [`RngCaptcha::set_rotation`](struct.RngCaptcha.html#method.set_rotation).
+    pub fn rotation(self, degrees: f32) -> Self {
+        CaptchaBuilder {
+            rotation: degrees,
+            ..self
+        }
+    }
+
+    /// See [`RngCaptcha::set_scale`](struct.RngCaptcha.html#method.set_scale).
+    pub fn scale(self, min: f32, max: f32) -> Self {
+        CaptchaBuilder {
+            scale: (min, max),
+            ..self
+        }
+    }
+
+    /// See [`RngCaptcha::set_jitter`](struct.RngCaptcha.html#method.set_jitter).
+    pub fn jitter(self, pixels: u32) -> Self {
+        CaptchaBuilder {
+            jitter: pixels,
+            ..self
+        }
+    }
+
+    /// See [`RngCaptcha::set_spacing`](struct.RngCaptcha.html#method.set_spacing).
+    pub fn spacing(self, pixels: i32) -> Self {
+        CaptchaBuilder {
+            spacing: pixels,
+            ..self
+        }
+    }
+
+    /// See [`RngCaptcha::set_mask`](struct.RngCaptcha.html#method.set_mask).
+    pub fn mask(self, enabled: bool) -> Self {
+        CaptchaBuilder {
+            mask: enabled,
+            ..self
+        }
+    }
+
+    /// Creates the CAPTCHA.
+    pub fn build(self) -> Captcha {
+        self.build_with_rng(default_rng())
+    }
+
+    /// Creates the CAPTCHA using `rng` as the source of randomness.
+    pub fn build_with_rng<T: Rng + RngCore>(self, rng: T) -> RngCaptcha<T> {
+        let (n, w, h, margin) = (self.n, self.width, self.height, self.margin);
+        let (rotation, scale) = (self.rotation, self.scale);
+        let mut c = self.empty_captcha(rng);
+        c.set_rotation(rotation).set_scale(scale.0, scale.1);
+        c.add_chars(n).fit(w, h, margin);
+        c
+    }
+
+    /// Creates the CAPTCHA.
+    ///
+    /// Returns an error if the rotation or the scale is invalid, if a character could not be
+    /// added or if the image without the margin is empty.
+    pub fn try_build(self) -> Result<Captcha> {
+        self.try_build_with_rng(default_rng())
+    }
+
+    /// Creates the CAPTCHA using `rng` as the source of randomness.
+    ///
+    /// See [`try_build`](#method.try_build).
+    pub fn try_build_with_rng<T: Rng + RngCore>(self, rng: T) -> Result<RngCaptcha<T>> {
+        let (n, w, h, margin) = (self.n, self.width, self.height, self.margin);
+        let (rotation, scale) = (self.rotation, self.scale);
+        let mut c = self.empty_captcha(rng);
+        c.try_set_rotation(rotation)?
+            .try_set_scale(scale.0, scale.1)?;
+        c.try_add_chars(n)?.try_fit(w, h, margin)?;
+        Ok(c)
+    }
+
+    fn empty_captcha<T: Rng + RngCore>(self, rng: T) -> RngCaptcha<T> {
+        let mut c = RngCaptcha::from_rng(rng);
+        if let Some(f) = self.font {
+            c.use_font_chars = f.chars();
+            c.font = f;
+        }
+        c.set_jitter(self.jitter)
+            .set_spacing(self.spacing)
+            .set_mask(self.mask);
+        c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use builder::CaptchaBuilder;
+    use images::Pixl;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn fits_text() {
+        for seed in 0..5 {
+            let c = CaptchaBuilder::new(150, 50)
+                .chars(10)
+                .margin(2)
+                .build_with_rng(StdRng::seed_from_u64(seed));
+            assert_eq!(c.chars().len(), 10);
+            assert_eq!((c.img.width(), c.img.height()), (150, 50));
+
+            // the border of the image must not contain any part of a character
+            let rows: Vec<Vec<Pixl>> = c.img.rows().map(|r| r.collect()).collect();
+            assert!(rows[0]
+                .iter()
+                .chain(rows[49].iter())
+                .all(|p| *p == Pixl::white()));
+            assert!(rows
+                .iter()
+                .all(|r| r[0] == Pixl::white() && r[149] == Pixl::white()));
+        }
+    }
+
+    #[test]
+    fn does_not_enlarge_text() {
+        let c = CaptchaBuilder::new(400, 200)
+            .chars(2)
+            .build_with_rng(StdRng::seed_from_u64(1));
+        let a = c.text_area();
+        assert!(a.right - a.left < 120);
+    }
+
+    #[test]
+    fn errors() {
+        let r = CaptchaBuilder::new(10, 10)
+            .margin(5)
+            .try_build_with_rng(StdRng::seed_from_u64(1));
+        assert!(r.is_err());
+        let c = CaptchaBuilder::new(150, 50)
+            .try_build_with_rng(StdRng::seed_from_u64(1))
+            .unwrap();
+        assert_eq!(c.chars().len(), 5);
+
+        let invalid = [
+            CaptchaBuilder::new(150, 50).rotation(f32::NAN),
+            CaptchaBuilder::new(150, 50).scale(1.0, 1e9),
+            CaptchaBuilder::new(150, 50).scale(1.2, 0.8),
+        ];
+        for b in invalid {
+            assert!(b.try_build_with_rng(StdRng::seed_from_u64(1)).is_err());
+        }
+    }
+}
@@ -0,0 +1,438 @@
+//! Labelled datasets of CAPTCHAs.
+//!
+//! A [`DatasetWriter`](struct.DatasetWriter.html) writes CAPTCHAs as PNG images into the
+//! directory `images` and annotates them with their text, the box of each character, the
+//! difficulty and the name of the predefined CAPTCHA. The annotations can be written in one of
+//! the following formats:
+//!
+//! * `JsonLines`: one JSON object per image in `labels.jsonl`.
+//! * `Yolo`: one text file per image in the directory `labels` with a line
+//!   `class x_center y_center width height` per character, where all values except the class
+//!   are relative to the size of the image. The classes are listed in `classes.txt`.
+//! * `Coco`: the file `annotations.json` in the COCO object detection format. The text, the
+//!   difficulty and the name are stored as additional fields of each image.
+//!
+//! The boxes of the characters are computed from the segmentation mask of a CAPTCHA (see
+//! [`set_mask`](../struct.RngCaptcha.html#method.set_mask)), so that they follow filters which
+//! move pixels, like [`Wave`](../filters/struct.Wave.html). The CAPTCHAs which are created by
+//! the writer have the mask enabled. For a CAPTCHA without a mask the boxes returned by
+//! [`char_areas`](../struct.RngCaptcha.html#method.char_areas) are used. Characters which have
+//! been cropped away completely are not annotated. The annotations of `JsonLines` and `Coco`
+//! contain the position of their character in the text (`index` and `char_index`).
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # extern crate captcha;
+//! use captcha::dataset::{DatasetWriter, Format};
+//! use captcha::{CaptchaName, Difficulty};
+//! use std::path::Path;
+//!
+//! # fn main() {
+//! let mut w = DatasetWriter::create(Path::new("dataset"), Format::Yolo).unwrap();
+//! for _ in 0..100 {
+//!     w.add(Difficulty::Medium, CaptchaName::Lucy).unwrap();
+//! }
+//! w.finish().unwrap();
+//! # }
+//! ```
+
+use rand::{Rng, RngCore};
+use serde_json::{json, Value};
+use std::cmp::{max, min};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use fonts::{Default, Font};
+use {
+    by_name_from, Captcha, CaptchaName, Difficulty, Error, Geometry, Result, RngCaptcha,
+    MASK_BACKGROUND,
+};
+
+/// The format of the annotations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    JsonLines,
+    Yolo,
+    Coco,
+}
+
+/// Writes CAPTCHAs and their annotations into a directory.
+///
+/// The annotations are complete only after [`finish`](#method.finish) has been called.
+pub struct DatasetWriter {
+    dir: PathBuf,
+    format: Format,
+    classes: Vec<char>,
+    n: usize,
+    jsonl: Option<BufWriter<File>>,
+    coco_images: Vec<Value>,
+    coco_annotations: Vec<Value>,
+}
+
+impl DatasetWriter {
+    /// Creates the directory `dir` if it does not exist and returns a writer for a dataset in
+    /// the given format.
+    ///
+    /// The classes are the characters of the [`Default`](../fonts/struct.Default.html) font.
+    pub fn create(dir: &Path, format: Format) -> Result<DatasetWriter> {
+        fs::create_dir_all(dir.join("images"))?;
+        let jsonl = match format {
+            Format::JsonLines => Some(BufWriter::new(File::create(dir.join("labels.jsonl"))?)),
+            Format::Yolo => {
+                fs::create_dir_all(dir.join("labels"))?;
+                None
+            }
+            Format::Coco => None,
+        };
+        Ok(DatasetWriter {
+            dir: dir.to_path_buf(),
+            format,
+            classes: Default::try_new()?.chars(),
+            n: 0,
+            jsonl,
+            coco_images: vec![],
+            coco_annotations: vec![],
+        })
+    }
+
+    /// Sets the characters which are used as classes for the formats `Yolo` and `Coco`.
+    ///
+    /// The class of a character is its position in `classes`. This is required if the
+    /// CAPTCHAs are created with another font.
+    pub fn classes(self, classes: &[char]) -> Self {
+        DatasetWriter {
+            classes: classes.to_vec(),
+            ..self
+        }
+    }
+
+    /// Returns the number of CAPTCHAs which have been written.
+    pub fn len(&self) -> usize {
+        self.n
+    }
+
+    /// Returns true if no CAPTCHA has been written.
+    pub fn is_empty(&self) -> bool {
+        self.n == 0
+    }
+
+    /// Creates a predefined CAPTCHA (see [`by_name`](../fn.by_name.html)) and adds it to the
+    /// dataset.
+    pub fn add(&mut self, d: Difficulty, t: CaptchaName) -> Result<()> {
+        let mut c = Captcha::new();
+        c.set_mask(true);
+        let c = by_name_from(d, t, c);
+        self.add_captcha(&c, Some((d, t)))
+    }
+
+    /// Creates a predefined CAPTCHA using `rng` as the source of randomness and adds it to the
+    /// dataset.
+    pub fn add_with_rng<T: Rng + RngCore>(
+        &mut self,
+        d: Difficulty,
+        t: CaptchaName,
+        rng: T,
+    ) -> Result<()> {
+        let mut c = RngCaptcha::from_rng(rng);
+        c.set_mask(true);
+        let c = by_name_from(d, t, c);
+        self.add_captcha(&c, Some((d, t)))
+    }
+
+    /// Adds a CAPTCHA to the dataset. If the CAPTCHA has been created by
+    /// [`by_name`](../fn.by_name.html) its difficulty and name can be given via `preset`.
+    pub fn add_captcha<T: Rng + RngCore>(
+        &mut self,
+        c: &RngCaptcha<T>,
+        preset: Option<(Difficulty, CaptchaName)>,
+    ) -> Result<()> {
+        let (text, png) = c.try_as_tuple()?;
+        let (w, h) = (c.width(), c.height());
+        let boxes = char_boxes(c);
+        let file = format!("images/{:06}.png", self.n);
+        let difficulty = preset.map(|p| format!("{:?}", p.0));
+        let name = preset.map(|p| format!("{:?}", p.1));
+        let classes = match self.format {
+            Format::JsonLines => vec![],
+            _ => boxes
+                .iter()
+                .map(|b| self.class(b.1))
+                .collect::<Result<Vec<usize>>>()?,
+        };
+
+        match self.format {
+            Format::JsonLines => {
+                let chars: Vec<Value> = boxes
+                    .iter()
+                    .map(|&(i, ch, ref g)| {
+                        json!({
+                            "index": i,
+                            "char": ch.to_string(),
+                            "box": [g.left, g.top, g.right, g.bottom],
+                        })
+                    })
+                    .collect();
+                let line = json!({
+                    "file": file,
+                    "text": text,
+                    "difficulty": difficulty,
+                    "name": name,
+                    "width": w,
+                    "height": h,
+                    "chars": chars,
+                });
+                let out = self.jsonl.as_mut().expect("labels.jsonl is open");
+                writeln!(out, "{}", line)?;
+            }
+            Format::Yolo => {
+                let mut s = String::new();
+                for ((_, _, g), class) in boxes.iter().zip(classes) {
+                    let (bw, bh) = (g.right - g.left + 1, g.bottom - g.top + 1);
+                    s += &format!(
+                        "{} {:.6} {:.6} {:.6} {:.6}\n",
+                        class,
+                        (g.left as f64 + bw as f64 / 2.0) / w as f64,
+                        (g.top as f64 + bh as f64 / 2.0) / h as f64,
+                        bw as f64 / w as f64,
+                        bh as f64 / h as f64
+                    );
+                }
+                fs::write(self.dir.join(format!("labels/{:06}.txt", self.n)), s)?;
+            }
+            Format::Coco => {
+                for ((i, _, g), class) in boxes.iter().zip(classes) {
+                    let (bw, bh) = (g.right - g.left + 1, g.bottom - g.top + 1);
+                    let a = json!({
+                        "id": self.coco_annotations.len() + 1,
+                        "image_id": self.n + 1,
+                        "category_id": class + 1,
+                        "char_index": i,
+                        "bbox": [g.left, g.top, bw, bh],
+                        "area": bw * bh,
+                        "iscrowd": 0,
+                    });
+                    self.coco_annotations.push(a);
+                }
+                self.coco_images.push(json!({
+                    "id": self.n + 1,
+                    "file_name": file,
+                    "width": w,
+                    "height": h,
+                    "text": text,
+                    "difficulty": difficulty,
+                    "name": name,
+                }));
+            }
+        }
+
+        fs::write(self.dir.join(&file), png)?;
+        self.n += 1;
+        Ok(())
+    }
+
+    fn class(&self, c: char) -> Result<usize> {
+        self.classes
+            .iter()
+            .position(|x| *x == c)
+            .ok_or(Error::MissingGlyph(c))
+    }
+
+    /// Writes the remaining annotations, i.e. `classes.txt` for `Yolo` and `annotations.json`
+    /// for `Coco`.
+    pub fn finish(self) -> Result<()> {
+        match self.format {
+            Format::JsonLines => {
+                if let Some(mut f) = self.jsonl {
+                    f.flush()?;
+                }
+            }
+            Format::Yolo => {
+                let s: String = self.classes.iter().map(|c| format!("{}\n", c)).collect();
+                fs::write(self.dir.join("classes.txt"), s)?;
+            }
+            Format::Coco => {
+                let categories: Vec<Value> = self
+                    .classes
+                    .iter()
+                    .enumerate()
+                    .map(|(i, c)| json!({ "id": i + 1, "name": c.to_string() }))
+                    .collect();
+                let v = json!({
+                    "images": self.coco_images,
+                    "annotations": self.coco_annotations,
+                    "categories": categories,
+                });
+                fs::write(self.dir.join("annotations.json"), v.to_string())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the position in the text, the character and the box of each character of `c`
+/// which is visible. The boxes are computed from the mask if it is enabled.
+fn char_boxes<T: Rng + RngCore>(c: &RngCaptcha<T>) -> Vec<(usize, char, Geometry)> {
+    let chars = c.chars();
+    let areas = match c.mask_labels() {
+        None => c.char_areas(),
+        Some(labels) => {
+            let w = c.width() as usize;
+            let mut areas: Vec<Option<Geometry>> = vec![None; chars.len()];
+            for (i, &l) in labels.iter().enumerate() {
+                if l == MASK_BACKGROUND || l as usize >= areas.len() {
+                    continue;
+                }
+                let (x, y) = ((i % w) as u32, (i / w) as u32);
+                areas[l as usize] = Some(match areas[l as usize].take() {
+                    None => Geometry::new(x, x, y, y),
+                    Some(g) => Geometry::new(
+                        min(g.left, x),
+                        max(g.right, x),
+                        min(g.top, y),
+                        max(g.bottom, y),
+                    ),
+                });
+            }
+            areas
+        }
+    };
+    chars
+        .into_iter()
+        .zip(areas)
+        .enumerate()
+        .filter_map(|(i, (ch, g))| g.map(|g| (i, ch, g)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use dataset::{char_boxes, DatasetWriter, Format};
+    use filters::Wave;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use serde_json::{self, Value};
+    use std::env;
+    use std::fs;
+    use {CaptchaName, Difficulty, Geometry, RngCaptcha};
+
+    fn write(format: Format, tag: &str) -> ::std::path::PathBuf {
+        let dir = env::temp_dir().join(format!("captcha_dataset_{}_{}", tag, ::std::process::id()));
+        let mut w = DatasetWriter::create(&dir, format).unwrap();
+        for i in 0..3 {
+            w.add_with_rng(
+                Difficulty::Easy,
+                CaptchaName::Lucy,
+                StdRng::seed_from_u64(i),
+            )
+            .unwrap();
+        }
+        assert_eq!(w.len(), 3);
+        w.finish().unwrap();
+        assert!(dir.join("images/000002.png").exists());
+        dir
+    }
+
+    #[test]
+    fn json_lines() {
+        let dir = write(Format::JsonLines, "jsonl");
+        let s = fs::read_to_string(dir.join("labels.jsonl")).unwrap();
+        let lines: Vec<Value> = s
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        let l = &lines[0];
+        assert_eq!(l["file"], "images/000000.png");
+        assert_eq!(l["difficulty"], "Easy");
+        assert_eq!(l["name"], "Lucy");
+        let text = l["text"].as_str().unwrap();
+        assert_eq!(l["chars"].as_array().unwrap().len(), text.chars().count());
+        for (i, ch) in text.chars().enumerate() {
+            assert_eq!(l["chars"][i]["index"], i);
+            assert_eq!(l["chars"][i]["char"], ch.to_string());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn yolo() {
+        let dir = write(Format::Yolo, "yolo");
+        let classes = fs::read_to_string(dir.join("classes.txt")).unwrap();
+        assert!(classes.lines().count() > 10);
+        let s = fs::read_to_string(dir.join("labels/000001.txt")).unwrap();
+        for l in s.lines() {
+            let v: Vec<f64> = l.split(' ').map(|x| x.parse().unwrap()).collect();
+            assert_eq!(v.len(), 5);
+            assert!(v[1..].iter().all(|&x| x > 0.0 && x <= 1.0));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn coco() {
+        let dir = write(Format::Coco, "coco");
+        let s = fs::read_to_string(dir.join("annotations.json")).unwrap();
+        let v: Value = serde_json::from_str(&s).unwrap();
+        assert_eq!(v["images"].as_array().unwrap().len(), 3);
+        assert_eq!(v["images"][1]["id"], 2);
+        let n: usize = v["images"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|i| i["text"].as_str().unwrap().len())
+            .sum();
+        assert_eq!(v["annotations"].as_array().unwrap().len(), n);
+        assert!(v["categories"][0]["id"] == 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn boxes() {
+        let captcha = || {
+            let mut c = RngCaptcha::from_rng(StdRng::seed_from_u64(3));
+            c.set_chars(&['W']).add_chars(3).view(200, 100);
+            c
+        };
+        let mut c = captcha();
+        let a = c.char_areas()[1].clone().unwrap();
+        let b = c.char_areas()[2].clone().unwrap();
+        // only a column of one pixel of the last character is left
+        let mut d = captcha();
+        d.extract(Geometry::new(0, b.left + 1, 0, 100));
+        let boxes = char_boxes(&d);
+        assert_eq!(boxes.len(), 3);
+        assert_eq!(boxes[1], (1, 'W', a));
+        assert_eq!(
+            (boxes[2].0, boxes[2].2.left, boxes[2].2.right),
+            (2, b.left, b.left)
+        );
+        // the first character is cropped away
+        let a0 = c.char_areas()[0].clone().unwrap();
+        c.extract(Geometry::new(a0.right + 1, 200, 0, 100));
+        let boxes = char_boxes(&c);
+        assert_eq!(boxes.len(), 2);
+        assert_eq!((boxes[0].0, boxes[1].0), (1, 2));
+
+        let mut c = RngCaptcha::from_rng(StdRng::seed_from_u64(3));
+        c.set_mask(true)
+            .set_chars(&['W'])
+            .add_chars(3)
+            .view(200, 100);
+        let areas = c.char_areas();
+        c.apply_filter(Wave::new(1.0, 15.0).vertical());
+        let boxes = char_boxes(&c);
+        assert_eq!(boxes.len(), 3);
+        let labels = c.mask_labels().unwrap();
+        for (k, (i, _, g)) in boxes.iter().enumerate() {
+            assert_eq!(*i, k);
+            assert_ne!(Some(g.clone()), areas[k]);
+            for (i, &l) in labels.iter().enumerate() {
+                let (x, y) = ((i % 200) as u32, (i / 200) as u32);
+                let inside = x >= g.left && x <= g.right && y >= g.top && y <= g.bottom;
+                assert!(l as usize != k || inside);
+            }
+        }
+    }
+}
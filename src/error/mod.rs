@@ -0,0 +1,77 @@
+//! The error type of the crate.
+
+#[cfg(feature = "audio")]
+use hound;
+use image::ImageError;
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+/// The errors which can occur when a CAPTCHA is created or encoded.
+#[derive(Debug)]
+pub enum Error {
+    /// An image or audio could not be encoded or decoded.
+    Encoding(String),
+    /// The font or the audio data has no glyph for the character.
+    MissingGlyph(char),
+    /// A parameter is invalid, e.g. a filter is configured with a minimum that is larger than
+    /// its maximum.
+    InvalidParameter(String),
+    /// An I/O error occurred.
+    Io(io::Error),
+}
+
+/// A result with the error type [`Error`](enum.Error.html).
+pub type Result<T> = result::Result<T, Error>;
+
+impl Error {
+    pub(crate) fn invalid<S: Into<String>>(msg: S) -> Error {
+        Error::InvalidParameter(msg.into())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Encoding(ref s) => write!(f, "encoding failed: {}", s),
+            Error::MissingGlyph(c) => write!(f, "no glyph for character {:?}", c),
+            Error::InvalidParameter(ref s) => write!(f, "invalid parameter: {}", s),
+            Error::Io(ref e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<ImageError> for Error {
+    fn from(e: ImageError) -> Error {
+        match e {
+            ImageError::IoError(e) => Error::Io(e),
+            e => Error::Encoding(e.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "audio")]
+impl From<hound::Error> for Error {
+    fn from(e: hound::Error) -> Error {
+        match e {
+            hound::Error::IoError(e) => Error::Io(e),
+            e => Error::Encoding(e.to_string()),
+        }
+    }
+}
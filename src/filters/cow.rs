@@ -1,11 +1,11 @@
 use rand::prelude::*;
-use rand::{rng, Rng};
+use rand::{rng, Rng, RngCore};
 use std::cmp::{max, min};
 use std::collections::BTreeSet;
 
 use filters::Filter;
 use images::Image;
-use Geometry;
+use {Error, Geometry, Result};
 
 pub struct Cow {
     min_radius: u32,
@@ -69,15 +69,23 @@ impl Cow {
         for &(x, y) in v {
             let mut p = i.get_pixel(x, y);
             p.invert();
-            i.put_pixel(x as u32, y as u32, p);
+            i.put_pixel(x, y, p);
         }
     }
 }
 
+impl Default for Cow {
+    fn default() -> Self {
+        Cow::new()
+    }
+}
+
 impl Filter for Cow {
     fn apply(&self, i: &mut Image) {
-        let mut rnd = rng();
+        self.apply_with_rng(i, &mut rng());
+    }
 
+    fn apply_with_rng(&self, i: &mut Image, rnd: &mut dyn RngCore) {
         let g = match self.geometry {
             Some(ref x) => x.clone(),
             None => Geometry::new(0, i.width() - 1, 0, i.height() - 1),
@@ -90,8 +98,7 @@ impl Filter for Cow {
         let mut set = BTreeSet::new();
 
         for _ in 0..self.n {
-            let mut rnd = rng();
-            let p = *pixels.choose(&mut rnd).expect("failed");
+            let p = *pixels.choose(rnd).expect("failed");
 
             let r = rnd.random_range(self.min_radius..self.max_radius + 1) as i32;
             let v = Self::get_pixels(p.0 as i32, p.1 as i32, r, i);
@@ -109,4 +116,25 @@ impl Filter for Cow {
 
         Self::invert_pixels(&pixels, i);
     }
+
+    fn validate(&self, i: &Image) -> Result<()> {
+        if self.min_radius > self.max_radius {
+            return Err(Error::invalid(format!(
+                "minimum radius {} is larger than maximum radius {}",
+                self.min_radius, self.max_radius
+            )));
+        }
+        if i.width() == 0 || i.height() == 0 {
+            return Err(Error::invalid("the image is empty"));
+        }
+        match self.geometry {
+            Some(ref g) if g.left > g.right || g.top > g.bottom => {
+                Err(Error::invalid(format!("invalid area {:?}", g)))
+            }
+            Some(ref g) if g.right >= i.width() || g.bottom >= i.height() => Err(Error::invalid(
+                format!("area {:?} is outside of the image", g),
+            )),
+            _ => Ok(()),
+        }
+    }
 }
@@ -1,7 +1,8 @@
-use rand::{rng, Rng};
+use rand::{rng, Rng, RngCore};
 
 use filters::Filter;
 use images::{Image, Pixl};
+use {Error, Result};
 
 pub struct Dots {
     n: u32,
@@ -35,7 +36,10 @@ impl Dots {
 
 impl Filter for Dots {
     fn apply(&self, i: &mut Image) {
-        let mut rng = rng();
+        self.apply_with_rng(i, &mut rng());
+    }
+
+    fn apply_with_rng(&self, i: &mut Image, rng: &mut dyn RngCore) {
         for _ in 0..self.n {
             let x = rng.random_range(0..i.width());
             let y = rng.random_range(0..i.height());
@@ -43,4 +47,17 @@ impl Filter for Dots {
             i.fill_circle(x, y, r, Pixl::black());
         }
     }
+
+    fn validate(&self, i: &Image) -> Result<()> {
+        if self.min_radius > self.max_radius {
+            return Err(Error::invalid(format!(
+                "minimum radius {} is larger than maximum radius {}",
+                self.min_radius, self.max_radius
+            )));
+        }
+        if self.n > 0 && (i.width() == 0 || i.height() == 0) {
+            return Err(Error::invalid("the image is empty"));
+        }
+        Ok(())
+    }
 }
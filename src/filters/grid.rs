@@ -1,5 +1,6 @@
 use filters::Filter;
 use images::{Image, Pixl};
+use {Error, Result};
 
 pub struct Grid {
     y_gap: u32,
@@ -25,4 +26,10 @@ impl Filter for Grid {
             }
         }
     }
+    fn validate(&self, _i: &Image) -> Result<()> {
+        if self.x_gap == 0 || self.y_gap == 0 {
+            return Err(Error::invalid("the gap of a grid must not be zero"));
+        }
+        Ok(())
+    }
 }
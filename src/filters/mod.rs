@@ -7,6 +7,8 @@ mod noise;
 mod wave;
 
 use images::Image;
+use rand::RngCore;
+use Result;
 
 // reexports
 pub use filters::cow::Cow;
@@ -17,4 +19,38 @@ pub use filters::wave::Wave;
 
 pub trait Filter {
     fn apply(&self, i: &mut Image);
+
+    /// Applies the filter using `rng` as the source of randomness.
+    ///
+    /// This method is called by [`Captcha::apply_filter`](../struct.RngCaptcha.html#method.apply_filter)
+    /// with the random number generator of the CAPTCHA so that a CAPTCHA created from a seeded
+    /// generator is reproducible. Filters which need random numbers should override this method.
+    /// The default implementation calls `apply`.
+    fn apply_with_rng(&self, i: &mut Image, _rng: &mut dyn RngCore) {
+        self.apply(i)
+    }
+
+    /// Applies the filter to the image `i` and its segmentation mask `mask`.
+    ///
+    /// This method is called instead of [`apply_with_rng`](#method.apply_with_rng) if the
+    /// segmentation mask of the CAPTCHA is enabled. The mask has the same size as the image.
+    /// Filters which move pixels have to override this method and move the pixels of the mask
+    /// in the same way, so that the mask stays aligned with the image. The default
+    /// implementation leaves the mask unchanged, which is correct for filters which only draw
+    /// on the image.
+    fn apply_with_mask(&self, i: &mut Image, _mask: &mut Image, rng: &mut dyn RngCore) {
+        self.apply_with_rng(i, rng)
+    }
+
+    /// Checks whether the parameters of the filter are valid for the image `i`.
+    ///
+    /// This method is called by [`Captcha::try_apply_filter`](../struct.RngCaptcha.html#method.try_apply_filter)
+    /// before the filter is applied. [`Captcha::apply_filter`](../struct.RngCaptcha.html#method.apply_filter)
+    /// skips a filter for which this method returns an error. Filters which would panic for
+    /// some parameters should override this method and return
+    /// [`Error::InvalidParameter`](../enum.Error.html). The default implementation accepts all
+    /// images.
+    fn validate(&self, _i: &Image) -> Result<()> {
+        Ok(())
+    }
 }
@@ -1,4 +1,4 @@
-use rand::{rng, Rng};
+use rand::{rng, Rng, RngCore};
 
 use filters::Filter;
 use images::{Image, Pixl};
@@ -15,7 +15,10 @@ impl Noise {
 
 impl Filter for Noise {
     fn apply(&self, i: &mut Image) {
-        let mut rng = rng();
+        self.apply_with_rng(i, &mut rng());
+    }
+
+    fn apply_with_rng(&self, i: &mut Image, rng: &mut dyn RngCore) {
         for y in 0..i.height() {
             for x in 0..i.width() {
                 if rng.random::<f32>() <= self.prob {
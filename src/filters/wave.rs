@@ -2,7 +2,9 @@ use std::f64::consts;
 
 use filters::Filter;
 use images::Image;
+use rand::RngCore;
 
+#[allow(clippy::upper_case_acronyms)]
 pub enum Direction {
     HORIZONTAL,
     VERTICAL,
@@ -76,4 +78,9 @@ impl Filter for Wave {
             }
         }
     }
+
+    fn apply_with_mask(&self, i: &mut Image, mask: &mut Image, _rng: &mut dyn RngCore) {
+        self.apply(i);
+        self.apply(mask);
+    }
 }
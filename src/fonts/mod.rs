@@ -1,52 +1,242 @@
-use base64::decode;
+//! Fonts which are used to draw the characters of a CAPTCHA.
+//!
+//! A font is a set of glyphs, each of which is a PNG image of a single character with black
+//! text on a white background. Besides the [`Default`](struct.Default.html) font which is
+//! embedded into the crate, own glyph sets can be loaded via [`Custom`](struct.Custom.html).
+//! With the feature `truetype` glyphs can also be rendered from TrueType and OpenType fonts via
+//! `TrueType`.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # extern crate captcha;
+//! use captcha::fonts::Custom;
+//! use captcha::Captcha;
+//! use std::path::Path;
+//!
+//! # fn main() {
+//! let font = Custom::from_dir(Path::new("glyphs")).expect("could not load font");
+//! Captcha::new().set_font(font).add_chars(5).as_png();
+//! # }
+//! ```
+
+use base64::{decode, encode};
 use serde_json;
 use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use images::Image;
+use {Error, Result};
+
+#[cfg(feature = "truetype")]
+mod truetype;
+
+#[cfg(feature = "truetype")]
+pub use fonts::truetype::TrueType;
 
 pub trait Font {
-    fn png_as_base64(&self, letter: char) -> Option<&String>;
+    /// Returns the glyph for `letter` as a base64 encoded PNG image.
+    ///
+    /// Fonts which do not store their glyphs as base64 encoded PNG images return `None` and
+    /// implement [`png`](#method.png) instead.
+    fn png_as_base64(&self, _letter: char) -> Option<&String> {
+        None
+    }
 
+    /// Returns the characters for which the font has a glyph.
+    ///
+    /// The order of the characters should not change between calls because random characters
+    /// are selected from this list. Otherwise CAPTCHAs created from a seeded random number
+    /// generator would not be reproducible.
     fn chars(&self) -> Vec<char>;
 
     /// Returns None if letter does not exist or if letter could not decoded.
     fn png(&self, letter: char) -> Option<Vec<u8>> {
         match self.png_as_base64(letter) {
             None => None,
-            Some(s) => match decode(s) {
-                Err(_) => None,
-                Ok(v) => Some(v),
-            },
+            Some(s) => decode(s).ok(),
+        }
+    }
+
+    /// Returns the decoded glyph for `letter`.
+    ///
+    /// This method is called for each character which is added to a CAPTCHA. The default
+    /// implementation decodes the image returned by [`png`](#method.png) on every call. Fonts
+    /// should override this method if they can keep the decoded glyphs.
+    fn glyph(&self, letter: char) -> Option<Image> {
+        self.png(letter).and_then(Image::from_png)
+    }
+}
+
+/// The glyphs of a font which are decoded only once and shared between all copies of the font.
+struct Glyphs {
+    base64: HashMap<char, String>,
+    images: HashMap<char, Image>,
+    chars: Vec<char>,
+}
+
+impl Glyphs {
+    /// Decodes the glyphs. Glyphs which are not valid PNG images are dropped.
+    fn new(mut base64: HashMap<char, String>) -> Glyphs {
+        let images: HashMap<char, Image> = base64
+            .iter()
+            .filter_map(|(c, s)| decode(s).ok().and_then(Image::from_png).map(|i| (*c, i)))
+            .collect();
+        base64.retain(|c, _| images.contains_key(c));
+        let mut chars: Vec<char> = images.keys().cloned().collect();
+        chars.sort();
+        Glyphs {
+            base64,
+            images,
+            chars,
         }
     }
 }
 
+/// The font which is used by default.
+///
+/// The embedded font data is parsed and decoded once per process. All instances share the
+/// decoded glyphs, so creating an instance is cheap.
+#[derive(Clone)]
 pub struct Default {
-    data: HashMap<char, String>,
+    glyphs: Arc<Glyphs>,
 }
 
+static DEFAULT_GLYPHS: OnceLock<::std::result::Result<Arc<Glyphs>, String>> = OnceLock::new();
+
 impl Default {
+    /// Loads the embedded font.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the embedded font data is corrupt. Use [`try_new`](#method.try_new) to get an
+    /// error instead.
     pub fn new() -> Default {
-        let json = include_str!("font_default.json").to_string();
+        Default::try_new().expect("invalid font data")
+    }
 
-        Default {
-            data: serde_json::from_str(&json).expect("invalid json"),
+    /// Loads the embedded font.
+    pub fn try_new() -> Result<Default> {
+        let glyphs = DEFAULT_GLYPHS.get_or_init(|| {
+            let json = include_str!("font_default.json");
+            serde_json::from_str(json)
+                .map(|data| Arc::new(Glyphs::new(data)))
+                .map_err(|e| e.to_string())
+        });
+        match *glyphs {
+            Ok(ref g) => Ok(Default { glyphs: g.clone() }),
+            Err(ref e) => Err(Error::Encoding(e.clone())),
         }
     }
 }
 
+impl ::std::default::Default for Default {
+    fn default() -> Self {
+        Default::new()
+    }
+}
+
 impl Font for Default {
     fn png_as_base64(&self, letter: char) -> Option<&String> {
-        self.data.get(&letter)
+        self.glyphs.base64.get(&letter)
     }
 
     fn chars(&self) -> Vec<char> {
-        self.data.keys().cloned().collect()
+        self.glyphs.chars.clone()
+    }
+
+    fn glyph(&self, letter: char) -> Option<Image> {
+        self.glyphs.images.get(&letter).cloned()
+    }
+}
+
+/// A font with user supplied glyphs.
+///
+/// Glyphs should have black text on a white background and should all have roughly the same
+/// height, similar to the glyphs of the [`Default`](struct.Default.html) font.
+///
+/// The glyphs are decoded when the font is created. Clones of the font share the decoded
+/// glyphs, so a font should be loaded once and cloned for each CAPTCHA.
+#[derive(Clone)]
+pub struct Custom {
+    glyphs: Arc<Glyphs>,
+}
+
+impl Custom {
+    /// Creates a font from a map which contains for each character its glyph encoded as PNG.
+    ///
+    /// Characters whose glyph is not a valid PNG image are not part of the font.
+    pub fn from_pngs(glyphs: HashMap<char, Vec<u8>>) -> Custom {
+        let data = glyphs.into_iter().map(|(c, v)| (c, encode(v))).collect();
+        Custom {
+            glyphs: Arc::new(Glyphs::new(data)),
+        }
+    }
+
+    /// Creates a font from a directory which contains one PNG file per glyph.
+    ///
+    /// The name of each file without the extension `.png` is either the character itself (e.g.
+    /// `a.png`) or its code point (e.g. `U+0041.png` for `A`). The latter is useful on file
+    /// systems which are not case sensitive. Files with another extension and files which are
+    /// not valid PNG images are ignored.
+    pub fn from_dir(p: &Path) -> io::Result<Custom> {
+        let mut glyphs = HashMap::new();
+        for entry in fs::read_dir(p)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("png") {
+                continue;
+            }
+            let c = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(Custom::char_from_name)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid glyph file name: {}", path.display()),
+                    )
+                })?;
+            glyphs.insert(c, fs::read(&path)?);
+        }
+        Ok(Custom::from_pngs(glyphs))
+    }
+
+    fn char_from_name(name: &str) -> Option<char> {
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ if name.starts_with("U+") => u32::from_str_radix(&name[2..], 16)
+                .ok()
+                .and_then(::std::char::from_u32),
+            _ => None,
+        }
+    }
+}
+
+impl Font for Custom {
+    fn png_as_base64(&self, letter: char) -> Option<&String> {
+        self.glyphs.base64.get(&letter)
+    }
+
+    fn chars(&self) -> Vec<char> {
+        self.glyphs.chars.clone()
+    }
+
+    fn glyph(&self, letter: char) -> Option<Image> {
+        self.glyphs.images.get(&letter).cloned()
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use fonts::{Default, Font};
+    use fonts::{Custom, Default, Font};
     use images::Image;
+    use std::collections::HashMap;
+    use std::env;
+    use std::fs;
 
     #[test]
     fn fonts_default() {
@@ -59,4 +249,56 @@ mod tests {
             assert!(Image::from_png(f.png(i).unwrap()).is_some());
         }
     }
+
+    #[test]
+    fn fonts_glyph_cache() {
+        let f = Default::new();
+        let g = Default::new();
+        for c in f.chars() {
+            let a = f.glyph(c).unwrap();
+            let b = Image::from_png(g.png(c).unwrap()).unwrap();
+            assert_eq!((a.width(), a.height()), (b.width(), b.height()));
+            assert!(a.rows().flatten().eq(b.rows().flatten()));
+        }
+        assert!(f.glyph('\u{263a}').is_none());
+
+        let mut m = HashMap::new();
+        m.insert('x', f.png('x').unwrap());
+        m.insert('y', vec![1, 2, 3]);
+        let c = Custom::from_pngs(m);
+        assert_eq!(c.chars(), vec!['x']);
+        assert!(c.clone().glyph('x').is_some());
+        assert!(c.glyph('y').is_none());
+        assert!(c.png('y').is_none());
+    }
+
+    #[test]
+    fn fonts_custom_from_dir() {
+        let d = Default::new();
+        let dir = env::temp_dir().join(format!("captcha_font_{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.png"), d.png('a').unwrap()).unwrap();
+        fs::write(dir.join("U+0041.png"), d.png('A').unwrap()).unwrap();
+        fs::write(dir.join("README.txt"), "ignored").unwrap();
+
+        let f = Custom::from_dir(&dir).unwrap();
+        let mut chars = f.chars();
+        chars.sort();
+        assert_eq!(chars, vec!['A', 'a']);
+        assert_eq!(f.png('A'), d.png('A'));
+
+        fs::write(dir.join("ab.png"), d.png('a').unwrap()).unwrap();
+        assert!(Custom::from_dir(&dir).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn fonts_custom_from_pngs() {
+        let mut m = HashMap::new();
+        m.insert('x', Default::new().png('x').unwrap());
+        let f = Custom::from_pngs(m);
+        assert_eq!(f.chars(), vec!['x']);
+        assert!(Image::from_png(f.png('x').unwrap()).is_some());
+        assert!(f.png('y').is_none());
+    }
 }
@@ -0,0 +1,196 @@
+use ab_glyph::{point, Font as AbFont, FontVec, PxScale, ScaleFont};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use fonts::Font;
+use images::{Image, Pixl};
+use {Error, Result};
+
+/// The smallest height of the glyphs in pixels.
+const MIN_SIZE: f32 = 4.0;
+/// The largest height of the glyphs in pixels.
+const MAX_SIZE: f32 = 512.0;
+
+/// A font which renders its glyphs from a TrueType or OpenType font.
+///
+/// The glyphs are rendered when they are requested for the first time and are kept for later
+/// use. A pixel of a glyph is black if it is covered to at least 50% by the outline of the
+/// character. Otherwise it is white. All glyphs have the height of a line of the font, so
+/// each character keeps its position relative to the baseline.
+pub struct TrueType {
+    font: FontVec,
+    size: f32,
+    cache: Mutex<HashMap<char, Option<Image>>>,
+}
+
+impl TrueType {
+    /// Creates a font from the content of a `.ttf` or `.otf` file. The glyphs are rendered with
+    /// a height of `size` pixels.
+    ///
+    /// Returns an error if the data is not a valid font or if `size` is not in `4..=512`.
+    pub fn from_bytes(data: Vec<u8>, size: f32) -> Result<TrueType> {
+        check_size(size)?;
+        let font = FontVec::try_from_vec(data).map_err(|e| Error::Encoding(e.to_string()))?;
+        Ok(TrueType {
+            font,
+            size,
+            cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Loads a font from a `.ttf` or `.otf` file. The glyphs are rendered with a height of
+    /// `size` pixels.
+    ///
+    /// Returns an error if the file cannot be read, if it is not a valid font or if `size` is
+    /// not in `4..=512`.
+    pub fn from_file(p: &Path, size: f32) -> Result<TrueType> {
+        TrueType::from_bytes(fs::read(p)?, size)
+    }
+
+    /// Sets the height of the glyphs to `size` pixels. Glyphs which have been rendered with the
+    /// previous size are discarded.
+    ///
+    /// Returns an error if `size` is not in `4..=512`.
+    pub fn size(self, size: f32) -> Result<TrueType> {
+        check_size(size)?;
+        Ok(TrueType {
+            size,
+            cache: Mutex::new(HashMap::new()),
+            ..self
+        })
+    }
+
+    fn render(&self, letter: char) -> Option<Image> {
+        let id = self.font.glyph_id(letter);
+        if id.0 == 0 {
+            return None;
+        }
+        let scale = PxScale::from(self.size);
+        let font = self.font.as_scaled(scale);
+        let g = self
+            .font
+            .outline_glyph(id.with_scale_and_position(scale, point(0.0, font.ascent())))?;
+        let bounds = g.px_bounds();
+        // The image spans the line from the ascent to the descent and, for glyphs which
+        // exceed it, the whole outline.
+        let top = bounds.min.y.min(0.0);
+        let bottom = bounds.max.y.max((font.ascent() - font.descent()).ceil());
+        let w = bounds.width().ceil() as u32;
+        let h = (bottom - top).ceil() as u32;
+        let dy = (bounds.min.y - top) as u32;
+        let mut i = Image::new(w + 2 * MARGIN, h + 2 * MARGIN);
+        g.draw(|x, y, c| {
+            if c >= 0.5 {
+                i.put_pixel(x + MARGIN, y + dy + MARGIN, Pixl::black());
+            }
+        });
+        Some(i)
+    }
+}
+
+fn check_size(size: f32) -> Result<()> {
+    if size.is_finite() && (MIN_SIZE..=MAX_SIZE).contains(&size) {
+        Ok(())
+    } else {
+        Err(Error::invalid(format!(
+            "the size of a TrueType font must be in {}..={}",
+            MIN_SIZE, MAX_SIZE
+        )))
+    }
+}
+
+const MARGIN: u32 = 1;
+
+impl Font for TrueType {
+    fn chars(&self) -> Vec<char> {
+        let mut v: Vec<char> = self
+            .font
+            .codepoint_ids()
+            .filter(|&(id, c)| {
+                !c.is_whitespace() && !c.is_control() && self.font.outline(id).is_some()
+            })
+            .map(|(_, c)| c)
+            .collect();
+        v.sort();
+        v.dedup();
+        v
+    }
+
+    fn png(&self, letter: char) -> Option<Vec<u8>> {
+        self.glyph(letter).and_then(|i| i.as_png())
+    }
+
+    fn glyph(&self, letter: char) -> Option<Image> {
+        let mut cache = self.cache.lock().expect("lock poisoned");
+        cache
+            .entry(letter)
+            .or_insert_with(|| self.render(letter))
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fonts::{Font, TrueType};
+    use images::{Image, Pixl};
+    use std::path::Path;
+
+    // created by gen/fonts/gen.py
+    const FONT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fonts/test.ttf");
+
+    #[test]
+    fn truetype_invalid() {
+        assert!(TrueType::from_bytes(vec![1, 2, 3], 40.0).is_err());
+        assert!(TrueType::from_file(Path::new("/nonexistent.ttf"), 40.0).is_err());
+        for &size in &[0.0, -10.0, 2.0, 1e6, f32::NAN, f32::INFINITY] {
+            assert!(
+                TrueType::from_file(Path::new(FONT), size).is_err(),
+                "{}",
+                size
+            );
+        }
+        let f = TrueType::from_file(Path::new(FONT), 40.0).unwrap();
+        assert!(f.size(0.0).is_err());
+    }
+
+    #[test]
+    fn truetype_render() {
+        let f = TrueType::from_file(Path::new(FONT), 40.0).unwrap();
+        assert_eq!(f.chars(), vec!['I', 'W', 'a', 'g']);
+        assert!(!f.chars().contains(&' '));
+        assert!(f.png(' ').is_none());
+        assert!(f.png('b').is_none());
+
+        let i = Image::from_png(f.png('W').unwrap()).unwrap();
+        assert!(i.height() > 20 && i.height() <= 42);
+        assert!(i.rows().flatten().any(|p| p == Pixl::black()));
+
+        let g = f.glyph('W').unwrap();
+        assert_eq!((g.width(), g.height()), (i.width(), i.height()));
+
+        let small = Image::from_png(f.size(20.0).unwrap().png('W').unwrap()).unwrap();
+        assert!(small.height() < i.height());
+    }
+
+    #[test]
+    fn truetype_baseline() {
+        let f = TrueType::from_file(Path::new(FONT), 40.0).unwrap();
+        let rows = |c: char| -> Vec<bool> {
+            let i = f.glyph(c).unwrap();
+            i.rows()
+                .map(|mut r| r.any(|p| p == Pixl::black()))
+                .collect()
+        };
+        let (a, g, w) = (rows('a'), rows('g'), rows('W'));
+        assert_eq!(a.len(), g.len());
+        assert_eq!(a.len(), w.len());
+        let last = |v: &[bool]| v.iter().rposition(|&b| b).unwrap();
+        let first = |v: &[bool]| v.iter().position(|&b| b).unwrap();
+        // 'a' and 'W' stand on the baseline, 'g' reaches below it, 'W' is taller than 'a'
+        assert_eq!(last(&a), last(&w));
+        assert!(last(&g) > last(&a) + 5);
+        assert!(first(&w) + 5 < first(&a));
+    }
+}
@@ -1,16 +1,49 @@
-use image::ImageResult as Result;
+//! Image primitives on which CAPTCHAs and filters operate.
+//!
+//! The types in this module are the building blocks for custom filters. A filter receives a
+//! mutable [`Image`](struct.Image.html) and can read and write single pixels, iterate over
+//! rows or work directly on the underlying `RgbImage` of the `image` crate.
+//!
+//! # Examples
+//!
+//! ```
+//! # extern crate captcha;
+//! use captcha::filters::Filter;
+//! use captcha::{Captcha, Image, Pixl};
+//!
+//! struct Stripes;
+//!
+//! impl Filter for Stripes {
+//!     fn apply(&self, i: &mut Image) {
+//!         for y in (0..i.height()).step_by(10) {
+//!             for x in 0..i.width() {
+//!                 i.blend_pixel(x, y, Pixl::black(), 0.5);
+//!             }
+//!         }
+//!     }
+//! }
+//!
+//! # fn main() {
+//! Captcha::new().add_chars(4).apply_filter(Stripes).as_png();
+//! # }
+//! ```
+
 use std::cmp::{max, min};
 use std::path::Path;
 
+use image::imageops::{resize, FilterType};
 use image::{load_from_memory, ImageBuffer, Rgb, RgbImage};
 use lodepng;
+use {Error, Result};
 
-#[derive(Clone, Copy)]
+/// A RGB pixel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Pixl {
     rgb: [u8; 3],
 }
 
-#[derive(Clone)]
+/// A RGB image.
+#[derive(Clone, Debug)]
 pub struct Image {
     img: RgbImage,
 }
@@ -24,15 +57,44 @@ impl Pixl {
         Pixl::new(0, 0, 0)
     }
 
+    pub fn white() -> Pixl {
+        Pixl::new(255, 255, 255)
+    }
+
     pub fn red() -> Pixl {
         Pixl::new(255, 0, 0)
     }
 
+    /// Returns the red, green and blue channel of the pixel.
+    pub fn rgb(&self) -> [u8; 3] {
+        self.rgb
+    }
+
     pub fn invert(&mut self) {
         self.rgb[0] = 255 - self.rgb[0];
         self.rgb[1] = 255 - self.rgb[1];
         self.rgb[2] = 255 - self.rgb[2];
     }
+
+    /// Returns the mix of this pixel and `p`.
+    ///
+    /// `alpha` is the weight of `p` and is clamped to the range [0, 1], i.e. for 0.0 the result
+    /// is this pixel and for 1.0 the result is `p`.
+    pub fn blend(&self, p: Pixl, alpha: f32) -> Pixl {
+        let a = alpha.clamp(0.0, 1.0);
+        let mut rgb = [0; 3];
+        for (i, v) in rgb.iter_mut().enumerate() {
+            let c = self.rgb[i] as f32 * (1.0 - a) + p.rgb[i] as f32 * a;
+            *v = c.round() as u8;
+        }
+        Pixl { rgb }
+    }
+}
+
+impl From<[u8; 3]> for Pixl {
+    fn from(rgb: [u8; 3]) -> Pixl {
+        Pixl { rgb }
+    }
 }
 
 impl Image {
@@ -40,6 +102,7 @@ impl Image {
         Rgb::<u8>([255, 255, 255])
     }
 
+    /// Decodes an image from the given bytes. Returns `None` if the data could not be decoded.
     pub fn from_png(v: Vec<u8>) -> Option<Image> {
         match load_from_memory(&v) {
             Err(_) => None,
@@ -47,6 +110,7 @@ impl Image {
         }
     }
 
+    /// Creates a white image of the given size.
     pub fn new(w: u32, h: u32) -> Image {
         Image {
             img: ImageBuffer::from_pixel(w, h, Self::pixel_white()),
@@ -67,12 +131,18 @@ impl Image {
         }
     }
 
+    /// Sets the pixel at the given position. Positions outside of the image are ignored.
     pub fn put_pixel(&mut self, x: u32, y: u32, p: Pixl) {
         if x < self.img.width() && y < self.img.height() {
             self.img.put_pixel(x, y, Rgb::<u8>(p.rgb));
         }
     }
 
+    /// Returns the pixel at the given position.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the position is outside of the image.
     pub fn get_pixel(&self, x: u32, y: u32) -> Pixl {
         let p = *self.img.get_pixel(x, y);
         Pixl {
@@ -80,6 +150,15 @@ impl Image {
         }
     }
 
+    /// Mixes the pixel at the given position with `p` (see [`Pixl::blend`]). Positions outside
+    /// of the image are ignored.
+    pub fn blend_pixel(&mut self, x: u32, y: u32, p: Pixl, alpha: f32) {
+        if x < self.img.width() && y < self.img.height() {
+            let q = self.get_pixel(x, y).blend(p, alpha);
+            self.put_pixel(x, y, q);
+        }
+    }
+
     pub fn width(&self) -> u32 {
         self.img.width()
     }
@@ -88,8 +167,29 @@ impl Image {
         self.img.height()
     }
 
+    /// Returns an iterator over the rows of the image, from top to bottom. Each row is an
+    /// iterator over its pixels from left to right.
+    pub fn rows(&self) -> impl Iterator<Item = impl Iterator<Item = Pixl> + '_> + '_ {
+        self.img.rows().map(|r| r.map(|p| Pixl { rgb: p.0 }))
+    }
+
+    /// Returns a reference to the underlying image.
+    pub fn as_rgb_image(&self) -> &RgbImage {
+        &self.img
+    }
+
+    /// Returns a mutable reference to the underlying image.
+    pub fn as_rgb_image_mut(&mut self) -> &mut RgbImage {
+        &mut self.img
+    }
+
+    /// Consumes the image and returns the underlying image.
+    pub fn into_rgb_image(self) -> RgbImage {
+        self.img
+    }
+
     pub fn save(&self, p: &Path) -> Result<()> {
-        self.img.save(p)
+        Ok(self.img.save(p)?)
     }
 
     pub fn fill_circle(&mut self, x: u32, y: u32, r: u32, p: Pixl) {
@@ -108,10 +208,12 @@ impl Image {
         }
     }
 
+    /// Sets all pixels to white.
     pub fn clear(&mut self) {
         self.img = ImageBuffer::from_pixel(self.width(), self.height(), Self::pixel_white())
     }
 
+    /// Copies the image `i` into this image with its upper left corner at the given position.
     pub fn add_image(&mut self, x: u32, y: u32, i: &Image) {
         for iy in 0..i.height() {
             for ix in 0..i.width() {
@@ -120,13 +222,150 @@ impl Image {
         }
     }
 
+    /// Draws the image `i` on top of this image with its upper left corner at the given position.
+    ///
+    /// In contrast to [`add_image`](#method.add_image) each channel of a pixel is set to the
+    /// darker value of both images, i.e. the white background of `i` does not overwrite what has
+    /// already been drawn.
+    pub fn overlay(&mut self, x: u32, y: u32, i: &Image) {
+        for iy in 0..i.height() {
+            for ix in 0..i.width() {
+                let (px, py) = (x + ix, y + iy);
+                if px < self.width() && py < self.height() {
+                    let a = self.get_pixel(px, py).rgb;
+                    let b = i.get_pixel(ix, iy).rgb;
+                    let p = Pixl::new(min(a[0], b[0]), min(a[1], b[1]), min(a[2], b[2]));
+                    self.put_pixel(px, py, p);
+                }
+            }
+        }
+    }
+
+    /// Returns a copy of the image which is resized by the factor `f`.
+    ///
+    /// The size of the new image is at least 1x1 pixels.
+    pub fn scale(&self, f: f32) -> Image {
+        let w = max((self.width() as f32 * f).round() as u32, 1);
+        let h = max((self.height() as f32 * f).round() as u32, 1);
+        Image {
+            img: resize(&self.img, w, h, FilterType::Nearest),
+        }
+    }
+
+    /// Returns a copy of the image which is rotated clockwise by the given angle in degrees.
+    ///
+    /// The new image is large enough to contain the whole rotated image. Areas which are not
+    /// covered by the rotated image are white.
+    pub fn rotate(&self, degrees: f32) -> Image {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let (w, h) = (self.width() as f32, self.height() as f32);
+        // subtract a small epsilon so that rounding errors do not add an additional row or column
+        let nw = (w * cos.abs() + h * sin.abs() - 1e-3).ceil() as u32;
+        let nh = (w * sin.abs() + h * cos.abs() - 1e-3).ceil() as u32;
+        let mut i = Image::new(nw, nh);
+        let (cx, cy) = (w / 2.0, h / 2.0);
+        let (ncx, ncy) = (nw as f32 / 2.0, nh as f32 / 2.0);
+        for y in 0..nh {
+            for x in 0..nw {
+                let dx = x as f32 + 0.5 - ncx;
+                let dy = y as f32 + 0.5 - ncy;
+                let sx = (dx * cos + dy * sin + cx).floor();
+                let sy = (dy * cos - dx * sin + cy).floor();
+                if sx >= 0.0 && sy >= 0.0 && sx < w && sy < h {
+                    i.put_pixel(x, y, self.get_pixel(sx as u32, sy as u32));
+                }
+            }
+        }
+        i
+    }
+
+    /// Encodes the image as PNG. Returns `None` on error.
     pub fn as_png(&self) -> Option<Vec<u8>> {
+        self.try_as_png().ok()
+    }
+
+    /// Encodes the image as PNG.
+    pub fn try_as_png(&self) -> Result<Vec<u8>> {
         let w = self.img.width() as usize;
         let h = self.img.height() as usize;
         let i = self.img.clone().into_raw();
-        match lodepng::encode_memory(&i, w, h, lodepng::ColorType::RGB, 8) {
-            Err(_) => None,
-            Ok(v) => Some(v),
-        }
+        lodepng::encode_memory(&i, w, h, lodepng::ColorType::RGB, 8)
+            .map_err(|e| Error::Encoding(e.to_string()))
+    }
+}
+
+impl From<RgbImage> for Image {
+    fn from(img: RgbImage) -> Image {
+        Image { img }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use images::{Image, Pixl};
+
+    #[test]
+    fn blend() {
+        let p = Pixl::white().blend(Pixl::black(), 0.5);
+        assert_eq!(p.rgb(), [128, 128, 128]);
+        assert_eq!(Pixl::white().blend(Pixl::red(), 2.0), Pixl::red());
+        assert_eq!(Pixl::red().blend(Pixl::white(), -1.0), Pixl::red());
+    }
+
+    #[test]
+    fn rows() {
+        let mut i = Image::new(3, 2);
+        i.put_pixel(1, 1, Pixl::black());
+        i.put_pixel(5, 5, Pixl::black());
+        let rows: Vec<Vec<Pixl>> = i.rows().map(|r| r.collect()).collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0], vec![Pixl::white(); 3]);
+        assert_eq!(rows[1], vec![Pixl::white(), Pixl::black(), Pixl::white()]);
+    }
+
+    #[test]
+    fn overlay() {
+        let mut i = Image::new(4, 1);
+        i.put_pixel(0, 0, Pixl::black());
+        let mut j = Image::new(2, 1);
+        j.put_pixel(1, 0, Pixl::new(100, 100, 100));
+        i.overlay(0, 0, &j);
+        i.overlay(3, 0, &j);
+        let row: Vec<Pixl> = i.rows().next().unwrap().collect();
+        assert_eq!(
+            row,
+            vec![
+                Pixl::black(),
+                Pixl::new(100, 100, 100),
+                Pixl::white(),
+                Pixl::white()
+            ]
+        );
+    }
+
+    #[test]
+    fn scale_and_rotate() {
+        let mut i = Image::new(10, 4);
+        i.put_pixel(0, 0, Pixl::black());
+        assert_eq!(i.scale(2.0).as_rgb_image().dimensions(), (20, 8));
+        assert_eq!(i.scale(0.01).as_rgb_image().dimensions(), (1, 1));
+        assert_eq!(i.rotate(0.0).get_pixel(0, 0), Pixl::black());
+
+        let r = i.rotate(90.0);
+        assert_eq!(r.as_rgb_image().dimensions(), (4, 10));
+        assert_eq!(r.get_pixel(3, 0), Pixl::black());
+        assert_eq!(
+            r.rows().flatten().filter(|p| *p == Pixl::black()).count(),
+            1
+        );
+    }
+
+    #[test]
+    fn rgb_image_roundtrip() {
+        let mut i = Image::new(4, 4);
+        i.blend_pixel(2, 3, Pixl::black(), 1.0);
+        let j = Image::from(i.clone().into_rgb_image());
+        assert_eq!(j.get_pixel(2, 3), Pixl::black());
+        assert_eq!(j.as_rgb_image().dimensions(), (4, 4));
     }
 }
@@ -43,40 +43,83 @@
 //! # }
 //! ```
 
-// TODO rotate characters
-// TODO resize characters
-// TODO overlap characters
-
+#[cfg(feature = "truetype")]
+extern crate ab_glyph;
+#[cfg(feature = "axum")]
+extern crate axum;
 extern crate base64;
+#[cfg(feature = "token")]
+extern crate hmac;
 #[cfg(feature = "audio")]
 extern crate hound;
 extern crate image;
 extern crate lodepng;
 extern crate rand;
+#[cfg(feature = "rayon")]
+extern crate rayon;
 extern crate serde_json;
+#[cfg(feature = "token")]
+extern crate sha2;
+#[cfg(feature = "server")]
+extern crate tiny_http;
+#[cfg(feature = "axum")]
+extern crate tokio;
+#[cfg(all(test, feature = "axum"))]
+extern crate tower;
+#[cfg(feature = "axum")]
+extern crate tower_layer;
+#[cfg(feature = "axum")]
+extern crate tower_service;
 
 mod audio;
+#[cfg(feature = "audio")]
+pub mod audio_filters;
+mod builder;
+pub mod dataset;
+mod error;
 pub mod filters;
-mod fonts;
-mod images;
+pub mod fonts;
+pub mod images;
+#[cfg(feature = "axum")]
+pub mod middleware;
+mod pool;
 mod samples;
+#[cfg(feature = "server")]
+pub mod server;
+mod store;
+#[cfg(feature = "token")]
+pub mod token;
+mod verify;
 
-pub use samples::{by_name, generate, CaptchaName, Difficulty};
+pub use builder::CaptchaBuilder;
+pub use error::{Error, Result};
+pub use samples::{
+    by_name, by_name_from, by_name_nth, by_name_with_rng, generate, generate_batch, generate_iter,
+    generate_nth, generate_with_rng, CaptchaName, Difficulty,
+};
 
 use filters::Filter;
 use fonts::{Default, Font};
-use images::{Image, Pixl};
+pub use images::{Image, Pixl};
+pub use pool::{CaptchaPool, PoolBuilder, PoolStats};
+pub use store::{challenge_id, ChallengeStore, MemoryStore, SharedStore, Verdict};
+pub use verify::{verify, Verifier, CONFUSABLES};
 
 #[cfg(feature = "audio")]
 use audio::Audio;
-use image::ImageResult as Result;
+#[cfg(feature = "audio")]
+pub use audio::{AudioEncoding, AudioOptions, Synth, VoicePack, VOICE_PACK_FORMAT};
+#[cfg(feature = "audio")]
+use audio_filters::{AudioFilter, SharedAudioFilter};
 use rand::prelude::*;
 use rand::rng;
+use rand::rngs::StdRng;
 use std::cmp::{max, min};
 use std::path::Path;
+use std::sync::Arc;
 
 /// Represents the area which contains text in a CAPTCHA.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Geometry {
     /// The minimum x coordinate of the area which contains text (inclusive).
     pub left: u32,
@@ -97,31 +140,114 @@ impl Geometry {
             bottom,
         }
     }
+
+    /// Moves the area by the given offset and clips it to an image of size `w` x `h`.
+    ///
+    /// Returns `None` if no part of the area is inside of the image.
+    fn translate(&self, dx: i64, dy: i64, w: u32, h: u32) -> Option<Geometry> {
+        let (left, right) = (self.left as i64 + dx, self.right as i64 + dx);
+        let (top, bottom) = (self.top as i64 + dy, self.bottom as i64 + dy);
+        if right < 0 || bottom < 0 || left >= w as i64 || top >= h as i64 {
+            return None;
+        }
+        Some(Geometry {
+            left: max(left, 0) as u32,
+            right: min(right, w as i64 - 1) as u32,
+            top: max(top, 0) as u32,
+            bottom: min(bottom, h as i64 - 1) as u32,
+        })
+    }
+
+    /// Scales the coordinates of the area by the factor `f`.
+    fn scale(&self, f: f32) -> Geometry {
+        let s = |v: u32| (v as f32 * f).floor() as u32;
+        Geometry {
+            left: s(self.left),
+            right: s(self.right),
+            top: s(self.top),
+            bottom: s(self.bottom),
+        }
+    }
+}
+
+/// A CAPTCHA which uses a random number generator seeded from the thread local generator.
+///
+/// In contrast to the thread local generator the generator can be moved between threads, so a
+/// `Captcha` is `Send` and `Sync`. It can be created in another thread, e.g. in
+/// `tokio::task::spawn_blocking`, and be passed back to the caller.
+pub type Captcha = RngCaptcha<StdRng>;
+
+/// A font which can be shared between CAPTCHAs and threads.
+pub type SharedFont = Arc<dyn Font + Send + Sync>;
+
+/// Returns a new random number generator for a [`Captcha`](type.Captcha.html).
+pub(crate) fn default_rng() -> StdRng {
+    StdRng::from_rng(&mut rng())
 }
 
-pub type Captcha = RngCaptcha<ThreadRng>;
+/// The value of the pixels of a segmentation mask which do not belong to a character.
+///
+/// See [`RngCaptcha::set_mask`](struct.RngCaptcha.html#method.set_mask).
+pub const MASK_BACKGROUND: u8 = 255;
+
+/// The largest factor by which characters can be resized (see
+/// [`RngCaptcha::set_scale`](struct.RngCaptcha.html#method.set_scale)).
+const MAX_SCALE: f32 = 10.0;
+
+/// Returns the part of the image `i` of size `w` x `h` with its upper left corner at the given
+/// position. Parts of the box which are outside of the image are white.
+fn crop_image(i: &Image, left: i64, top: i64, w: u32, h: u32) -> Image {
+    let mut r = Image::new(w, h);
+    for iy in 0..h {
+        for ix in 0..w {
+            let x = left + ix as i64;
+            let y = top + iy as i64;
+            if x >= 0 && y >= 0 && x < i.width() as i64 && y < i.height() as i64 {
+                r.put_pixel(ix, iy, i.get_pixel(x as u32, y as u32));
+            }
+        }
+    }
+    r
+}
 
 /// A CAPTCHA.
+///
+/// A CAPTCHA is `Send` and `Sync` if its random number generator is.
 pub struct RngCaptcha<T> {
     img: Image,
-    font: Box<dyn Font>,
+    mask: Option<Image>,
+    font: SharedFont,
     text_area: Geometry,
+    char_areas: Vec<Option<Geometry>>,
     chars: Vec<char>,
     use_font_chars: Vec<char>,
     color: Option<[u8; 3]>,
+    rotation: f32,
+    scale: (f32, f32),
+    jitter: u32,
+    spacing: i32,
+    #[cfg(feature = "audio")]
+    voice: Option<VoicePack>,
+    #[cfg(feature = "audio")]
+    synth: Option<Synth>,
+    #[cfg(feature = "audio")]
+    audio: AudioOptions,
+    #[cfg(feature = "audio")]
+    audio_filters: Vec<SharedAudioFilter>,
     rng: T,
 }
 
 impl<T: rand::Rng + rand::RngCore> RngCaptcha<T> {
-
     pub fn from_rng(rng: T) -> RngCaptcha<T> {
-        // TODO fixed width + height
+        // The canvas is enlarged automatically if characters do not fit into it. Use
+        // CaptchaBuilder to create CAPTCHAs of a fixed size.
         let w = 400;
         let h = 300;
-        let f = Box::new(Default::new());
+        let f: SharedFont = Arc::new(Default::new());
         RngCaptcha::<T> {
             use_font_chars: f.chars(),
             img: Image::new(w, h),
+            mask: None,
             font: f,
             text_area: Geometry {
                 left: w / 4,
@@ -129,26 +255,92 @@ impl<T: rand::Rng + rand::RngCore> RngCaptcha<T> {
                 top: h / 2,
                 bottom: h / 2,
             },
+            char_areas: vec![],
             chars: vec![],
             color: None,
+            rotation: 0.0,
+            scale: (1.0, 1.0),
+            jitter: 0,
+            spacing: 0,
+            #[cfg(feature = "audio")]
+            voice: None,
+            #[cfg(feature = "audio")]
+            synth: None,
+            #[cfg(feature = "audio")]
+            audio: AudioOptions::new(),
+            #[cfg(feature = "audio")]
+            audio_filters: vec![],
             rng,
         }
     }
 
     /// Returns an empty CAPTCHA.
     pub fn new() -> Captcha {
-        Captcha::from_rng(rng())
+        Captcha::from_rng(default_rng())
     }
 
     /// Applies the filter `f` to the CAPTCHA.
     ///
     /// This method is used to add noise, grids, etc or to transform the shape of the CAPTCHA.
+    ///
+    /// A filter whose parameters are invalid for this CAPTCHA is ignored. Use
+    /// [`try_apply_filter`](#method.try_apply_filter) to get an error in this case.
     pub fn apply_filter<F: Filter>(&mut self, f: F) -> &mut Self {
-        f.apply(&mut self.img);
+        let _ = self.try_apply_filter(f);
         self
         // TODO support other fonts
     }
 
+    /// Applies the filter `f` to the CAPTCHA.
+    ///
+    /// Returns an error if the parameters of the filter are invalid for this CAPTCHA. In this
+    /// case the CAPTCHA is not modified.
+    pub fn try_apply_filter<F: Filter>(&mut self, f: F) -> Result<&mut Self> {
+        f.validate(&self.img)?;
+        match self.mask {
+            Some(ref mut m) => f.apply_with_mask(&mut self.img, m, &mut self.rng),
+            None => f.apply_with_rng(&mut self.img, &mut self.rng),
+        }
+        Ok(self)
+    }
+
+    /// Enables or disables the segmentation mask of the CAPTCHA.
+    ///
+    /// The mask has the same size as the CAPTCHA. Each pixel of the mask is either the index of
+    /// the character whose glyph has drawn the pixel or
+    /// [`MASK_BACKGROUND`](constant.MASK_BACKGROUND.html) for the background and for pixels
+    /// which are drawn by filters, e.g. noise or dots. The mask is moved together with the
+    /// image by filters which move pixels (like [`Wave`](filters/struct.Wave.html)) and by
+    /// the methods which crop or resize the CAPTCHA. Characters with an index above 254 are
+    /// labelled with 254.
+    ///
+    /// The mask should be enabled before characters are added. Characters which have already
+    /// been added are not contained in the mask.
+    pub fn set_mask(&mut self, enabled: bool) -> &mut Self {
+        self.mask = if enabled {
+            Some(Image::new(self.img.width(), self.img.height()))
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Returns the segmentation mask or `None` if the mask is not enabled.
+    ///
+    /// The label of a pixel is stored in each of its color channels. See
+    /// [`set_mask`](#method.set_mask).
+    pub fn mask(&self) -> Option<Image> {
+        self.mask.clone()
+    }
+
+    /// Returns the label of each pixel of the segmentation mask row by row or `None` if the
+    /// mask is not enabled.
+    pub fn mask_labels(&self) -> Option<Vec<u8>> {
+        self.mask
+            .as_ref()
+            .map(|m| m.rows().flatten().map(|p| p.rgb()[0]).collect())
+    }
+
     /// Sets another font that is used for the characters.
     ///
     /// Calling this method does not have an effect on the font of the characters which have already
@@ -156,11 +348,17 @@ impl<T: rand::Rng + rand::RngCore> RngCaptcha<T> {
     /// to the CAPTCHA after this method is called.
     ///
     /// If characters have been set via set_chars(), this method will overwrite the setting.
-    pub fn set_font<F: Font + 'static>(&mut self, f: F) -> &mut Self {
-        self.font = Box::new(f);
+    pub fn set_font<F: Font + Send + Sync + 'static>(&mut self, f: F) -> &mut Self {
+        self.set_shared_font(Arc::new(f))
+    }
+
+    /// Sets a font which is shared with other CAPTCHAs.
+    ///
+    /// This avoids loading a font for each CAPTCHA. See [`set_font`](#method.set_font).
+    pub fn set_shared_font(&mut self, f: SharedFont) -> &mut Self {
+        self.font = f;
         self.use_font_chars = self.font.chars();
         self
-        // TODO support other fonts
     }
 
     pub fn set_color(&mut self, color: [u8; 3]) -> &mut Self {
@@ -168,6 +366,70 @@ impl<T: rand::Rng + rand::RngCore> RngCaptcha<T> {
         self
     }
 
+    /// Sets the maximum angle in degrees by which characters are rotated.
+    ///
+    /// Each character which is added afterwards is rotated by a random angle between
+    /// `-degrees` and `degrees`. By default characters are not rotated.
+    ///
+    /// If `degrees` is not finite the setting is not changed. Use
+    /// [`try_set_rotation`](#method.try_set_rotation) to get an error in this case.
+    pub fn set_rotation(&mut self, degrees: f32) -> &mut Self {
+        let _ = self.try_set_rotation(degrees);
+        self
+    }
+
+    /// Sets the maximum angle in degrees by which characters are rotated.
+    ///
+    /// Returns an error if `degrees` is not finite.
+    pub fn try_set_rotation(&mut self, degrees: f32) -> Result<&mut Self> {
+        if !degrees.is_finite() {
+            return Err(Error::invalid(format!("invalid rotation {}", degrees)));
+        }
+        self.rotation = degrees.abs();
+        Ok(self)
+    }
+
+    /// Sets the range of the factor by which characters are resized.
+    ///
+    /// Each character which is added afterwards is resized by a random factor between `min`
+    /// and `max` (inclusive). By default characters are not resized.
+    ///
+    /// If the range is invalid the setting is not changed. Use
+    /// [`try_set_scale`](#method.try_set_scale) to get an error in this case.
+    pub fn set_scale(&mut self, min: f32, max: f32) -> &mut Self {
+        let _ = self.try_set_scale(min, max);
+        self
+    }
+
+    /// Sets the range of the factor by which characters are resized.
+    ///
+    /// Returns an error if `min` or `max` is not in `0 < x <= 10` or if `min` is greater than
+    /// `max`.
+    pub fn try_set_scale(&mut self, min: f32, max: f32) -> Result<&mut Self> {
+        if !(min > 0.0 && min <= max && max <= MAX_SCALE) {
+            return Err(Error::invalid(format!("invalid scale {}..{}", min, max)));
+        }
+        self.scale = (min, max);
+        Ok(self)
+    }
+
+    /// Sets the maximum number of pixels by which characters are moved up or down.
+    ///
+    /// Each character which is added afterwards is moved vertically by a random offset between
+    /// `-pixels` and `pixels`. By default all characters share the same vertical center.
+    pub fn set_jitter(&mut self, pixels: u32) -> &mut Self {
+        self.jitter = pixels;
+        self
+    }
+
+    /// Sets the horizontal space in pixels between two characters.
+    ///
+    /// A negative value lets neighbouring characters overlap. The default is 0.
+    pub fn set_spacing(&mut self, pixels: i32) -> &mut Self {
+        self.spacing = pixels;
+        self
+    }
+
     /// Saves the CAPTCHA to a image file.
     ///
     /// The format that is written is determined from the filename's extension. On error `Err` is
@@ -186,31 +448,115 @@ impl<T: rand::Rng + rand::RngCore> RngCaptcha<T> {
         self
     }
 
-    fn random_char_as_image(&mut self) -> Option<(char, Image)> {
-        match self.use_font_chars.choose(&mut self.rng) {
-            None => None,
-            Some(c) => match self.font.png(*c) {
-                None => None,
-                Some(p) => Image::from_png(p).map(|i| (*c, i)),
-            },
+    /// Sets the characters that should be used when generating a CAPTCHA.
+    ///
+    /// Returns an error if `c` is empty or if the current font does not support one of the
+    /// characters.
+    pub fn try_set_chars(&mut self, c: &[char]) -> Result<&mut Self> {
+        if c.is_empty() {
+            return Err(Error::invalid("the set of characters is empty"));
+        }
+        let supported = self.font.chars();
+        if let Some(x) = c.iter().find(|x| !supported.contains(x)) {
+            return Err(Error::MissingGlyph(*x));
         }
+        Ok(self.set_chars(c))
+    }
+
+    fn random_char_as_image(&mut self) -> Result<(char, Image)> {
+        let c = *self
+            .use_font_chars
+            .choose(&mut self.rng)
+            .ok_or_else(|| Error::invalid("the set of characters is empty"))?;
+        let i = self.font.glyph(c).ok_or(Error::MissingGlyph(c))?;
+        Ok((c, i))
+    }
+
+    fn transform_char(&mut self, mut i: Image) -> Image {
+        let (lo, hi) = self.scale;
+        if lo != 1.0 || hi != 1.0 {
+            i = i.scale(self.rng.random_range(lo..=hi));
+        }
+        if self.rotation > 0.0 {
+            i = i.rotate(self.rng.random_range(-self.rotation..=self.rotation));
+        }
+        i
     }
 
     /// Adds a random character using the current font.
+    ///
+    /// The character is rotated, resized and moved according to the settings of
+    /// [`set_rotation`](#method.set_rotation), [`set_scale`](#method.set_scale),
+    /// [`set_jitter`](#method.set_jitter) and [`set_spacing`](#method.set_spacing).
+    ///
+    /// If the glyph of the character could not be loaded, nothing is added. Use
+    /// [`try_add_char`](#method.try_add_char) to get an error in this case.
     pub fn add_char(&mut self) -> &mut Self {
-        if let Some((c, i)) = self.random_char_as_image() {
-            let x = self.text_area.right;
-            let y = (self.text_area.bottom + self.text_area.top) / 2 - i.height() / 2;
-            self.img.add_image(x, y, &i);
+        let _ = self.try_add_char();
+        self
+    }
+
+    /// Adds a random character using the current font.
+    ///
+    /// Returns an error if no characters are available or if the glyph of the character could
+    /// not be loaded.
+    pub fn try_add_char(&mut self) -> Result<&mut Self> {
+        let (c, i) = self.random_char_as_image()?;
+        {
+            let i = self.transform_char(i);
+            let x = if self.chars.is_empty() {
+                self.text_area.right
+            } else {
+                max(self.text_area.right as i64 + self.spacing as i64, 0) as u32
+            };
+            let dy = if self.jitter > 0 {
+                let j = self.jitter as i64;
+                self.rng.random_range(-j..=j)
+            } else {
+                0
+            };
+            let center = ((self.text_area.bottom + self.text_area.top) / 2) as i64;
+            let y = max(center - (i.height() / 2) as i64 + dy, 0) as u32;
+            self.grow(x + i.width(), y + i.height());
+            self.img.overlay(x, y, &i);
+            if let Some(ref mut m) = self.mask {
+                let label = min(self.chars.len(), MASK_BACKGROUND as usize - 1) as u8;
+                let l = Pixl::new(label, label, label);
+                for (iy, row) in i.rows().enumerate() {
+                    for (ix, p) in row.enumerate() {
+                        let [r, g, b] = p.rgb();
+                        if (r as u32 + g as u32 + b as u32) < 3 * 128 {
+                            m.put_pixel(x + ix as u32, y + iy as u32, l);
+                        }
+                    }
+                }
+            }
 
+            self.text_area.left = min(self.text_area.left, x);
             self.text_area.top = min(self.text_area.top, y);
-            self.text_area.right = x + i.width() - 1;
+            self.text_area.right = max(self.text_area.right, x + i.width() - 1);
             self.text_area.bottom = max(self.text_area.bottom, y + i.height() - 1);
+            self.char_areas.push(Some(Geometry::new(
+                x,
+                x + i.width() - 1,
+                y,
+                y + i.height() - 1,
+            )));
             self.chars.push(c);
         }
 
-        self
-        // TODO automatically resize if many characters are added
+        Ok(self)
+    }
+
+    /// Enlarges the canvas so that it has at least the given width and height.
+    fn grow(&mut self, w: u32, h: u32) {
+        if w > self.img.width() || h > self.img.height() {
+            let (w, h) = (max(w, self.img.width()), max(h, self.img.height()));
+            self.img = crop_image(&self.img, 0, 0, w, h);
+            if let Some(ref mut m) = self.mask {
+                *m = crop_image(m, 0, 0, w, h);
+            }
+        }
     }
 
     /// Adds a red box to the CAPTCHA representing the area which contains text.
@@ -227,45 +573,141 @@ impl<T: rand::Rng + rand::RngCore> RngCaptcha<T> {
     }
 
     /// Returns the geometry of the area which contains text in the CAPTCHA.
+    ///
+    /// The coordinates refer to the current image, i.e. they are updated by
+    /// [`extract`](#method.extract), [`view`](#method.view) and [`fit`](#method.fit). If the
+    /// text has been cropped completely the area is the upper left pixel of the image.
     pub fn text_area(&self) -> Geometry {
         self.text_area.clone()
     }
 
+    /// Returns for each character of the CAPTCHA the box which contains its glyph.
+    ///
+    /// The boxes are in the same order as the characters returned by [`chars`](#method.chars)
+    /// and their coordinates refer to the current image. If a character has been cropped
+    /// partially its box is clipped to the image; if it has been cropped completely its box is
+    /// `None`. Filters which move pixels, like [`Wave`](filters/struct.Wave.html), are not taken
+    /// into account.
+    pub fn char_areas(&self) -> Vec<Option<Geometry>> {
+        self.char_areas.clone()
+    }
+
     /// Crops the CAPTCHA to the given geometry.
     pub fn extract(&mut self, area: Geometry) -> &mut Self {
         // TODO rename the method
-        // TODO adjust the text area
-        let w = area.right - area.left;
-        let h = area.bottom - area.top;
-        let mut i = Image::new(w, h);
-        for (y, iy) in (area.top..area.bottom).zip(0..h + 1) {
-            for (x, ix) in (area.left..area.right).zip(0..w + 1) {
-                i.put_pixel(ix, iy, self.img.get_pixel(x, y));
-            }
-        }
-        self.img = i;
+        let w = area.right.saturating_sub(area.left);
+        let h = area.bottom.saturating_sub(area.top);
+        self.crop(area.left as i64, area.top as i64, w, h);
         self
     }
 
+    /// Crops the CAPTCHA to the given geometry.
+    ///
+    /// Returns an error if the geometry is empty.
+    pub fn try_extract(&mut self, area: Geometry) -> Result<&mut Self> {
+        if area.right <= area.left || area.bottom <= area.top {
+            return Err(Error::invalid(format!("empty area {:?}", area)));
+        }
+        Ok(self.extract(area))
+    }
+
+    /// Crops the image to the box of size `w` x `h` with its upper left corner at the given
+    /// position. Parts of the box which are outside of the image are white.
+    fn crop(&mut self, left: i64, top: i64, w: u32, h: u32) {
+        self.img = crop_image(&self.img, left, top, w, h);
+        if let Some(ref mut m) = self.mask {
+            *m = crop_image(m, left, top, w, h);
+        }
+
+        self.text_area = self
+            .text_area
+            .translate(-left, -top, w, h)
+            .unwrap_or_else(|| Geometry::new(0, 0, 0, 0));
+        for a in self.char_areas.iter_mut() {
+            *a = a.as_ref().and_then(|g| g.translate(-left, -top, w, h));
+        }
+    }
+
     /// Crops the CAPTCHA to the given width and height with the text centered withing this
     /// box.
+    ///
+    /// If the box is larger than the CAPTCHA the missing area is filled with white. If the box
+    /// is smaller than the text, the text is clipped. Use [`fit`](#method.fit) to scale the text
+    /// so that it fits into the box.
     pub fn view(&mut self, w: u32, h: u32) -> &mut Self {
-        let mut a = self.text_area();
-        a.left = (a.right + a.left) / 2 - w / 2;
-        a.right = a.left + w;
-        a.top = (a.bottom + a.top) / 2 - h / 2;
-        a.bottom = a.top + h;
-        self.extract(a);
-        // TODO update text area
-        // TODO what happens if w or h are too small
+        let a = self.text_area();
+        let left = ((a.right + a.left) / 2) as i64 - (w / 2) as i64;
+        let top = ((a.bottom + a.top) / 2) as i64 - (h / 2) as i64;
+        self.crop(left, top, w, h);
         self
     }
 
+    /// Crops the CAPTCHA to the given width and height with the text centered within this box.
+    ///
+    /// Returns an error if the width or the height is zero.
+    pub fn try_view(&mut self, w: u32, h: u32) -> Result<&mut Self> {
+        Self::check_size(w, h)?;
+        Ok(self.view(w, h))
+    }
+
+    fn check_size(w: u32, h: u32) -> Result<()> {
+        if w == 0 || h == 0 {
+            return Err(Error::invalid(format!("invalid size {}x{}", w, h)));
+        }
+        Ok(())
+    }
+
+    /// Crops the CAPTCHA to the given width and height with the text centered within this box.
+    ///
+    /// In contrast to [`view`](#method.view) the text is scaled down if it does not fit into the
+    /// box, so that no character is clipped. `margin` is the minimum number of pixels between
+    /// the text and the border of the box.
+    pub fn fit(&mut self, w: u32, h: u32, margin: u32) -> &mut Self {
+        let a = self.text_area();
+        let tw = a.right - a.left + 1;
+        let th = a.bottom - a.top + 1;
+        let aw = w.saturating_sub(2 * margin) as f32;
+        let ah = h.saturating_sub(2 * margin) as f32;
+        let f = (aw / tw as f32).min(ah / th as f32);
+        if f < 1.0 {
+            self.crop(a.left as i64, a.top as i64, tw, th);
+            self.img = self.img.scale(f);
+            if let Some(ref mut m) = self.mask {
+                *m = m.scale(f);
+            }
+            let (sw, sh) = (self.img.width(), self.img.height());
+            self.text_area = Geometry::new(0, sw - 1, 0, sh - 1);
+            for a in self.char_areas.iter_mut() {
+                *a = a.as_ref().and_then(|g| g.scale(f).translate(0, 0, sw, sh));
+            }
+        }
+        self.view(w, h)
+    }
+
+    /// Crops the CAPTCHA to the given width and height and scales the text so that it fits into
+    /// this box.
+    ///
+    /// Returns an error if the box without the margin is empty.
+    pub fn try_fit(&mut self, w: u32, h: u32, margin: u32) -> Result<&mut Self> {
+        Self::check_size(w.saturating_sub(2 * margin), h.saturating_sub(2 * margin))?;
+        Ok(self.fit(w, h, margin))
+    }
+
     /// Returns the characters that have been added to this CAPTCHA.
     pub fn chars(&self) -> Vec<char> {
         self.chars.clone()
     }
 
+    /// Returns the width of the CAPTCHA in pixels.
+    pub fn width(&self) -> u32 {
+        self.img.width()
+    }
+
+    /// Returns the height of the CAPTCHA in pixels.
+    pub fn height(&self) -> u32 {
+        self.img.height()
+    }
+
     /// Returns the characters that have been added to this CAPTCHA collected into a string.
     pub fn chars_as_string(&self) -> String {
         self.chars.iter().collect()
@@ -279,48 +721,256 @@ impl<T: rand::Rng + rand::RngCore> RngCaptcha<T> {
         self
     }
 
+    /// Adds the given number of random characters to the CAPTCHA using the current font.
+    ///
+    /// Stops at the first character which could not be added and returns its error.
+    pub fn try_add_chars(&mut self, n: u32) -> Result<&mut Self> {
+        for _ in 0..n {
+            self.try_add_char()?;
+        }
+        Ok(self)
+    }
+
     fn apply_transformations(&self) -> Image {
         let mut i = self.img.clone();
-        if self.color.is_some() {
-            i.set_color(&self.color.unwrap());
+        if let Some(color) = self.color {
+            i.set_color(&color);
         }
         i
     }
 
-    /// Returns for each letter in the CAPTCHA an audio in WAV format.
+    /// Sets the voice pack which is used by [`as_wav`](#method.as_wav). By default the built-in
+    /// voice pack is used (see [`VoicePack::builtin`](struct.VoicePack.html#method.builtin)).
+    #[cfg(feature = "audio")]
+    pub fn set_voice(&mut self, voice: VoicePack) -> &mut Self {
+        self.voice = Some(voice);
+        self.synth = None;
+        self
+    }
+
+    /// Speaks the CAPTCHA with a synthesiser instead of a voice pack. Synthesisers with
+    /// invalid parameters are ignored.
+    ///
+    /// Each call of [`as_wav`](#method.as_wav) or [`as_audio`](#method.as_audio) speaks with a
+    /// different voice close to the voice of the synthesiser (see
+    /// [`Synth::vary`](struct.Synth.html#method.vary)), which takes its random numbers from the
+    /// random number generator of the CAPTCHA. To speak always with the same voice create a
+    /// voice pack with [`Synth::voice_pack`](struct.Synth.html#method.voice_pack) and use
+    /// [`set_voice`](#method.set_voice).
+    #[cfg(feature = "audio")]
+    pub fn set_synth(&mut self, synth: Synth) -> &mut Self {
+        let _ = self.try_set_synth(synth);
+        self
+    }
+
+    /// Speaks the CAPTCHA with a synthesiser instead of a voice pack.
+    ///
+    /// Returns an error if the parameters of the synthesiser are invalid.
+    #[cfg(feature = "audio")]
+    pub fn try_set_synth(&mut self, synth: Synth) -> Result<&mut Self> {
+        synth.validate()?;
+        self.synth = Some(synth);
+        self.voice = None;
+        Ok(self)
+    }
+
+    /// Returns for each letter in the CAPTCHA an audio file.
     ///
-    /// Warning: Currently this feature is rather limited. The same audio data is returned
-    /// for the same letter, i.e. no noise is added. Someone could solve the CAPTCHA by
-    /// simply having the audio for each letter and comparing them with the current challenge.
+    /// The files are encoded as set with [`set_audio_options`](#method.set_audio_options); by
+    /// default they are WAV files with 16 bit samples. The audio filters of the CAPTCHA (see
+    /// [`add_audio_filter`](#method.add_audio_filter)) and the noise take their random numbers
+    /// from the random number generator of the CAPTCHA, which is why this method takes
+    /// `&mut self`.
+    ///
+    /// A letter for which no audio could be created is `None`. If the audio data could not be
+    /// loaded, or if the synthesiser (see [`set_synth`](#method.set_synth)) cannot speak one of
+    /// the letters, all letters are `None`.
+    ///
+    /// Warning: Without audio filters the same letter always sounds the same apart from the
+    /// white noise. Someone could solve the CAPTCHA by comparing the audio with a recording of
+    /// each letter. The CAPTCHAs created by [`generate`](fn.generate.html) use the audio
+    /// filters of their difficulty.
+    #[cfg(feature = "hound")]
+    pub fn as_wav(&mut self) -> Vec<Option<Vec<u8>>> {
+        let chars = self.chars();
+        let audio = match self.audio(false) {
+            Ok(a) => a,
+            Err(_) => return vec![None; chars.len()],
+        };
+        chars
+            .iter()
+            .map(|x| audio.as_wav(*x, &self.audio, &mut self.rng))
+            .collect()
+    }
+
+    /// Returns for each letter in the CAPTCHA an audio file.
+    ///
+    /// Returns an error if the audio data could not be loaded, if there is no audio for one
+    /// of the letters or if the audio could not be encoded.
     #[cfg(feature = "hound")]
-    pub fn as_wav(&self) -> Vec<Option<Vec<u8>>> {
-        let audio = Audio::new();
-        self.chars().iter().map(|x| audio.as_wav(*x)).collect()
+    pub fn try_as_wav(&mut self) -> Result<Vec<Vec<u8>>> {
+        let audio = self.audio(false)?;
+        let chars = self.chars();
+        chars
+            .iter()
+            .map(|x| audio.try_as_wav(*x, &self.audio, &mut self.rng))
+            .collect()
+    }
+
+    /// Adds an audio filter which is applied by [`as_wav`](#method.as_wav) and
+    /// [`as_audio`](#method.as_audio). Filters with invalid parameters are ignored.
+    ///
+    /// See the module [`audio_filters`](audio_filters/index.html).
+    #[cfg(feature = "audio")]
+    pub fn add_audio_filter<F: AudioFilter + Send + Sync + 'static>(&mut self, f: F) -> &mut Self {
+        let _ = self.try_add_audio_filter(f);
+        self
+    }
+
+    /// Adds an audio filter which is applied by [`as_wav`](#method.as_wav) and
+    /// [`as_audio`](#method.as_audio).
+    ///
+    /// Returns an error if the parameters of the filter are invalid.
+    #[cfg(feature = "audio")]
+    pub fn try_add_audio_filter<F: AudioFilter + Send + Sync + 'static>(
+        &mut self,
+        f: F,
+    ) -> Result<&mut Self> {
+        f.validate()?;
+        self.audio_filters.push(Arc::new(f));
+        Ok(self)
+    }
+
+    /// Replaces the audio filters by the preset for the given difficulty (see
+    /// [`audio_filters::preset`](audio_filters/fn.preset.html)).
+    #[cfg(feature = "audio")]
+    pub fn set_audio_difficulty(&mut self, d: Difficulty) -> &mut Self {
+        self.audio_filters = audio_filters::preset(d);
+        self
+    }
+
+    /// The synthesiser only speaks the prompt `intro` if `intro` is set.
+    #[cfg(feature = "audio")]
+    fn audio(&mut self, intro: bool) -> Result<Audio> {
+        let audio = if let Some(ref s) = self.synth {
+            let prompts: &[&str] = if intro { &["intro"] } else { &[] };
+            Audio::with_voice(
+                s.vary(&mut self.rng)
+                    .voice_pack_with(&self.chars, prompts)?,
+            )
+        } else if let Some(ref v) = self.voice {
+            Audio::with_voice(v.clone())
+        } else {
+            Audio::try_new()?
+        };
+        Ok(audio.filters(self.audio_filters.clone()))
+    }
+
+    /// Sets the options for [`as_audio`](#method.as_audio). The sample rate and the encoding
+    /// of the options also apply to [`as_wav`](#method.as_wav).
+    #[cfg(feature = "audio")]
+    pub fn set_audio_options(&mut self, o: AudioOptions) -> &mut Self {
+        self.audio = o;
+        self
+    }
+
+    /// Returns the MIME type of the audio files which are returned by
+    /// [`as_wav`](#method.as_wav) and [`as_audio`](#method.as_audio), e.g. `audio/wav`.
+    #[cfg(feature = "audio")]
+    pub fn audio_mime_type(&self) -> &'static str {
+        self.audio.mime_type()
+    }
+
+    /// Returns one audio file which contains all letters of the CAPTCHA.
+    ///
+    /// The file is encoded as set with [`set_audio_options`](#method.set_audio_options). The
+    /// pauses between the letters have random lengths, so the letters cannot be found
+    /// at fixed positions. Use [`set_audio_options`](#method.set_audio_options) to speak an
+    /// intro before the letters or to repeat them.
+    ///
+    /// Returns `None` on error.
+    #[cfg(feature = "audio")]
+    pub fn as_audio(&mut self) -> Option<Vec<u8>> {
+        self.try_as_audio().ok()
+    }
+
+    /// Returns one audio file which contains all letters of the CAPTCHA.
+    ///
+    /// Returns an error if the audio data could not be loaded, if there is no audio for one
+    /// of the letters, if an intro is requested but the voice pack has none or if the audio
+    /// could not be encoded.
+    #[cfg(feature = "audio")]
+    pub fn try_as_audio(&mut self) -> Result<Vec<u8>> {
+        let intro = self.audio.has_intro();
+        let audio = self.audio(intro)?;
+        let chars = self.chars();
+        audio.try_as_audio(&chars, &self.audio, &mut self.rng)
     }
 
     /// Returns the CAPTCHA as a png image.
     ///
     /// Returns `None` on error.
     pub fn as_png(&self) -> Option<Vec<u8>> {
+        self.try_as_png().ok()
+    }
+
+    /// Returns the CAPTCHA as a png image.
+    pub fn try_as_png(&self) -> Result<Vec<u8>> {
         // TODO currently we always create a copy. For most use cases this might not be
         // necessary.
         let i = self.apply_transformations();
-        i.as_png()
+        i.try_as_png()
     }
 
     pub fn as_base64(&self) -> Option<String> {
         self.as_png().map(base64::encode)
     }
 
+    /// Returns the CAPTCHA as a base64 encoded png image.
+    pub fn try_as_base64(&self) -> Result<String> {
+        self.try_as_png().map(base64::encode)
+    }
+
     /// Returns a tuple which contains the characters that have been added to this CAPTCHA
     /// as a string and the image encoded as a PNG.
     ///
     /// Returns `None` on error.
     pub fn as_tuple(&self) -> Option<(String, Vec<u8>)> {
-        match self.as_png() {
-            None => None,
-            Some(p) => Some((self.chars_as_string(), p)),
-        }
+        self.as_png().map(|p| (self.chars_as_string(), p))
+    }
+
+    /// Returns a tuple which contains the characters that have been added to this CAPTCHA
+    /// as a string and the image encoded as a PNG.
+    pub fn try_as_tuple(&self) -> Result<(String, Vec<u8>)> {
+        self.try_as_png().map(|p| (self.chars_as_string(), p))
+    }
+
+    /// Registers the answer of this CAPTCHA in `store` under a new random id.
+    ///
+    /// Returns a tuple which contains the id and the image encoded as PNG. The id has to be sent
+    /// to the client together with the image so that the answer can be checked later via
+    /// [`ChallengeStore::consume`](trait.ChallengeStore.html#tymethod.consume).
+    ///
+    /// Returns `None` on error. In this case nothing is inserted into the store.
+    pub fn register<S: ChallengeStore + ?Sized>(&self, store: &S) -> Option<(String, Vec<u8>)> {
+        let png = self.as_png()?;
+        let id = store::insert_new(store, &self.chars_as_string());
+        Some((id, png))
+    }
+
+    /// Returns a verifier for the answer of this CAPTCHA.
+    ///
+    /// The verifier only folds confusable characters which cannot appear in this CAPTCHA (see
+    /// [`Verifier::charset`](struct.Verifier.html#method.charset)).
+    pub fn verifier(&self) -> Verifier {
+        Verifier::new().charset(&self.use_font_chars)
+    }
+
+    /// Returns true if `input` is the answer to this CAPTCHA.
+    ///
+    /// The input is compared with [`verifier`](#method.verifier) and its default settings.
+    pub fn verify(&self, input: &str) -> bool {
+        self.verifier().verify(&self.chars_as_string(), input)
     }
 
     /// Returns the supported characters of the current font.
@@ -331,11 +981,20 @@ impl<T: rand::Rng + rand::RngCore> RngCaptcha<T> {
 
 #[cfg(test)]
 mod tests {
-    use filters::{Grid, Noise};
+    use filters::{Cow, Dots, Grid, Noise, Wave};
     use fonts::Default;
-    use Captcha;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use {
+        generate, generate_batch, generate_iter, generate_with_rng, Captcha, CaptchaBuilder,
+        Difficulty, Error, Geometry, RngCaptcha, SharedFont, MASK_BACKGROUND,
+    };
+    #[cfg(feature = "audio")]
+    use {AudioOptions, Synth, VoicePack};
 
     use std::path::Path;
+    use std::sync::Arc;
+    use std::thread;
 
     #[test]
     fn it_works() {
@@ -355,6 +1014,351 @@ mod tests {
         c.as_png().expect("no png");
     }
 
+    #[test]
+    fn deterministic() {
+        let build = |seed| {
+            let mut c = RngCaptcha::from_rng(StdRng::seed_from_u64(seed));
+            c.add_chars(5)
+                .apply_filter(Noise::new(0.2))
+                .apply_filter(Dots::new(10))
+                .apply_filter(Cow::new())
+                .view(220, 120);
+            (c.chars_as_string(), c.as_png().unwrap())
+        };
+        assert_eq!(build(1), build(1));
+        assert_ne!(build(1).1, build(2).1);
+
+        for d in &[Difficulty::Easy, Difficulty::Medium, Difficulty::Hard] {
+            let a = generate_with_rng(*d, StdRng::seed_from_u64(3)).as_tuple();
+            let b = generate_with_rng(*d, StdRng::seed_from_u64(3)).as_tuple();
+            assert_eq!(a, b);
+        }
+    }
+
+    #[test]
+    fn batch() {
+        let tuples = |v: Vec<Captcha>| v.iter().map(|c| c.as_tuple()).collect::<Vec<_>>();
+        let a = tuples(generate_batch(6, Difficulty::Medium, 7));
+        assert_eq!(a.len(), 6);
+        assert_eq!(a, tuples(generate_batch(6, Difficulty::Medium, 7)));
+        assert_eq!(
+            a,
+            tuples(generate_iter(Difficulty::Medium, 7).take(6).collect())
+        );
+        assert_ne!(a, tuples(generate_batch(6, Difficulty::Medium, 8)));
+        assert_ne!(a[0], a[1]);
+        let b = tuples(generate_batch(
+            1,
+            Difficulty::Medium,
+            7 ^ 0x9e37_79b9_7f4a_7c15,
+        ));
+        assert_ne!(a[1], b[0]);
+    }
+
+    #[test]
+    fn transformed_chars() {
+        let captcha = || {
+            let mut c = RngCaptcha::from_rng(StdRng::seed_from_u64(4));
+            c.set_chars(&['W']);
+            c
+        };
+        let width = |a: Geometry| a.right - a.left;
+        let mut plain = captcha();
+        plain.add_chars(4);
+        let a = plain.text_area();
+
+        let mut c = captcha();
+        c.set_spacing(-8).add_chars(4);
+        assert_eq!(c.text_area().right, a.right - 3 * 8);
+
+        let mut c = captcha();
+        c.set_scale(1.5, 1.5).add_chars(4);
+        assert!(width(c.text_area()) > width(a.clone()) * 5 / 4);
+
+        let mut c = captcha();
+        c.set_rotation(30.0).set_jitter(5).add_chars(4);
+        assert_eq!(c.chars().len(), 4);
+        assert_ne!(c.char_areas(), plain.char_areas());
+        assert!(c.text_area().bottom - c.text_area().top > a.bottom - a.top);
+    }
+
+    #[test]
+    fn invalid_transformations() {
+        let mut c = RngCaptcha::from_rng(StdRng::seed_from_u64(4));
+        c.set_rotation(10.0).set_scale(0.8, 1.2);
+        assert!(c.try_set_rotation(f32::NAN).is_err());
+        assert!(c.try_set_rotation(f32::INFINITY).is_err());
+        assert!(c.try_set_scale(f32::NAN, 1.2).is_err());
+        assert!(c.try_set_scale(0.8, f32::INFINITY).is_err());
+        assert!(c.try_set_scale(1.2, 0.8).is_err());
+        assert!(c.try_set_scale(0.0, 1.0).is_err());
+        assert!(c.try_set_scale(1.0, 1e9).is_err());
+        assert!(c.try_set_scale(10.0, 10.0).is_ok());
+        c.set_scale(0.8, 1.2);
+        c.set_rotation(f32::NAN).set_scale(1.2, 0.8);
+        assert_eq!((c.rotation, c.scale), (10.0, (0.8, 1.2)));
+        c.add_chars(4);
+        assert_eq!(c.chars().len(), 4);
+        assert!(c.try_set_rotation(-20.0).is_ok());
+        assert_eq!(c.rotation, 20.0);
+    }
+
+    #[test]
+    fn many_chars() {
+        let mut c = RngCaptcha::from_rng(StdRng::seed_from_u64(0));
+        c.add_chars(20);
+        let a = c.text_area();
+        assert!(a.right < c.img.width());
+        assert!(a.right - a.left > 400);
+
+        c.view(1000, 400);
+        assert_eq!((c.img.width(), c.img.height()), (1000, 400));
+    }
+
+    #[test]
+    fn geometry_after_crop() {
+        let mut c = RngCaptcha::from_rng(StdRng::seed_from_u64(9));
+        c.add_chars(4);
+        let before: Vec<Geometry> = c.char_areas().into_iter().flatten().collect();
+        let a = c.text_area();
+        assert_eq!(before.len(), 4);
+        assert_eq!(before[0].left, a.left);
+        assert_eq!(before[3].right, a.right);
+
+        c.view(300, 100);
+        let t = c.text_area();
+        let dx = t.left as i64 - a.left as i64;
+        let dy = t.top as i64 - a.top as i64;
+        let mv = |v: u32, d: i64| (v as i64 + d) as u32;
+        assert_eq!(t.right, mv(a.right, dx));
+        assert_eq!(t.bottom, mv(a.bottom, dy));
+        for (g, b) in c.char_areas().iter().flatten().zip(before.iter()) {
+            let m = Geometry::new(
+                mv(b.left, dx),
+                mv(b.right, dx),
+                mv(b.top, dy),
+                mv(b.bottom, dy),
+            );
+            assert_eq!(*g, m);
+        }
+
+        // boxes are clipped to the image
+        let t = c.text_area();
+        c.extract(Geometry::new(0, t.left + 10, 0, t.bottom + 1));
+        let areas = c.char_areas();
+        assert_eq!(areas.len(), 4);
+        let g = areas[0].clone().unwrap();
+        assert_eq!((g.left, g.right), (t.left, t.left + 9));
+        assert!(areas[1..].iter().all(|g| g.is_none()));
+
+        // the text has been cropped completely
+        c.extract(Geometry::new(0, 5, 0, 5));
+        assert!(c.char_areas().iter().all(|g| g.is_none()));
+        assert_eq!(c.text_area(), Geometry::new(0, 0, 0, 0));
+    }
+
+    fn assert_mask_aligned<T: ::rand::Rng + ::rand::RngCore>(c: &RngCaptcha<T>) {
+        let m = c.mask().unwrap();
+        assert_eq!((m.width(), m.height()), (c.width(), c.height()));
+        for (a, b) in c.img.rows().flatten().zip(m.rows().flatten()) {
+            let dark = a.rgb().iter().map(|x| *x as u32).sum::<u32>() < 3 * 128;
+            let label = b.rgb()[0];
+            assert_eq!(dark, label != MASK_BACKGROUND);
+            assert!(label == MASK_BACKGROUND || (label as usize) < c.chars().len());
+        }
+    }
+
+    #[test]
+    fn mask() {
+        let mut c = RngCaptcha::from_rng(StdRng::seed_from_u64(5));
+        assert!(c.mask().is_none());
+        c.set_mask(true).set_rotation(20.0).add_chars(4);
+        assert_mask_aligned(&c);
+        let labels = c.mask_labels().unwrap();
+        for (k, a) in c.char_areas().iter().flatten().enumerate() {
+            let w = c.width() as usize;
+            let n = labels
+                .iter()
+                .enumerate()
+                .filter(|&(_, l)| *l as usize == k)
+                .inspect(|&(i, _)| {
+                    let (x, y) = ((i % w) as u32, (i / w) as u32);
+                    assert!(x >= a.left && x <= a.right && y >= a.top && y <= a.bottom);
+                })
+                .count();
+            assert!(n > 0);
+        }
+
+        c.apply_filter(Wave::new(2.0, 10.0).horizontal())
+            .apply_filter(Wave::new(2.0, 10.0).vertical())
+            .view(220, 120);
+        assert_mask_aligned(&c);
+
+        let c = CaptchaBuilder::new(100, 40)
+            .chars(6)
+            .mask(true)
+            .build_with_rng(StdRng::seed_from_u64(6));
+        assert_mask_aligned(&c);
+    }
+
+    #[test]
+    fn geometry_after_fit() {
+        let mut c = RngCaptcha::from_rng(StdRng::seed_from_u64(9));
+        c.add_chars(8).fit(100, 40, 0);
+        let t = c.text_area();
+        assert!(t.right < 100 && t.bottom < 40);
+        let areas: Vec<Geometry> = c.char_areas().into_iter().flatten().collect();
+        assert_eq!(areas.len(), 8);
+        assert_eq!(areas[0].left, t.left);
+        assert!(areas
+            .iter()
+            .all(|g| g.right <= t.right && g.bottom <= t.bottom));
+        assert!(areas.windows(2).all(|w| w[0].left <= w[1].left));
+    }
+
+    #[test]
+    fn verify_answer() {
+        let mut c = RngCaptcha::from_rng(StdRng::seed_from_u64(2));
+        c.set_chars(&['l', 'x']).add_chars(4);
+        let answer = c.chars_as_string();
+        assert!(c.verify(&answer));
+        assert!(c.verify(&format!(" {} ", answer)));
+        assert!(!c.verify(&answer.to_uppercase()));
+        assert!(c
+            .verifier()
+            .default_confusables()
+            .verify(&answer, &answer.replace('l', "1")));
+    }
+
+    #[cfg(feature = "audio")]
+    #[test]
+    fn audio_filters() {
+        use audio_filters::{LowPass, PitchShift};
+
+        let mut c = Captcha::new();
+        c.add_chars(3);
+        assert!(c.try_add_audio_filter(PitchShift::new(3.0, 1.0)).is_err());
+        c.add_audio_filter(PitchShift::new(3.0, 1.0))
+            .add_audio_filter(LowPass::new(3000.0));
+        assert_eq!(c.audio_filters.len(), 1);
+        assert!(c.as_audio().is_some());
+
+        let a = generate_with_rng(Difficulty::Hard, StdRng::seed_from_u64(4)).as_wav();
+        let b = generate_with_rng(Difficulty::Hard, StdRng::seed_from_u64(4)).as_wav();
+        assert_eq!(a, b);
+        assert!(a.iter().all(|x| x.is_some()));
+    }
+
+    #[cfg(feature = "audio")]
+    #[test]
+    fn by_name_keeps_audio_filters() {
+        use audio_filters::{self, LowPass};
+        use {by_name_from, CaptchaName};
+
+        let c = by_name_from(Difficulty::Hard, CaptchaName::Lucy, Captcha::new());
+        assert_eq!(
+            c.audio_filters.len(),
+            audio_filters::preset(Difficulty::Hard).len()
+        );
+
+        let mut c = Captcha::new();
+        c.add_audio_filter(LowPass::new(3000.0));
+        let c = by_name_from(Difficulty::Hard, CaptchaName::Lucy, c);
+        assert_eq!(c.audio_filters.len(), 1);
+    }
+
+    #[cfg(feature = "audio")]
+    #[test]
+    fn synth() {
+        let mut c = RngCaptcha::from_rng(StdRng::seed_from_u64(0));
+        assert!(c.try_set_synth(Synth::new().speed(5.0)).is_err());
+        c.set_synth(Synth::new().prompt("intro", "tippe Folgendes"))
+            .add_chars(3);
+        let a = c.try_as_wav().unwrap();
+        assert_eq!(a.len(), 3);
+        // each call speaks with another voice
+        assert_ne!(a, c.try_as_wav().unwrap());
+        c.set_audio_options(AudioOptions::new().intro(true));
+        assert!(c.try_as_audio().is_ok());
+        assert!(c.synth.is_some());
+        c.set_voice(VoicePack::builtin().unwrap());
+        assert!(c.synth.is_none());
+    }
+
+    #[cfg(feature = "audio")]
+    #[test]
+    fn synth_missing_char() {
+        use fonts::{Custom, Font};
+        use std::collections::HashMap;
+
+        let mut glyphs = HashMap::new();
+        glyphs.insert('x', Default::new().png('x').unwrap());
+        glyphs.insert('\u{20ac}', Default::new().png('E').unwrap());
+        let mut c = RngCaptcha::from_rng(StdRng::seed_from_u64(0));
+        c.set_font(Custom::from_pngs(glyphs))
+            .set_chars(&['\u{20ac}'])
+            .add_chars(4)
+            .set_synth(Synth::new());
+        assert_eq!(c.as_wav(), vec![None; 4]);
+        assert!(c.try_as_wav().is_err());
+        assert!(c.as_audio().is_none());
+    }
+
+    #[test]
+    fn errors() {
+        let mut c = RngCaptcha::from_rng(StdRng::seed_from_u64(0));
+        assert!(c.try_set_chars(&[]).is_err());
+        match c.try_set_chars(&['a', '\u{263a}']) {
+            Err(Error::MissingGlyph('\u{263a}')) => {}
+            _ => panic!("expected missing glyph"),
+        }
+        c.set_chars(&['\u{263a}']);
+        assert!(c.try_add_char().is_err());
+        assert!(c.add_char().chars().is_empty());
+
+        c.set_chars(&['a']);
+        c.try_add_chars(2).unwrap();
+        assert!(c.try_apply_filter(Grid::new(0, 5)).is_err());
+        assert!(c.try_apply_filter(Dots::new(3).min_radius(20)).is_err());
+        let area = Geometry::new(500, 600, 0, 10);
+        assert!(c.try_apply_filter(Cow::new().area(area)).is_err());
+        c.apply_filter(Grid::new(0, 5));
+        assert!(c.try_view(0, 10).is_err());
+        assert!(c.try_fit(10, 10, 5).is_err());
+        assert!(c.try_extract(Geometry::new(5, 5, 0, 10)).is_err());
+        c.try_view(100, 50).unwrap();
+        assert_eq!(c.try_as_tuple().unwrap().0, "aa");
+        assert!(c.try_as_base64().is_ok());
+    }
+
+    #[test]
+    fn auto_traits() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Captcha>();
+        assert_send_sync::<CaptchaBuilder>();
+        assert_send_sync::<SharedFont>();
+    }
+
+    #[test]
+    fn shared_font() {
+        let f: SharedFont = Arc::new(Default::new());
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let f = f.clone();
+                thread::spawn(move || {
+                    let mut c = Captcha::new();
+                    c.set_shared_font(f).add_chars(3);
+                    c
+                })
+            })
+            .collect();
+        for h in handles {
+            assert_eq!(h.join().unwrap().chars().len(), 3);
+        }
+        let c = thread::spawn(|| generate(Difficulty::Easy)).join().unwrap();
+        assert!(c.as_png().is_some());
+    }
+
     #[test]
     fn image_size() {
         let mut c = Captcha::new();
@@ -0,0 +1,339 @@
+//! Integration with [axum](https://docs.rs/axum) and [tower](https://docs.rs/tower).
+//!
+//! A [`CaptchaConfig`](struct.CaptchaConfig.html) connects the CAPTCHAs of this crate with a
+//! [`ChallengeStore`](../trait.ChallengeStore.html). It provides two building blocks:
+//!
+//! * The extractor [`Challenge`](struct.Challenge.html) hands a handler a new CAPTCHA whose
+//!   answer has already been stored. The handler renders the CAPTCHA and sends its id to the
+//!   client. The CAPTCHA is created on the blocking thread pool of tokio, so that the
+//!   asynchronous tasks are not blocked while it is drawn.
+//! * The layer [`CaptchaLayer`](struct.CaptchaLayer.html) protects a group of routes. A request
+//!   is passed on only if the headers `X-Captcha-Id` and `X-Captcha-Answer` contain the id of a
+//!   challenge and its correct answer, encoded as UTF-8. Otherwise the request is rejected
+//!   with `403 Forbidden`. A challenge is removed from the store as soon as it has been solved,
+//!   so each challenge admits exactly one request.
+//!
+//! This module requires the feature `axum`.
+//!
+//! # Examples
+//!
+//! ```
+//! # extern crate axum;
+//! # extern crate captcha;
+//! use axum::routing::{get, post};
+//! use axum::Router;
+//! use captcha::middleware::{CaptchaConfig, Challenge};
+//! use captcha::{Difficulty, MemoryStore};
+//! use std::future::ready;
+//! use std::sync::Arc;
+//!
+//! # fn main() {
+//! let config = CaptchaConfig::new(Arc::new(MemoryStore::new())).difficulty(Difficulty::Easy);
+//!
+//! let app: Router = Router::new()
+//!     .route("/comment", post(|| ready("thank you")))
+//!     .route_layer(config.layer())
+//!     .route(
+//!         "/challenge",
+//!         get(|c: Challenge| ready(format!("{} {}", c.id, c.captcha.as_base64().unwrap()))),
+//!     )
+//!     .with_state(config);
+//! # }
+//! ```
+
+use axum::body::Body;
+use axum::extract::{FromRef, FromRequestParts};
+use axum::http::request::Parts;
+use axum::http::{Request, StatusCode};
+use axum::response::{IntoResponse, Response};
+use std::future::{ready, Future};
+use std::pin::Pin;
+use std::str;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::task::{spawn_blocking, JoinHandle};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use store::insert_new;
+use {generate, Captcha, Difficulty, MemoryStore, SharedStore, Verdict};
+
+/// The header which contains the id of a challenge.
+pub const ID_HEADER: &str = "x-captcha-id";
+
+/// The header which contains the answer of a challenge.
+pub const ANSWER_HEADER: &str = "x-captcha-answer";
+
+/// The store and the difficulty of the challenges of an application.
+///
+/// Use the configuration as the state of a router or make it available via
+/// [`FromRef`](https://docs.rs/axum/latest/axum/extract/trait.FromRef.html) so that the
+/// extractor [`Challenge`](struct.Challenge.html) can find it.
+#[derive(Clone)]
+pub struct CaptchaConfig {
+    store: SharedStore,
+    difficulty: Difficulty,
+}
+
+impl CaptchaConfig {
+    /// Returns a configuration which keeps the answers in `store` and creates challenges with
+    /// the difficulty `Medium`.
+    pub fn new(store: SharedStore) -> CaptchaConfig {
+        CaptchaConfig {
+            store,
+            difficulty: Difficulty::Medium,
+        }
+    }
+
+    /// Sets the difficulty of the challenges.
+    pub fn difficulty(self, difficulty: Difficulty) -> Self {
+        CaptchaConfig { difficulty, ..self }
+    }
+
+    /// Returns the store of the answers.
+    pub fn store(&self) -> &SharedStore {
+        &self.store
+    }
+
+    /// Creates a new challenge with [`generate`](../fn.generate.html) and stores its answer.
+    pub fn challenge(&self) -> Challenge {
+        let captcha = generate(self.difficulty);
+        let id = insert_new(&*self.store, &captcha.chars_as_string());
+        Challenge { id, captcha }
+    }
+
+    /// Returns a layer which admits only requests with a solved challenge.
+    pub fn layer(&self) -> CaptchaLayer {
+        CaptchaLayer {
+            store: self.store.clone(),
+        }
+    }
+}
+
+impl Default for CaptchaConfig {
+    /// Returns a configuration with a [`MemoryStore`](../struct.MemoryStore.html).
+    fn default() -> Self {
+        CaptchaConfig::new(Arc::new(MemoryStore::new()))
+    }
+}
+
+/// A new challenge whose answer has been stored. See the
+/// [module documentation](index.html).
+pub struct Challenge {
+    /// The id which the client has to send in the header `X-Captcha-Id`.
+    pub id: String,
+    /// The CAPTCHA which has to be shown to the client.
+    pub captcha: Captcha,
+}
+
+impl<S> FromRequestParts<S> for Challenge
+where
+    CaptchaConfig: FromRef<S>,
+    S: Send + Sync,
+{
+    /// The status `500 Internal Server Error` if the CAPTCHA could not be created.
+    type Rejection = StatusCode;
+
+    fn from_request_parts(
+        _parts: &mut Parts,
+        state: &S,
+    ) -> impl Future<Output = Result<Self, Self::Rejection>> + Send {
+        let config = CaptchaConfig::from_ref(state);
+        NewChallenge(spawn_blocking(move || config.challenge()))
+    }
+}
+
+/// Waits for a challenge which is created on the blocking thread pool.
+struct NewChallenge(JoinHandle<Challenge>);
+
+impl Future for NewChallenge {
+    type Output = Result<Challenge, StatusCode>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        Pin::new(&mut self.0)
+            .poll(cx)
+            .map(|r| r.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR))
+    }
+}
+
+/// A layer which admits only requests with a solved challenge. Created by
+/// [`CaptchaConfig::layer`](struct.CaptchaConfig.html#method.layer).
+#[derive(Clone)]
+pub struct CaptchaLayer {
+    store: SharedStore,
+}
+
+impl<S> Layer<S> for CaptchaLayer {
+    type Service = RequireCaptcha<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequireCaptcha {
+            inner,
+            store: self.store.clone(),
+        }
+    }
+}
+
+/// The service created by [`CaptchaLayer`](struct.CaptchaLayer.html).
+#[derive(Clone)]
+pub struct RequireCaptcha<S> {
+    inner: S,
+    store: SharedStore,
+}
+
+impl<S> RequireCaptcha<S> {
+    /// Checks the headers of the request. Returns the reason if the request is rejected.
+    fn check<B>(&self, r: &Request<B>) -> Option<&'static str> {
+        let header = |k| {
+            r.headers()
+                .get(k)
+                .and_then(|v| str::from_utf8(v.as_bytes()).ok())
+        };
+        let (id, answer) = match (header(ID_HEADER), header(ANSWER_HEADER)) {
+            (Some(id), Some(answer)) => (id, answer),
+            _ => return Some("missing captcha"),
+        };
+        match self.store.consume(id, answer) {
+            Verdict::Solved => None,
+            Verdict::Wrong { .. } => Some("wrong captcha answer"),
+            Verdict::Unknown => Some("unknown captcha"),
+        }
+    }
+}
+
+impl<S> Service<Request<Body>> for RequireCaptcha<S>
+where
+    S: Service<Request<Body>, Response = Response>,
+    S::Error: Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, r: Request<Body>) -> Self::Future {
+        match self.check(&r) {
+            None => Box::pin(self.inner.call(r)),
+            Some(reason) => Box::pin(ready(Ok((StatusCode::FORBIDDEN, reason).into_response()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{HeaderValue, Request, StatusCode};
+    use axum::routing::get;
+    use axum::Router;
+    use middleware::{CaptchaConfig, Challenge};
+    use std::future::{ready, Future};
+    use std::sync::Arc;
+    use tokio::runtime::Builder;
+    use tower::ServiceExt;
+    use {ChallengeStore, Difficulty, MemoryStore};
+
+    fn block_on<F: Future>(f: F) -> F::Output {
+        Builder::new_current_thread().build().unwrap().block_on(f)
+    }
+
+    fn app(config: CaptchaConfig) -> Router {
+        Router::new()
+            .route("/secret", get(|| ready("secret")))
+            .route_layer(config.layer())
+            .route(
+                "/challenge",
+                get(|c: Challenge| ready(format!("{} {}", c.id, c.captcha.chars_as_string()))),
+            )
+            .with_state(config)
+    }
+
+    /// Sends a request and returns the status and the body of the response.
+    fn send(app: &Router, path: &str, headers: &[(&str, &str)]) -> (StatusCode, String) {
+        let mut r = Request::get(path);
+        for &(k, v) in headers {
+            r = r.header(k, HeaderValue::from_bytes(v.as_bytes()).unwrap());
+        }
+        let r = r.body(Body::empty()).unwrap();
+        let response = block_on(app.clone().oneshot(r)).unwrap();
+        let status = response.status();
+        let body = block_on(to_bytes(response.into_body(), usize::MAX)).unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[test]
+    fn protect() {
+        let store = Arc::new(MemoryStore::new());
+        let config = CaptchaConfig::new(store.clone()).difficulty(Difficulty::Easy);
+        let app = app(config);
+
+        let (status, body) = send(&app, "/challenge", &[]);
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(store.len(), 1);
+        let mut it = body.split(' ');
+        let (id, answer) = (it.next().unwrap(), it.next().unwrap());
+        assert_eq!(id.len(), 32);
+
+        assert_eq!(send(&app, "/secret", &[]).0, StatusCode::FORBIDDEN);
+        let wrong = [("x-captcha-id", id), ("x-captcha-answer", "-")];
+        assert_eq!(send(&app, "/secret", &wrong).0, StatusCode::FORBIDDEN);
+        let right = [("x-captcha-id", id), ("x-captcha-answer", answer)];
+        assert_eq!(
+            send(&app, "/secret", &right),
+            (StatusCode::OK, "secret".to_string())
+        );
+        // A challenge admits only one request.
+        assert_eq!(send(&app, "/secret", &right).0, StatusCode::FORBIDDEN);
+
+        store.insert("known", "abc");
+        let other = [("x-captcha-id", "known"), ("x-captcha-answer", "abc")];
+        assert_eq!(send(&app, "/secret", &other).0, StatusCode::OK);
+    }
+
+    #[test]
+    fn rejections() {
+        let store = Arc::new(MemoryStore::new());
+        let app = app(CaptchaConfig::new(store.clone()));
+        let forbidden = |reason: &str| (StatusCode::FORBIDDEN, reason.to_string());
+
+        store.insert("a", "abc");
+        assert_eq!(send(&app, "/secret", &[]), forbidden("missing captcha"));
+        let id_only = [("x-captcha-id", "a")];
+        assert_eq!(
+            send(&app, "/secret", &id_only),
+            forbidden("missing captcha")
+        );
+        let answer_only = [("x-captcha-answer", "abc")];
+        assert_eq!(
+            send(&app, "/secret", &answer_only),
+            forbidden("missing captcha")
+        );
+
+        let wrong = [("x-captcha-id", "a"), ("x-captcha-answer", "abd")];
+        assert_eq!(
+            send(&app, "/secret", &wrong),
+            forbidden("wrong captcha answer")
+        );
+        let unknown = [("x-captcha-id", "b"), ("x-captcha-answer", "abc")];
+        assert_eq!(
+            send(&app, "/secret", &unknown),
+            forbidden("unknown captcha")
+        );
+
+        let right = [("x-captcha-id", "a"), ("x-captcha-answer", "abc")];
+        assert_eq!(send(&app, "/secret", &right).0, StatusCode::OK);
+        assert_eq!(send(&app, "/secret", &right), forbidden("unknown captcha"));
+    }
+
+    #[test]
+    fn non_ascii_answer() {
+        let store = Arc::new(MemoryStore::new());
+        let app = app(CaptchaConfig::new(store.clone()));
+        store.insert("a", "äöß");
+        let right = [("x-captcha-id", "a"), ("x-captcha-answer", "äöß")];
+        assert_eq!(send(&app, "/secret", &right).0, StatusCode::OK);
+    }
+}
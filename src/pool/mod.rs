@@ -0,0 +1,391 @@
+//! A pool of pre-generated CAPTCHAs.
+//!
+//! Creating a CAPTCHA takes a few milliseconds. A [`CaptchaPool`](struct.CaptchaPool.html)
+//! moves this work off the request path: worker threads keep a bounded queue of ready-made
+//! CAPTCHAs for each configured kind and refill it in the background.
+//!
+//! # Examples
+//!
+//! ```
+//! # extern crate captcha;
+//! use captcha::{CaptchaName, Difficulty, PoolBuilder};
+//!
+//! # fn main() {
+//! let pool = PoolBuilder::new()
+//!     .capacity(10)
+//!     .workers(2)
+//!     .difficulty(Difficulty::Hard)
+//!     .captcha(Difficulty::Easy, CaptchaName::Lucy)
+//!     .build();
+//!
+//! let (answer, png) = pool.pop(Difficulty::Hard).expect("could not create CAPTCHA");
+//! let (answer, png) = pool
+//!     .pop_by_name(Difficulty::Easy, CaptchaName::Lucy)
+//!     .expect("could not create CAPTCHA");
+//! # }
+//! ```
+
+use std::cmp::min;
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use {by_name, generate, CaptchaName, Difficulty, Result};
+
+/// The kind of the CAPTCHAs in a queue. If no name is given the CAPTCHAs are created with
+/// [`generate`](fn.generate.html), otherwise with [`by_name`](fn.by_name.html).
+type Kind = (Difficulty, Option<CaptchaName>);
+
+/// Creates the answer and the PNG image of a CAPTCHA.
+type Create = fn(Kind) -> Result<(String, Vec<u8>)>;
+
+/// The time a worker waits before it retries a kind whose CAPTCHA could not be created. The
+/// time is doubled with each further failure up to `MAX_BACKOFF`.
+const MIN_BACKOFF: Duration = Duration::from_millis(10);
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+fn create(k: Kind) -> Result<(String, Vec<u8>)> {
+    match k {
+        (d, None) => generate(d).try_as_tuple(),
+        (d, Some(n)) => by_name(d, n).try_as_tuple(),
+    }
+}
+
+/// The fill level of a queue of a [`CaptchaPool`](struct.CaptchaPool.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolStats {
+    /// The difficulty of the CAPTCHAs in the queue.
+    pub difficulty: Difficulty,
+    /// The name of the CAPTCHAs in the queue or `None` if random CAPTCHAs are created.
+    pub name: Option<CaptchaName>,
+    /// The number of CAPTCHAs which are ready.
+    pub ready: usize,
+    /// The maximum number of CAPTCHAs in the queue.
+    pub capacity: usize,
+    /// The number of CAPTCHAs which have been taken from the queue.
+    pub hits: u64,
+    /// The number of CAPTCHAs which have been created on the fly because the queue was empty.
+    pub misses: u64,
+    /// The number of CAPTCHAs which the worker threads could not create. After a failure the
+    /// queue is refilled again after a delay which grows with each further failure.
+    pub errors: u64,
+}
+
+struct Queue {
+    kind: Kind,
+    ready: VecDeque<(String, Vec<u8>)>,
+    hits: u64,
+    misses: u64,
+    errors: u64,
+    /// The number of failures since the last CAPTCHA has been created.
+    failures: u32,
+    /// The queue is not refilled before this time.
+    retry: Option<Instant>,
+}
+
+struct State {
+    queues: Vec<Queue>,
+    shutdown: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    refill: Condvar,
+    capacity: usize,
+    create: Create,
+}
+
+impl Shared {
+    /// Returns the index of the queue with the lowest fill level which is not full and which
+    /// is not waiting for a retry.
+    fn next_queue(&self, s: &State, now: Instant) -> Option<usize> {
+        s.queues
+            .iter()
+            .enumerate()
+            .filter(|&(_, q)| q.ready.len() < self.capacity)
+            .filter(|&(_, q)| q.retry.is_none_or(|t| t <= now))
+            .min_by_key(|&(_, q)| q.ready.len())
+            .map(|(i, _)| i)
+    }
+
+    fn work(&self) {
+        let mut s = self.state.lock().expect("lock poisoned");
+        loop {
+            if s.shutdown {
+                return;
+            }
+            let now = Instant::now();
+            let i = match self.next_queue(&s, now) {
+                Some(i) => i,
+                None => {
+                    let retry = s
+                        .queues
+                        .iter()
+                        .filter(|q| q.ready.len() < self.capacity)
+                        .filter_map(|q| q.retry)
+                        .min();
+                    s = match retry {
+                        Some(t) => {
+                            self.refill
+                                .wait_timeout(s, t - now)
+                                .expect("lock poisoned")
+                                .0
+                        }
+                        None => self.refill.wait(s).expect("lock poisoned"),
+                    };
+                    continue;
+                }
+            };
+            let kind = s.queues[i].kind;
+            drop(s);
+            let c = (self.create)(kind);
+            s = self.state.lock().expect("lock poisoned");
+            let q = &mut s.queues[i];
+            match c {
+                Ok(c) => {
+                    q.failures = 0;
+                    q.retry = None;
+                    if q.ready.len() < self.capacity {
+                        q.ready.push_back(c);
+                    }
+                }
+                Err(_) => {
+                    q.errors += 1;
+                    let backoff = MIN_BACKOFF * 2u32.pow(min(q.failures, 10));
+                    q.failures += 1;
+                    q.retry = Some(Instant::now() + min(backoff, MAX_BACKOFF));
+                }
+            }
+        }
+    }
+}
+
+/// Configures and starts a [`CaptchaPool`](struct.CaptchaPool.html).
+pub struct PoolBuilder {
+    capacity: usize,
+    workers: usize,
+    kinds: Vec<Kind>,
+}
+
+impl PoolBuilder {
+    /// Returns a builder for a pool with one worker thread and a capacity of 100 CAPTCHAs per
+    /// kind. No CAPTCHAs are pre-generated until a kind is added.
+    pub fn new() -> PoolBuilder {
+        PoolBuilder {
+            capacity: 100,
+            workers: 1,
+            kinds: vec![],
+        }
+    }
+
+    /// Sets the maximum number of ready CAPTCHAs for each kind.
+    pub fn capacity(self, capacity: usize) -> Self {
+        PoolBuilder { capacity, ..self }
+    }
+
+    /// Sets the number of worker threads which refill the pool.
+    pub fn workers(self, workers: usize) -> Self {
+        PoolBuilder { workers, ..self }
+    }
+
+    /// Pre-generates random CAPTCHAs of the given difficulty (see
+    /// [`generate`](fn.generate.html)).
+    pub fn difficulty(self, d: Difficulty) -> Self {
+        self.kind((d, None))
+    }
+
+    /// Pre-generates predefined CAPTCHAs of the given difficulty (see
+    /// [`by_name`](fn.by_name.html)).
+    pub fn captcha(self, d: Difficulty, t: CaptchaName) -> Self {
+        self.kind((d, Some(t)))
+    }
+
+    fn kind(mut self, k: Kind) -> Self {
+        if !self.kinds.contains(&k) {
+            self.kinds.push(k);
+        }
+        self
+    }
+
+    /// Starts the worker threads and returns the pool.
+    pub fn build(self) -> CaptchaPool {
+        self.build_with(create)
+    }
+
+    fn build_with(self, create: Create) -> CaptchaPool {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                queues: self
+                    .kinds
+                    .iter()
+                    .map(|&kind| Queue {
+                        kind,
+                        ready: VecDeque::with_capacity(self.capacity),
+                        hits: 0,
+                        misses: 0,
+                        errors: 0,
+                        failures: 0,
+                        retry: None,
+                    })
+                    .collect(),
+                shutdown: false,
+            }),
+            refill: Condvar::new(),
+            capacity: self.capacity,
+            create,
+        });
+        let workers = (0..self.workers)
+            .map(|_| {
+                let s = shared.clone();
+                thread::spawn(move || s.work())
+            })
+            .collect();
+        CaptchaPool { shared, workers }
+    }
+}
+
+impl Default for PoolBuilder {
+    fn default() -> Self {
+        PoolBuilder::new()
+    }
+}
+
+/// A pool of pre-generated CAPTCHAs which is refilled by background threads.
+///
+/// The worker threads are stopped when the pool is dropped.
+pub struct CaptchaPool {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl CaptchaPool {
+    fn take(&self, k: Kind) -> Result<(String, Vec<u8>)> {
+        {
+            let mut s = self.shared.state.lock().expect("lock poisoned");
+            if let Some(q) = s.queues.iter_mut().find(|q| q.kind == k) {
+                match q.ready.pop_front() {
+                    Some(c) => {
+                        q.hits += 1;
+                        self.shared.refill.notify_one();
+                        return Ok(c);
+                    }
+                    None => q.misses += 1,
+                }
+            }
+        }
+        (self.shared.create)(k)
+    }
+
+    /// Returns the answer and the PNG image of a random CAPTCHA of the given difficulty.
+    ///
+    /// The CAPTCHA is taken from the pool. If the pool is empty or does not contain CAPTCHAs of
+    /// this difficulty, the CAPTCHA is created on the fly.
+    pub fn pop(&self, d: Difficulty) -> Result<(String, Vec<u8>)> {
+        self.take((d, None))
+    }
+
+    /// Returns the answer and the PNG image of a predefined CAPTCHA.
+    ///
+    /// See [`pop`](#method.pop).
+    pub fn pop_by_name(&self, d: Difficulty, t: CaptchaName) -> Result<(String, Vec<u8>)> {
+        self.take((d, Some(t)))
+    }
+
+    /// Returns the fill level of each queue in the order in which the kinds have been added.
+    pub fn stats(&self) -> Vec<PoolStats> {
+        let s = self.shared.state.lock().expect("lock poisoned");
+        s.queues
+            .iter()
+            .map(|q| PoolStats {
+                difficulty: q.kind.0,
+                name: q.kind.1,
+                ready: q.ready.len(),
+                capacity: self.shared.capacity,
+                hits: q.hits,
+                misses: q.misses,
+                errors: q.errors,
+            })
+            .collect()
+    }
+}
+
+impl Drop for CaptchaPool {
+    fn drop(&mut self) {
+        self.shared.state.lock().expect("lock poisoned").shutdown = true;
+        self.shared.refill.notify_all();
+        for w in self.workers.drain(..) {
+            let _ = w.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pool::{Kind, PoolBuilder};
+    use std::thread::sleep;
+    use std::time::{Duration, Instant};
+    use {CaptchaName, Difficulty, Error, Image, Result};
+
+    #[test]
+    fn refill() {
+        let p = PoolBuilder::new()
+            .capacity(3)
+            .workers(2)
+            .difficulty(Difficulty::Easy)
+            .captcha(Difficulty::Medium, CaptchaName::Mila)
+            .build();
+        let start = Instant::now();
+        while p.stats().iter().any(|s| s.ready < 3) {
+            assert!(start.elapsed() < Duration::from_secs(30));
+            sleep(Duration::from_millis(10));
+        }
+
+        let (answer, png) = p.pop(Difficulty::Easy).unwrap();
+        assert!(answer.len() >= 4);
+        assert!(Image::from_png(png).is_some());
+        p.pop_by_name(Difficulty::Medium, CaptchaName::Mila)
+            .unwrap();
+
+        let s = p.stats();
+        assert_eq!(s.len(), 2);
+        assert_eq!((s[0].difficulty, s[0].name), (Difficulty::Easy, None));
+        assert_eq!(s[0].hits, 1);
+        assert_eq!(s[0].misses, 0);
+        assert_eq!(s[0].capacity, 3);
+    }
+
+    #[test]
+    fn fallback() {
+        let p = PoolBuilder::new()
+            .workers(0)
+            .difficulty(Difficulty::Hard)
+            .build();
+        assert!(p.pop(Difficulty::Hard).is_ok());
+        assert!(p.pop(Difficulty::Easy).is_ok());
+        let s = p.stats();
+        assert_eq!(s.len(), 1);
+        assert_eq!((s[0].ready, s[0].hits, s[0].misses), (0, 0, 1));
+    }
+
+    #[test]
+    fn backoff() {
+        fn fail(_: Kind) -> Result<(String, Vec<u8>)> {
+            Err(Error::Encoding("failed".to_string()))
+        }
+        let p = PoolBuilder::new()
+            .workers(1)
+            .difficulty(Difficulty::Easy)
+            .build_with(fail);
+        sleep(Duration::from_millis(100));
+        let s = p.stats();
+        assert_eq!(s[0].ready, 0);
+        // 10 + 20 + 40 + 80 ms
+        assert!(
+            s[0].errors >= 2 && s[0].errors <= 5,
+            "{} errors",
+            s[0].errors
+        );
+        assert!(p.pop(Difficulty::Easy).is_err());
+    }
+}
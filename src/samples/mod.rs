@@ -14,13 +14,18 @@
 //! # }
 //! ```
 use filters::{Cow, Dots, Grid, Noise, Wave};
-use rand::{rng, Rng};
-use {Captcha, Geometry};
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use std::str::FromStr;
+use {default_rng, Captcha, Error, Geometry, RngCaptcha};
 
 const WIDTH: u32 = 220;
 const HEIGHT: u32 = 120;
 
 /// The difficulty of a CAPTCHA.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Difficulty {
     Easy,
     Medium,
@@ -28,14 +33,49 @@ pub enum Difficulty {
 }
 
 /// Names of predefined CAPTCHAs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum CaptchaName {
     Amelia,
     Lucy,
     Mila,
 }
 
-static CAPTCHA_FUNCTIONS: &[fn(Difficulty) -> Captcha] =
-    &[captcha_amelia, captcha_lucy, captcha_mila];
+static CAPTCHA_NAMES: &[CaptchaName] = &[CaptchaName::Amelia, CaptchaName::Lucy, CaptchaName::Mila];
+
+impl FromStr for Difficulty {
+    type Err = Error;
+
+    /// Parses the name of a difficulty, e.g. `easy`. Upper and lower case are ignored.
+    fn from_str(s: &str) -> Result<Difficulty, Error> {
+        match s.to_lowercase().as_str() {
+            "easy" => Ok(Difficulty::Easy),
+            "medium" => Ok(Difficulty::Medium),
+            "hard" => Ok(Difficulty::Hard),
+            _ => Err(Error::invalid(format!("unknown difficulty: {}", s))),
+        }
+    }
+}
+
+impl CaptchaName {
+    /// Returns the names of all predefined CAPTCHAs.
+    pub fn all() -> &'static [CaptchaName] {
+        CAPTCHA_NAMES
+    }
+}
+
+impl FromStr for CaptchaName {
+    type Err = Error;
+
+    /// Parses the name of a predefined CAPTCHA, e.g. `amelia`. Upper and lower case are
+    /// ignored.
+    fn from_str(s: &str) -> Result<CaptchaName, Error> {
+        CAPTCHA_NAMES
+            .iter()
+            .find(|n| format!("{:?}", n).eq_ignore_ascii_case(s))
+            .cloned()
+            .ok_or_else(|| Error::invalid(format!("unknown CAPTCHA name: {}", s)))
+    }
+}
 
 /// Creates a random CAPTCHA with the given difficulty.
 ///
@@ -44,8 +84,106 @@ static CAPTCHA_FUNCTIONS: &[fn(Difficulty) -> Captcha] =
 ///
 /// If you need more flexibility please have a look at [`Captcha`](../struct.Captcha.html).
 pub fn generate(d: Difficulty) -> Captcha {
-    let m = CAPTCHA_FUNCTIONS.len();
-    CAPTCHA_FUNCTIONS[rng().random::<u32>() as usize % m](d)
+    generate_with_rng(d, default_rng())
+}
+
+/// Creates a random CAPTCHA with the given difficulty using `rng` as the source of randomness.
+///
+/// All random decisions, i.e. the predefined CAPTCHA, the characters and the filters, are
+/// taken from `rng`. Hence, the same seed always results in the same CAPTCHA.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate captcha;
+/// # extern crate rand;
+/// use captcha::{generate_with_rng, Difficulty};
+/// use rand::rngs::StdRng;
+/// use rand::SeedableRng;
+///
+/// # fn main() {
+/// let a = generate_with_rng(Difficulty::Hard, StdRng::seed_from_u64(42)).as_png();
+/// let b = generate_with_rng(Difficulty::Hard, StdRng::seed_from_u64(42)).as_png();
+/// assert_eq!(a, b);
+/// # }
+/// ```
+pub fn generate_with_rng<T: Rng + RngCore>(d: Difficulty, mut rng: T) -> RngCaptcha<T> {
+    let t = random_name(&mut rng);
+    by_name_with_rng(d, t, rng)
+}
+
+fn random_name<T: Rng + RngCore>(rng: &mut T) -> CaptchaName {
+    let m = CAPTCHA_NAMES.len();
+    CAPTCHA_NAMES[rng.random::<u32>() as usize % m]
+}
+
+/// Creates `n` random CAPTCHAs of the given difficulty.
+///
+/// Each CAPTCHA is created with its own random number generator which is seeded from `seed`
+/// and the position of the CAPTCHA in the batch. Hence, the same seed always results in the same
+/// batch. The CAPTCHAs are the first `n` CAPTCHAs of [`generate_iter`](fn.generate_iter.html).
+///
+/// With the feature `rayon` the CAPTCHAs are created in parallel.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate captcha;
+/// use captcha::{generate_batch, Difficulty};
+///
+/// # fn main() {
+/// let batch = generate_batch(10, Difficulty::Medium, 42);
+/// assert_eq!(batch.len(), 10);
+/// # }
+/// ```
+pub fn generate_batch(n: usize, d: Difficulty, seed: u64) -> Vec<Captcha> {
+    #[cfg(feature = "rayon")]
+    let batch = (0..n as u64)
+        .into_par_iter()
+        .map(|i| generate_nth(d, seed, i).1)
+        .collect();
+    #[cfg(not(feature = "rayon"))]
+    let batch = generate_iter(d, seed).take(n).collect();
+    batch
+}
+
+/// Returns an endless iterator over random CAPTCHAs of the given difficulty.
+///
+/// See [`generate_batch`](fn.generate_batch.html).
+pub fn generate_iter(d: Difficulty, seed: u64) -> impl Iterator<Item = Captcha> {
+    (0..).map(move |i| generate_nth(d, seed, i).1)
+}
+
+/// Creates the CAPTCHA at position `i` of [`generate_iter`](fn.generate_iter.html) and returns
+/// it together with the name of the predefined CAPTCHA which has been used.
+///
+/// This allows to create a part of a batch without creating the CAPTCHAs before it.
+pub fn generate_nth(d: Difficulty, seed: u64, i: u64) -> (CaptchaName, Captcha) {
+    let mut rng = nth_rng(seed, i);
+    let t = random_name(&mut rng);
+    (t, by_name_with_rng(d, t, rng))
+}
+
+/// Creates a predefined CAPTCHA with the random number generator which
+/// [`generate_nth`](fn.generate_nth.html) uses for the position `i`.
+pub fn by_name_nth(d: Difficulty, t: CaptchaName, seed: u64, i: u64) -> Captcha {
+    by_name_with_rng(d, t, nth_rng(seed, i))
+}
+
+/// Returns the random number generator for the CAPTCHA at position `i` of the sequence for
+/// `seed`.
+///
+/// The seeds of the generators are the outputs of a SplitMix64 generator whose state is
+/// initialised from the hashed `seed`, so the sequences of different seeds do not share seeds.
+fn nth_rng(seed: u64, i: u64) -> StdRng {
+    let s = splitmix64(splitmix64(seed).wrapping_add(i.wrapping_mul(0x9e37_79b9_7f4a_7c15)));
+    StdRng::seed_from_u64(s)
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    let x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
 }
 
 // TODO document easy/medium/hard
@@ -111,22 +249,64 @@ pub fn generate(d: Difficulty) -> Captcha {
 /// </div>
 ///
 pub fn by_name(d: Difficulty, t: CaptchaName) -> Captcha {
+    by_name_with_rng(d, t, default_rng())
+}
+
+/// Creates a predefined CAPTCHA by its name using `rng` as the source of randomness.
+///
+/// See [`by_name`](fn.by_name.html) and [`generate_with_rng`](fn.generate_with_rng.html).
+pub fn by_name_with_rng<T: Rng + RngCore>(d: Difficulty, t: CaptchaName, rng: T) -> RngCaptcha<T> {
+    by_name_from(d, t, RngCaptcha::from_rng(rng))
+}
+
+/// Draws a predefined CAPTCHA onto the empty CAPTCHA `c`.
+///
+/// This allows to configure the CAPTCHA before the predefined CAPTCHA is drawn, e.g. to use
+/// another font or to enable the segmentation mask. If `c` has no audio filters, the audio
+/// filters of the difficulty are added (see
+/// [`set_audio_difficulty`](struct.RngCaptcha.html#method.set_audio_difficulty)); otherwise
+/// its audio filters are kept.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate captcha;
+/// use captcha::{by_name_from, Captcha, CaptchaName, Difficulty};
+///
+/// # fn main() {
+/// let mut c = Captcha::new();
+/// c.set_mask(true);
+/// let c = by_name_from(Difficulty::Medium, CaptchaName::Lucy, c);
+/// assert!(c.mask().is_some());
+/// # }
+/// ```
+pub fn by_name_from<T: Rng + RngCore>(
+    d: Difficulty,
+    t: CaptchaName,
+    #[cfg_attr(not(feature = "audio"), allow(unused_mut))] mut c: RngCaptcha<T>,
+) -> RngCaptcha<T> {
+    #[cfg(feature = "audio")]
+    {
+        if c.audio_filters.is_empty() {
+            c.set_audio_difficulty(d);
+        }
+    }
     match t {
-        CaptchaName::Amelia => captcha_amelia(d),
-        CaptchaName::Lucy => captcha_lucy(d),
-        CaptchaName::Mila => captcha_mila(d),
+        CaptchaName::Amelia => captcha_amelia(d, c),
+        CaptchaName::Lucy => captcha_lucy(d, c),
+        CaptchaName::Mila => captcha_mila(d, c),
     }
 }
 
 // -------------------------------------------
 
-fn rnd() -> u32 {
-    rng().random_range(4..7)
+fn rnd<T: Rng>(rng: &mut T) -> u32 {
+    rng.random_range(4..7)
 }
 
-fn captcha_amelia(d: Difficulty) -> Captcha {
-    let mut c = Captcha::new();
-    c.add_chars(rnd());
+fn captcha_amelia<T: Rng + RngCore>(d: Difficulty, mut c: RngCaptcha<T>) -> RngCaptcha<T> {
+    let n = rnd(&mut c.rng);
+    c.add_chars(n);
     match d {
         Difficulty::Easy => c
             .apply_filter(Noise::new(0.2))
@@ -147,24 +327,24 @@ fn captcha_amelia(d: Difficulty) -> Captcha {
     c
 }
 
-fn captcha_lucy(d: Difficulty) -> Captcha {
+fn captcha_lucy<T: Rng + RngCore>(d: Difficulty, mut c: RngCaptcha<T>) -> RngCaptcha<T> {
     let (n, g) = match d {
         Difficulty::Easy => (0.1, 8),
         Difficulty::Medium => (0.4, 6),
         Difficulty::Hard => (0.6, 4),
     };
 
-    let mut c = Captcha::new();
-    c.add_chars(rnd())
+    let k = rnd(&mut c.rng);
+    c.add_chars(k)
         .apply_filter(Noise::new(n))
         .apply_filter(Grid::new(g, g))
         .view(WIDTH, HEIGHT);
     c
 }
 
-fn captcha_mila(d: Difficulty) -> Captcha {
-    let mut c = Captcha::new();
-    c.add_chars(rnd());
+fn captcha_mila<T: Rng + RngCore>(d: Difficulty, mut c: RngCaptcha<T>) -> RngCaptcha<T> {
+    let n = rnd(&mut c.rng);
+    c.add_chars(n);
     match d {
         Difficulty::Easy => c.apply_filter(Noise::new(0.2)),
         Difficulty::Medium => c.apply_filter(Noise::new(0.3)),
@@ -0,0 +1,324 @@
+//! A small HTTP server which hands out CAPTCHAs and checks the answers.
+//!
+//! The server allows services which are not written in Rust to use this crate via HTTP. It
+//! provides two endpoints:
+//!
+//! * `GET /challenge` creates a new CAPTCHA and stores its answer in a
+//!   [`ChallengeStore`](../trait.ChallengeStore.html). The response is a JSON object with the
+//!   id of the challenge and the image as a data URI, e.g.
+//!   `{"id": "…", "image": "data:image/png;base64,…"}`. The following query parameters are
+//!   supported:
+//!   * `difficulty=easy|medium|hard` overrides the default difficulty of the server.
+//!   * `format=png` returns the PNG image instead of JSON. The id is sent in the header
+//!     `X-Challenge-Id`.
+//!   * `audio=1` adds the field `audio` with one WAV data URI per character. This requires the
+//!     feature `audio`.
+//! * `POST /verify` checks an answer. The body is a JSON object `{"id": "…", "answer": "…"}`.
+//!   The response is `{"result": "solved"}`, `{"result": "wrong", "attempts_left": n}` or
+//!   `{"result": "unknown"}`. A challenge is removed from the store as soon as it has been
+//!   solved. Bodies which are larger than 4 KiB are rejected with the status 413.
+//!
+//! This module requires the feature `server`.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # extern crate captcha;
+//! use captcha::server::ChallengeServer;
+//! use captcha::Difficulty;
+//!
+//! # fn main() {
+//! let server = ChallengeServer::bind("127.0.0.1:8080")
+//!     .expect("could not bind")
+//!     .difficulty(Difficulty::Medium);
+//! server.run();
+//! # }
+//! ```
+
+#[cfg(feature = "audio")]
+use base64;
+use serde_json::{self, json, Value};
+use std::io::{self, Read};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use store::insert_new;
+use {generate, Difficulty, Error, MemoryStore, Result, SharedStore, Verdict};
+
+/// The maximum size of the body of a request in bytes.
+const MAX_BODY: usize = 4096;
+
+/// An HTTP server for challenges. See the [module documentation](index.html).
+pub struct ChallengeServer {
+    server: Server,
+    store: SharedStore,
+    difficulty: Difficulty,
+}
+
+impl ChallengeServer {
+    /// Creates a server which listens on the given address, e.g. `127.0.0.1:8080`. Use port 0
+    /// to let the operating system choose a free port.
+    ///
+    /// By default the challenges are stored in a [`MemoryStore`](../struct.MemoryStore.html)
+    /// and have the difficulty `Medium`.
+    pub fn bind(addr: &str) -> Result<ChallengeServer> {
+        let server = Server::http(addr).map_err(|e| Error::Io(io::Error::other(e.to_string())))?;
+        Ok(ChallengeServer {
+            server,
+            store: Arc::new(MemoryStore::new()),
+            difficulty: Difficulty::Medium,
+        })
+    }
+
+    /// Sets the store for the answers of the challenges.
+    pub fn store(self, store: SharedStore) -> Self {
+        ChallengeServer { store, ..self }
+    }
+
+    /// Sets the difficulty of challenges for which no difficulty is requested.
+    pub fn difficulty(self, difficulty: Difficulty) -> Self {
+        ChallengeServer { difficulty, ..self }
+    }
+
+    /// Returns the address on which the server listens.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    /// Handles requests until [`shutdown`](#method.shutdown) is called.
+    ///
+    /// The method can be called from several threads to handle requests in parallel.
+    pub fn run(&self) {
+        for r in self.server.incoming_requests() {
+            self.handle(r);
+        }
+    }
+
+    /// Stops one thread which is running [`run`](#method.run).
+    pub fn shutdown(&self) {
+        self.server.unblock();
+    }
+
+    fn handle(&self, mut r: Request) {
+        let url = r.url().to_string();
+        let (path, query) = match url.find('?') {
+            Some(i) => (&url[..i], &url[i + 1..]),
+            None => (&url[..], ""),
+        };
+        let response = match (r.method(), path) {
+            (&Method::Get, "/challenge") => self.challenge(query),
+            (&Method::Post, "/verify") => {
+                let too_large = Err((413, "request body too large".to_string()));
+                let mut body = String::new();
+                if r.body_length().is_some_and(|n| n > MAX_BODY) {
+                    too_large
+                } else {
+                    let limit = MAX_BODY as u64 + 1;
+                    match r.as_reader().take(limit).read_to_string(&mut body) {
+                        Ok(n) if n > MAX_BODY => too_large,
+                        Ok(_) => self.verify(&body),
+                        Err(e) => Err((400, e.to_string())),
+                    }
+                }
+            }
+            (_, "/challenge") | (_, "/verify") => Err((405, "method not allowed".to_string())),
+            _ => Err((404, "not found".to_string())),
+        };
+        let response = match response {
+            Ok(x) => x,
+            Err((status, msg)) => json_response(status, &json!({ "error": msg })),
+        };
+        let _ = r.respond(response);
+    }
+
+    fn challenge(&self, query: &str) -> ::std::result::Result<HttpResponse, (u16, String)> {
+        let bad = |e: Error| (400, e.to_string());
+        let mut difficulty = self.difficulty;
+        let mut png = false;
+        let mut audio = false;
+        for (k, v) in query.split('&').filter_map(|p| split_pair(p, '=')) {
+            match k {
+                "difficulty" => difficulty = v.parse().map_err(bad)?,
+                "format" => png = v == "png",
+                "audio" => audio = v == "1" || v == "true",
+                _ => {}
+            }
+        }
+
+        #[cfg_attr(not(feature = "audio"), allow(unused_mut))]
+        let mut c = generate(difficulty);
+        let internal = |e: Error| (500, e.to_string());
+
+        if png {
+            let data = c.try_as_png().map_err(internal)?;
+            let id = insert_new(&*self.store, &c.chars_as_string());
+            let r = Response::from_data(data)
+                .with_chunked_threshold(usize::MAX)
+                .with_header(header("Content-Type", "image/png"))
+                .with_header(header("X-Challenge-Id", &id))
+                .with_header(header("Cache-Control", "no-store"));
+            return Ok(r);
+        }
+
+        let image = c.try_as_base64().map_err(internal)?;
+        let mut v = json!({
+            "image": format!("data:image/png;base64,{}", image),
+        });
+        if audio {
+            v["audio"] = wav_uris(&mut c)?;
+        }
+        v["id"] = json!(insert_new(&*self.store, &c.chars_as_string()));
+        Ok(json_response(200, &v))
+    }
+
+    fn verify(&self, body: &str) -> ::std::result::Result<HttpResponse, (u16, String)> {
+        let v: Value = serde_json::from_str(body).map_err(|e| (400, e.to_string()))?;
+        let field = |k: &str| {
+            v[k].as_str()
+                .ok_or_else(|| (400, format!("missing field {}", k)))
+        };
+        let r = match self.store.consume(field("id")?, field("answer")?) {
+            Verdict::Solved => json!({ "result": "solved" }),
+            Verdict::Wrong { attempts_left } => {
+                json!({ "result": "wrong", "attempts_left": attempts_left })
+            }
+            Verdict::Unknown => json!({ "result": "unknown" }),
+        };
+        Ok(json_response(200, &r))
+    }
+}
+
+type HttpResponse = Response<::std::io::Cursor<Vec<u8>>>;
+
+#[cfg(feature = "audio")]
+fn wav_uris(c: &mut ::Captcha) -> ::std::result::Result<Value, (u16, String)> {
+    let wavs = c.try_as_wav().map_err(|e| (500, e.to_string()))?;
+    let mime = c.audio_mime_type();
+    Ok(wavs
+        .iter()
+        .map(|w| Value::from(format!("data:{};base64,{}", mime, base64::encode(w))))
+        .collect())
+}
+
+#[cfg(not(feature = "audio"))]
+fn wav_uris(_c: &mut ::Captcha) -> ::std::result::Result<Value, (u16, String)> {
+    Err((400, "audio is not supported by this server".to_string()))
+}
+
+fn split_pair(s: &str, sep: char) -> Option<(&str, &str)> {
+    s.find(sep).map(|i| (&s[..i], &s[i + 1..]))
+}
+
+fn header(k: &str, v: &str) -> Header {
+    Header::from_bytes(k.as_bytes(), v.as_bytes()).expect("valid header")
+}
+
+fn json_response(status: u16, v: &Value) -> HttpResponse {
+    // The length of the body is known, so the body is never sent in chunks. This keeps
+    // clients simple.
+    Response::from_string(v.to_string())
+        .with_chunked_threshold(usize::MAX)
+        .with_status_code(status)
+        .with_header(header("Content-Type", "application/json"))
+        .with_header(header("Cache-Control", "no-store"))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{self, Value};
+    use server::ChallengeServer;
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpStream};
+    use std::sync::Arc;
+    use std::thread;
+    use {ChallengeStore, MemoryStore};
+
+    /// Sends a request and returns the status, the headers and the body of the response.
+    fn request(addr: SocketAddr, method: &str, path: &str, body: &str) -> (u16, String, Vec<u8>) {
+        let mut s = TcpStream::connect(addr).unwrap();
+        write!(
+            s,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut r = vec![];
+        s.read_to_end(&mut r).unwrap();
+        let end = r.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let head = String::from_utf8(r[..end].to_vec()).unwrap();
+        let status = head[9..12].parse().unwrap();
+        (status, head, r[end + 4..].to_vec())
+    }
+
+    fn json(body: &[u8]) -> Value {
+        serde_json::from_slice(body).unwrap()
+    }
+
+    #[test]
+    fn challenge_and_verify() {
+        let store = Arc::new(MemoryStore::new());
+        let server = Arc::new(
+            ChallengeServer::bind("127.0.0.1:0")
+                .unwrap()
+                .store(store.clone()),
+        );
+        let addr = server.local_addr().unwrap();
+        let s = server.clone();
+        let t = thread::spawn(move || s.run());
+
+        let (status, _, body) = request(addr, "GET", "/challenge?difficulty=easy", "");
+        assert_eq!(status, 200);
+        let v = json(&body);
+        assert_eq!(v["id"].as_str().unwrap().len(), 32);
+        assert!(v["image"]
+            .as_str()
+            .unwrap()
+            .starts_with("data:image/png;base64,"));
+        assert_eq!(store.len(), 1);
+
+        let (status, head, body) = request(addr, "GET", "/challenge?format=png", "");
+        assert_eq!(status, 200);
+        assert!(head.contains("image/png"));
+        assert_eq!(&body[1..4], b"PNG");
+
+        store.insert("known", "abc");
+        let verify = |answer: &str| {
+            let body = format!("{{\"id\": \"known\", \"answer\": \"{}\"}}", answer);
+            let (status, _, r) = request(addr, "POST", "/verify", &body);
+            assert_eq!(status, 200);
+            json(&r)
+        };
+        let v = verify("abd");
+        assert_eq!(v["result"], "wrong");
+        assert_eq!(v["attempts_left"], 2);
+        assert_eq!(verify("abc")["result"], "solved");
+        assert_eq!(verify("abc")["result"], "unknown");
+
+        if cfg!(feature = "audio") {
+            let (status, _, body) = request(addr, "GET", "/challenge?audio=1", "");
+            assert_eq!(status, 200);
+            let audio = json(&body)["audio"].as_array().unwrap().clone();
+            assert!(audio.len() >= 4);
+            assert!(audio[0]
+                .as_str()
+                .unwrap()
+                .starts_with("data:audio/wav;base64,"));
+        } else {
+            assert_eq!(request(addr, "GET", "/challenge?audio=1", "").0, 400);
+        }
+
+        assert_eq!(request(addr, "GET", "/nothing", "").0, 404);
+        assert_eq!(request(addr, "GET", "/verify", "").0, 405);
+        assert_eq!(request(addr, "POST", "/verify", "{").0, 400);
+        let large = format!("{{\"id\": \"{}\", \"answer\": \"\"}}", "x".repeat(5000));
+        assert_eq!(request(addr, "POST", "/verify", &large).0, 413);
+        assert_eq!(request(addr, "GET", "/challenge?difficulty=x", "").0, 400);
+
+        server.shutdown();
+        t.join().unwrap();
+    }
+}
@@ -0,0 +1,400 @@
+//! Server side storage of the answers to CAPTCHAs.
+//!
+//! # Examples
+//!
+//! ```
+//! # extern crate captcha;
+//! use captcha::{generate, ChallengeStore, Difficulty, MemoryStore, Verdict};
+//!
+//! # fn main() {
+//! let store = MemoryStore::new();
+//! let c = generate(Difficulty::Easy);
+//! let (id, png) = c.register(&store).expect("could not create CAPTCHA");
+//! // ... send id and png to the client ...
+//!
+//! assert_eq!(store.consume(&id, &c.chars_as_string()), Verdict::Solved);
+//! // a challenge can be solved only once
+//! assert_eq!(store.consume(&id, &c.chars_as_string()), Verdict::Unknown);
+//! # }
+//! ```
+
+use rand::{rng, RngCore};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use verify::Verifier;
+
+/// The result of checking the input of a user against a stored challenge.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// The input is correct. The challenge has been removed from the store.
+    Solved,
+    /// The input is wrong. If no attempts are left, the challenge has been removed from the
+    /// store.
+    Wrong { attempts_left: u32 },
+    /// The challenge does not exist. It has either never been inserted, has already been
+    /// consumed, has expired or has been evicted.
+    Unknown,
+}
+
+/// A storage for the answers to CAPTCHAs which have been sent to clients.
+///
+/// Each challenge can be solved only once. Implementations must remove a challenge as soon as
+/// it has been solved, so that the same image cannot be replayed.
+pub trait ChallengeStore {
+    /// Stores the answer of the challenge with the given id.
+    ///
+    /// Returns false if the store already contains a challenge with this id. In this case the
+    /// answer is not stored.
+    fn insert(&self, id: &str, answer: &str) -> bool;
+
+    /// Checks `input` against the answer of the challenge with the given id.
+    fn consume(&self, id: &str, input: &str) -> Verdict;
+}
+
+/// A store which can be shared between threads.
+pub type SharedStore = Arc<dyn ChallengeStore + Send + Sync>;
+
+/// Returns a new random id for a challenge.
+pub fn challenge_id() -> String {
+    let mut b = [0u8; 16];
+    rng().fill_bytes(&mut b);
+    b.iter().map(|x| format!("{:02x}", x)).collect()
+}
+
+/// Stores `answer` in `store` under a new random id and returns the id.
+pub(crate) fn insert_new<S: ChallengeStore + ?Sized>(store: &S, answer: &str) -> String {
+    loop {
+        let id = challenge_id();
+        if store.insert(&id, answer) {
+            return id;
+        }
+    }
+}
+
+/// The longest time to live of a challenge in a [`MemoryStore`](struct.MemoryStore.html).
+const MAX_TTL: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+struct Entry {
+    answer: String,
+    expires: Instant,
+    attempts_left: u32,
+    seq: u64,
+}
+
+/// The challenges of a [`MemoryStore`](struct.MemoryStore.html).
+///
+/// All challenges have the same time to live, so the order in which they have been inserted is
+/// the order in which they expire. `queue` keeps this order. Challenges which have been
+/// consumed are only removed from `map`; their items in `queue` are skipped later.
+#[derive(Default)]
+struct Entries {
+    map: HashMap<String, Entry>,
+    queue: VecDeque<(u64, String)>,
+    seq: u64,
+}
+
+impl Entries {
+    /// Returns the challenge which expires next.
+    fn first(&mut self) -> Option<&Entry> {
+        while let Some(&(seq, ref id)) = self.queue.front() {
+            if self.map.get(id).is_some_and(|e| e.seq == seq) {
+                break;
+            }
+            self.queue.pop_front();
+        }
+        let map = &self.map;
+        self.queue.front().and_then(|(_, id)| map.get(id))
+    }
+
+    /// Removes the challenge which expires next.
+    fn remove_first(&mut self) {
+        if self.first().is_some() {
+            if let Some((_, id)) = self.queue.pop_front() {
+                self.map.remove(&id);
+            }
+        }
+    }
+
+    /// Removes all challenges which have expired at `now`.
+    fn remove_expired(&mut self, now: Instant) {
+        while self.first().is_some_and(|e| e.expires <= now) {
+            self.remove_first();
+        }
+    }
+
+    fn insert(&mut self, id: &str, e: Entry) {
+        self.queue.push_back((e.seq, id.to_string()));
+        self.map.insert(id.to_string(), e);
+        // drops the items of consumed challenges, so that the queue does not grow if all
+        // challenges are solved before they expire
+        if self.queue.len() > 2 * self.map.len() + 16 {
+            let map = &self.map;
+            self.queue
+                .retain(|&(seq, ref id)| map.get(id).is_some_and(|e| e.seq == seq));
+        }
+    }
+}
+
+/// A [`ChallengeStore`](trait.ChallengeStore.html) which keeps the challenges in memory.
+///
+/// Challenges expire after a time to live. If the store is full, expired challenges are
+/// removed first and then the challenge which expires next is evicted. A challenge which is
+/// inserted with the id of a challenge in the store is rejected, so that its attempts are not
+/// reset.
+pub struct MemoryStore {
+    entries: Mutex<Entries>,
+    ttl: Duration,
+    capacity: usize,
+    max_attempts: u32,
+    verifier: Verifier,
+    clock: Arc<dyn Fn() -> Instant + Send + Sync>,
+}
+
+impl MemoryStore {
+    /// Returns a store with a time to live of 5 minutes, a capacity of 100000 challenges and
+    /// 3 attempts per challenge.
+    pub fn new() -> MemoryStore {
+        MemoryStore {
+            entries: Mutex::new(Entries::default()),
+            ttl: Duration::from_secs(300),
+            capacity: 100_000,
+            max_attempts: 3,
+            verifier: Verifier::new(),
+            clock: Arc::new(Instant::now),
+        }
+    }
+
+    /// Sets the duration after which a challenge expires. Durations longer than one year are
+    /// reduced to one year.
+    pub fn ttl(self, ttl: Duration) -> Self {
+        MemoryStore {
+            ttl: ::std::cmp::min(ttl, MAX_TTL),
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of challenges in the store.
+    pub fn capacity(self, capacity: usize) -> Self {
+        MemoryStore {
+            capacity: ::std::cmp::max(capacity, 1),
+            ..self
+        }
+    }
+
+    /// Sets the number of wrong answers after which a challenge is removed.
+    pub fn max_attempts(self, max_attempts: u32) -> Self {
+        MemoryStore {
+            max_attempts: ::std::cmp::max(max_attempts, 1),
+            ..self
+        }
+    }
+
+    /// Sets the verifier which is used to compare the input with the answer.
+    pub fn verifier(self, verifier: Verifier) -> Self {
+        MemoryStore { verifier, ..self }
+    }
+
+    /// Sets the function which returns the current time.
+    #[cfg(test)]
+    fn clock<F: Fn() -> Instant + Send + Sync + 'static>(self, f: F) -> Self {
+        MemoryStore {
+            clock: Arc::new(f),
+            ..self
+        }
+    }
+
+    /// Returns the number of challenges in the store including expired ones which have not been
+    /// removed yet.
+    pub fn len(&self) -> usize {
+        self.entries.lock().expect("lock poisoned").map.len()
+    }
+
+    /// Returns true if the store contains no challenges.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all expired challenges.
+    pub fn purge(&self) {
+        let now = (self.clock)();
+        self.entries
+            .lock()
+            .expect("lock poisoned")
+            .remove_expired(now);
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        MemoryStore::new()
+    }
+}
+
+impl ChallengeStore for MemoryStore {
+    fn insert(&self, id: &str, answer: &str) -> bool {
+        let now = (self.clock)();
+        let mut m = self.entries.lock().expect("lock poisoned");
+        m.remove_expired(now);
+        if m.map.contains_key(id) {
+            return false;
+        }
+        if m.map.len() >= self.capacity {
+            m.remove_first();
+        }
+        m.seq += 1;
+        let e = Entry {
+            answer: answer.to_string(),
+            expires: now + self.ttl,
+            attempts_left: self.max_attempts,
+            seq: m.seq,
+        };
+        m.insert(id, e);
+        true
+    }
+
+    fn consume(&self, id: &str, input: &str) -> Verdict {
+        let now = (self.clock)();
+        let mut m = self.entries.lock().expect("lock poisoned");
+        let verdict = match m.map.get_mut(id) {
+            None => return Verdict::Unknown,
+            Some(ref e) if e.expires <= now => Verdict::Unknown,
+            Some(ref e) if self.verifier.verify(&e.answer, input) => Verdict::Solved,
+            Some(e) => {
+                e.attempts_left -= 1;
+                Verdict::Wrong {
+                    attempts_left: e.attempts_left,
+                }
+            }
+        };
+        match verdict {
+            Verdict::Wrong { attempts_left } if attempts_left > 0 => {}
+            _ => {
+                m.map.remove(id);
+            }
+        }
+        verdict
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use store::{challenge_id, ChallengeStore, MemoryStore, Verdict};
+
+    #[test]
+    fn one_time_use() {
+        let s = MemoryStore::new();
+        s.insert("a", "xyz");
+        assert_eq!(s.consume("b", "xyz"), Verdict::Unknown);
+        assert_eq!(s.consume("a", "xyz"), Verdict::Solved);
+        assert_eq!(s.consume("a", "xyz"), Verdict::Unknown);
+        assert!(s.is_empty());
+    }
+
+    #[test]
+    fn attempts() {
+        let s = MemoryStore::new().max_attempts(2);
+        s.insert("a", "xyz");
+        assert_eq!(s.consume("a", "xy"), Verdict::Wrong { attempts_left: 1 });
+        assert_eq!(s.consume("a", "xyz"), Verdict::Solved);
+
+        s.insert("a", "xyz");
+        assert_eq!(s.consume("a", "1"), Verdict::Wrong { attempts_left: 1 });
+        assert_eq!(s.consume("a", "2"), Verdict::Wrong { attempts_left: 0 });
+        assert_eq!(s.consume("a", "xyz"), Verdict::Unknown);
+    }
+
+    /// Returns a store whose clock only advances by calling the returned function.
+    fn store_with_clock() -> (MemoryStore, impl Fn(u64)) {
+        let t = Arc::new(Mutex::new(Instant::now()));
+        let c = t.clone();
+        let s = MemoryStore::new().clock(move || *c.lock().unwrap());
+        (s, move |ms| *t.lock().unwrap() += Duration::from_millis(ms))
+    }
+
+    #[test]
+    fn expiry() {
+        let (s, advance) = store_with_clock();
+        let s = s.ttl(Duration::from_millis(20));
+        s.insert("a", "xyz");
+        advance(10);
+        s.insert("b", "xyz");
+        advance(10);
+        assert_eq!(s.consume("a", "xyz"), Verdict::Unknown);
+        assert_eq!(s.len(), 1);
+        s.purge();
+        assert_eq!(s.len(), 1);
+        advance(10);
+        s.purge();
+        assert!(s.is_empty());
+    }
+
+    #[test]
+    fn capacity() {
+        let (s, advance) = store_with_clock();
+        let s = s.capacity(2).ttl(Duration::from_millis(20));
+        s.insert("a", "1");
+        s.insert("b", "2");
+        s.insert("c", "3");
+        assert_eq!(s.len(), 2);
+        assert_eq!(s.consume("a", "1"), Verdict::Unknown);
+        assert_eq!(s.consume("c", "3"), Verdict::Solved);
+
+        // consumed challenges are not evicted a second time
+        s.insert("d", "4");
+        s.insert("e", "5");
+        assert_eq!(s.consume("b", "2"), Verdict::Unknown);
+        assert_eq!(s.consume("d", "4"), Verdict::Solved);
+
+        advance(20);
+        s.insert("f", "6");
+        assert_eq!(s.len(), 1);
+        assert_eq!(s.consume("f", "6"), Verdict::Solved);
+    }
+
+    #[test]
+    fn duplicate_ids() {
+        let s = MemoryStore::new().max_attempts(2);
+        s.insert("a", "xyz");
+        assert_eq!(s.consume("a", "1"), Verdict::Wrong { attempts_left: 1 });
+        assert!(!s.insert("a", "abc"));
+        assert_eq!(s.len(), 1);
+        assert_eq!(s.consume("a", "abc"), Verdict::Wrong { attempts_left: 0 });
+        assert_eq!(s.consume("a", "xyz"), Verdict::Unknown);
+    }
+
+    #[test]
+    fn long_ttl() {
+        let s = MemoryStore::new().ttl(Duration::from_secs(u64::MAX));
+        assert!(s.insert("a", "xyz"));
+        assert_eq!(s.consume("a", "xyz"), Verdict::Solved);
+    }
+
+    #[test]
+    fn insert_new() {
+        let s = MemoryStore::new();
+        let id = super::insert_new(&s, "xyz");
+        assert_eq!(s.consume(&id, "xyz"), Verdict::Solved);
+    }
+
+    #[test]
+    fn queue_of_consumed_challenges() {
+        let s = MemoryStore::new();
+        for i in 0..1000 {
+            let id = i.to_string();
+            s.insert(&id, "xyz");
+            assert_eq!(s.consume(&id, "xyz"), Verdict::Solved);
+        }
+        assert!(s.entries.lock().unwrap().queue.len() < 20);
+    }
+
+    #[test]
+    fn ids() {
+        let a = challenge_id();
+        assert_eq!(a.len(), 32);
+        assert_ne!(a, challenge_id());
+    }
+}
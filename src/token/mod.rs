@@ -0,0 +1,258 @@
+//! Stateless challenge tokens.
+//!
+//! A token binds the answer of a CAPTCHA to a random nonce and to the time at which the token
+//! was issued. The token is signed with HMAC-SHA256 and can be sent to the client together with
+//! the image. When the client submits its answer together with the token, any server which knows
+//! the secret key can check the answer without keeping any state.
+//!
+//! The answer is not contained in plain text but only as a keyed hash, so a client cannot
+//! recover the answer from the token. Note that a token can be submitted more than once until it
+//! expires. If replays have to be prevented, use a [`ChallengeStore`](../trait.ChallengeStore.html)
+//! instead or remember the tokens which have already been used.
+//!
+//! This module requires the feature `token`.
+//!
+//! # Examples
+//!
+//! ```
+//! # extern crate captcha;
+//! use captcha::token::TokenIssuer;
+//! use captcha::{generate, Difficulty};
+//!
+//! # fn main() {
+//! let issuer = TokenIssuer::new(b"a secret key of the server");
+//!
+//! let c = generate(Difficulty::Easy);
+//! let token = issuer.issue(&c.chars_as_string());
+//! let image = c.as_base64();
+//! // ... send token and image to the client ...
+//!
+//! let input = c.chars_as_string();
+//! assert!(issuer.verify(&token, &input).is_ok());
+//! # }
+//! ```
+
+use base64::{decode_config, encode_config, URL_SAFE_NO_PAD};
+use hmac::{Hmac, Mac};
+use rand::{rng, RngCore};
+use sha2::Sha256;
+use std::error;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use verify::{constant_time_eq, Verifier};
+
+type HmacSha256 = Hmac<Sha256>;
+
+const VERSION: &str = "v1";
+const NONCE_LEN: usize = 16;
+const HASH_LEN: usize = 32;
+const PAYLOAD_LEN: usize = 8 + NONCE_LEN + HASH_LEN;
+
+/// The reason why a token was rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenError {
+    /// The token could not be parsed.
+    Malformed,
+    /// The signature of the token is invalid, i.e. the token was not issued with this key or it
+    /// has been modified.
+    BadSignature,
+    /// The token is older than the time to live of the issuer.
+    Expired,
+    /// The input does not match the answer.
+    WrongAnswer,
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            TokenError::Malformed => "malformed token",
+            TokenError::BadSignature => "invalid token signature",
+            TokenError::Expired => "token expired",
+            TokenError::WrongAnswer => "wrong answer",
+        };
+        f.write_str(s)
+    }
+}
+
+impl error::Error for TokenError {}
+
+/// Issues and verifies signed challenge tokens.
+#[derive(Clone)]
+pub struct TokenIssuer {
+    key: Vec<u8>,
+    ttl: Duration,
+    verifier: Verifier,
+}
+
+impl TokenIssuer {
+    /// Returns an issuer which signs tokens with the given secret key.
+    ///
+    /// The key should contain at least 32 random bytes. Tokens expire after 5 minutes by
+    /// default.
+    pub fn new(key: &[u8]) -> TokenIssuer {
+        TokenIssuer {
+            key: key.to_vec(),
+            ttl: Duration::from_secs(300),
+            verifier: Verifier::new(),
+        }
+    }
+
+    /// Sets the duration after which a token expires.
+    pub fn ttl(self, ttl: Duration) -> Self {
+        TokenIssuer { ttl, ..self }
+    }
+
+    /// Sets the verifier which normalises the answer and the input of the user.
+    ///
+    /// The same verifier has to be used for issuing and for verifying a token.
+    pub fn verifier(self, verifier: Verifier) -> Self {
+        TokenIssuer { verifier, ..self }
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        HmacSha256::new_from_slice(&self.key).expect("HMAC accepts keys of any size")
+    }
+
+    fn answer_hash(&self, nonce: &[u8], answer: &str) -> Vec<u8> {
+        let mut m = self.mac();
+        m.update(b"answer\0");
+        m.update(nonce);
+        m.update(self.verifier.normalize(answer).as_bytes());
+        m.finalize().into_bytes().to_vec()
+    }
+
+    fn signature(&self, payload: &[u8]) -> HmacSha256 {
+        let mut m = self.mac();
+        m.update(VERSION.as_bytes());
+        m.update(b".");
+        m.update(payload);
+        m
+    }
+
+    /// Returns a new token for the given answer.
+    pub fn issue(&self, answer: &str) -> String {
+        self.issue_at(answer, SystemTime::now())
+    }
+
+    /// Returns a new token for the given answer which has been issued at time `now`.
+    pub fn issue_at(&self, answer: &str, now: SystemTime) -> String {
+        let mut nonce = [0u8; NONCE_LEN];
+        rng().fill_bytes(&mut nonce);
+
+        let issued = now
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let mut payload = Vec::with_capacity(PAYLOAD_LEN);
+        payload.extend_from_slice(&issued.to_be_bytes());
+        payload.extend_from_slice(&nonce);
+        payload.extend_from_slice(&self.answer_hash(&nonce, answer));
+
+        let sig = self.signature(&payload).finalize().into_bytes();
+        format!(
+            "{}.{}.{}",
+            VERSION,
+            encode_config(&payload, URL_SAFE_NO_PAD),
+            encode_config(sig, URL_SAFE_NO_PAD)
+        )
+    }
+
+    /// Checks whether `input` is the answer for which the token was issued.
+    pub fn verify(&self, token: &str, input: &str) -> Result<(), TokenError> {
+        self.verify_at(token, input, SystemTime::now())
+    }
+
+    /// Checks at time `now` whether `input` is the answer for which the token was issued.
+    pub fn verify_at(&self, token: &str, input: &str, now: SystemTime) -> Result<(), TokenError> {
+        let parts: Vec<&str> = token.split('.').collect();
+        if parts.len() != 3 || parts[0] != VERSION {
+            return Err(TokenError::Malformed);
+        }
+        let payload =
+            decode_config(parts[1], URL_SAFE_NO_PAD).map_err(|_| TokenError::Malformed)?;
+        let sig = decode_config(parts[2], URL_SAFE_NO_PAD).map_err(|_| TokenError::Malformed)?;
+        if payload.len() != PAYLOAD_LEN {
+            return Err(TokenError::Malformed);
+        }
+        self.signature(&payload)
+            .verify_slice(&sig)
+            .map_err(|_| TokenError::BadSignature)?;
+
+        let mut t = [0u8; 8];
+        t.copy_from_slice(&payload[..8]);
+        let issued = UNIX_EPOCH + Duration::from_secs(u64::from_be_bytes(t));
+        match now.duration_since(issued) {
+            Ok(age) if age > self.ttl => return Err(TokenError::Expired),
+            _ => {}
+        }
+
+        let nonce = &payload[8..8 + NONCE_LEN];
+        let hash = &payload[8 + NONCE_LEN..];
+        if constant_time_eq(hash, &self.answer_hash(nonce, input)) {
+            Ok(())
+        } else {
+            Err(TokenError::WrongAnswer)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+    use token::{TokenError, TokenIssuer};
+    use verify::Verifier;
+
+    #[test]
+    fn roundtrip() {
+        let i = TokenIssuer::new(b"key");
+        let t = i.issue("aBc4");
+        assert_eq!(i.verify(&t, "aBc4"), Ok(()));
+        assert_eq!(i.verify(&t, " aBc4 "), Ok(()));
+        assert_eq!(i.verify(&t, "abc4"), Err(TokenError::WrongAnswer));
+        assert_ne!(t, i.issue("aBc4"));
+        assert!(!t.contains("aBc4"));
+    }
+
+    #[test]
+    fn verifier() {
+        let i = TokenIssuer::new(b"key").verifier(Verifier::new().case_insensitive(true));
+        let t = i.issue("aBc4");
+        assert_eq!(i.verify(&t, "ABC4"), Ok(()));
+    }
+
+    #[test]
+    fn expiry() {
+        let i = TokenIssuer::new(b"key").ttl(Duration::from_secs(60));
+        let now = SystemTime::now();
+        let t = i.issue_at("xyz", now);
+        assert_eq!(
+            i.verify_at(&t, "xyz", now + Duration::from_secs(59)),
+            Ok(())
+        );
+        assert_eq!(
+            i.verify_at(&t, "xyz", now + Duration::from_secs(61)),
+            Err(TokenError::Expired)
+        );
+    }
+
+    #[test]
+    fn tampering() {
+        let i = TokenIssuer::new(b"key");
+        let t = i.issue("xyz");
+        assert_eq!(
+            TokenIssuer::new(b"other key").verify(&t, "xyz"),
+            Err(TokenError::BadSignature)
+        );
+
+        let parts: Vec<&str> = t.split('.').collect();
+        let other = i.issue("abc");
+        let other_parts: Vec<&str> = other.split('.').collect();
+        let forged = format!("{}.{}.{}", parts[0], other_parts[1], parts[2]);
+        assert_eq!(i.verify(&forged, "abc"), Err(TokenError::BadSignature));
+
+        assert_eq!(i.verify("", "xyz"), Err(TokenError::Malformed));
+        assert_eq!(i.verify("v1.!!.??", "xyz"), Err(TokenError::Malformed));
+        assert_eq!(i.verify("v1.YWJj.YWJj", "xyz"), Err(TokenError::Malformed));
+    }
+}
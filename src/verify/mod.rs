@@ -0,0 +1,223 @@
+//! Verification of the answers to CAPTCHAs.
+//!
+//! # Examples
+//!
+//! ```
+//! # extern crate captcha;
+//! use captcha::{verify, Verifier};
+//!
+//! # fn main() {
+//! assert!(verify("aBc3", " aBc3 "));
+//!
+//! let v = Verifier::new().case_insensitive(true).confusable('0', 'O');
+//! assert!(v.verify("OBc3", "0bc3"));
+//! # }
+//! ```
+
+use fonts::Font;
+
+/// Characters which are easily confused with each other. The first character of each pair is
+/// mapped to the second one.
+pub const CONFUSABLES: &[(char, char)] = &[
+    ('0', 'O'),
+    ('o', 'O'),
+    ('1', 'l'),
+    ('I', 'l'),
+    ('|', 'l'),
+    ('5', 'S'),
+    ('2', 'Z'),
+    ('8', 'B'),
+];
+
+/// Compares the answer of a CAPTCHA with the input of a user.
+///
+/// Before both strings are compared they are normalised according to the settings of the
+/// verifier. The comparison itself takes the same time regardless of the position of the first
+/// character in which both strings differ.
+#[derive(Clone, Debug)]
+pub struct Verifier {
+    case_insensitive: bool,
+    strip_whitespace: bool,
+    confusables: Vec<(char, char)>,
+    charset: Option<Vec<char>>,
+}
+
+impl Verifier {
+    /// Returns a verifier which strips whitespace and compares case sensitive without folding
+    /// confusable characters.
+    pub fn new() -> Verifier {
+        Verifier {
+            case_insensitive: false,
+            strip_whitespace: true,
+            confusables: vec![],
+            charset: None,
+        }
+    }
+
+    /// Sets whether upper and lower case letters are treated as equal. The strings are then
+    /// converted to lower case before confusable characters are folded, and a pair of
+    /// confusable characters applies to both cases of its characters.
+    pub fn case_insensitive(self, case_insensitive: bool) -> Self {
+        Verifier {
+            case_insensitive,
+            ..self
+        }
+    }
+
+    /// Sets whether whitespace is removed before the strings are compared.
+    pub fn strip_whitespace(self, strip_whitespace: bool) -> Self {
+        Verifier {
+            strip_whitespace,
+            ..self
+        }
+    }
+
+    /// Treats the character `from` as if it were `to`.
+    pub fn confusable(mut self, from: char, to: char) -> Self {
+        self.confusables.push((from, to));
+        self
+    }
+
+    /// Adds all pairs of [`CONFUSABLES`](constant.CONFUSABLES.html).
+    pub fn default_confusables(mut self) -> Self {
+        self.confusables.extend_from_slice(CONFUSABLES);
+        self
+    }
+
+    /// Restricts the folding of confusable characters to the given character set.
+    ///
+    /// A pair `(from, to)` is only applied if `to` is contained in the character set and `from`
+    /// is not. Hence, two characters which can both appear in a CAPTCHA are never treated as
+    /// equal.
+    pub fn charset(self, chars: &[char]) -> Self {
+        Verifier {
+            charset: Some(chars.to_vec()),
+            ..self
+        }
+    }
+
+    /// Restricts the folding of confusable characters to the characters of the font `f` (see
+    /// [`charset`](#method.charset)).
+    pub fn font<F: Font + ?Sized>(self, f: &F) -> Self {
+        self.charset(&f.chars())
+    }
+
+    fn fold(&self, c: char) -> char {
+        let active = |&&(from, to): &&(char, char)| match self.charset {
+            None => true,
+            Some(ref s) => s.contains(&to) && !s.contains(&from),
+        };
+        let same = |&&(from, _): &&(char, char)| {
+            if self.case_insensitive {
+                from.to_lowercase().eq(c.to_lowercase())
+            } else {
+                from == c
+            }
+        };
+        match self.confusables.iter().filter(active).find(same) {
+            Some(&(_, to)) => to,
+            None => c,
+        }
+    }
+
+    /// Returns the normalised form of `s` which is used for the comparison.
+    pub fn normalize(&self, s: &str) -> String {
+        let mut r = String::new();
+        for c in s.chars() {
+            if self.strip_whitespace && c.is_whitespace() {
+                continue;
+            }
+            if self.case_insensitive {
+                for c in c.to_lowercase() {
+                    r.extend(self.fold(c).to_lowercase());
+                }
+            } else {
+                r.push(self.fold(c));
+            }
+        }
+        r
+    }
+
+    /// Returns true if the input of the user matches the expected answer.
+    pub fn verify(&self, expected: &str, input: &str) -> bool {
+        let a = self.normalize(expected);
+        let b = self.normalize(input);
+        constant_time_eq(a.as_bytes(), b.as_bytes())
+    }
+}
+
+impl Default for Verifier {
+    fn default() -> Self {
+        Verifier::new()
+    }
+}
+
+/// Compares both slices in a time which only depends on their lengths.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let n = ::std::cmp::max(a.len(), b.len());
+    let mut diff = (a.len() != b.len()) as u8;
+    for i in 0..n {
+        let x = a.get(i).cloned().unwrap_or(0);
+        let y = b.get(i).cloned().unwrap_or(0);
+        diff |= x ^ y;
+    }
+    diff == 0
+}
+
+/// Returns true if `input` matches `expected` using the settings of
+/// [`Verifier::new`](struct.Verifier.html#method.new).
+pub fn verify(expected: &str, input: &str) -> bool {
+    Verifier::new().verify(expected, input)
+}
+
+#[cfg(test)]
+mod tests {
+    use fonts::Default;
+    use verify::{constant_time_eq, verify, Verifier};
+
+    #[test]
+    fn defaults() {
+        assert!(verify("abc", "abc"));
+        assert!(verify("abc", " a b\tc\n"));
+        assert!(!verify("abc", "ABC"));
+        assert!(!verify("abc", "ab"));
+        assert!(!verify("abc", "abcd"));
+        assert!(!verify("abc", ""));
+        assert!(!Verifier::new()
+            .strip_whitespace(false)
+            .verify("abc", "a bc"));
+    }
+
+    #[test]
+    fn case_and_confusables() {
+        let v = Verifier::new().case_insensitive(true).default_confusables();
+        assert!(v.verify("AbC", "aBc"));
+        assert!(v.verify("Ol5", "0IS"));
+        assert!(!v.verify("Ol5", "0IX"));
+
+        let v = Verifier::new().case_insensitive(true).confusable('O', '0');
+        assert!(v.verify("0", "o"));
+        assert!(v.verify("o", "0"));
+        assert_eq!(v.normalize("O o 0"), "000");
+        assert!(!Verifier::new().confusable('O', '0').verify("0", "o"));
+    }
+
+    #[test]
+    fn charset() {
+        // '1' and 'l' and '5' and 'S' can appear in the default font, so they are not folded,
+        // but 'I' can not appear and is folded to 'l'.
+        let v = Verifier::new().default_confusables().font(&Default::new());
+        assert!(!v.verify("l", "1"));
+        assert!(!v.verify("S", "5"));
+        assert!(v.verify("l", "I"));
+        assert_eq!(v.normalize("I1 5"), "l15");
+    }
+
+    #[test]
+    fn constant_time() {
+        assert!(constant_time_eq(b"", b""));
+        assert!(constant_time_eq(b"ab", b"ab"));
+        assert!(!constant_time_eq(b"ab", b"ac"));
+        assert!(!constant_time_eq(b"ab", b"ab\0"));
+    }
+}